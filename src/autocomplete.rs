@@ -0,0 +1,156 @@
+use std::path::PathBuf;
+
+use base64::Engine;
+use hmac::{Mac, digest::KeyInit};
+use reqwest::{Method, Url};
+use sha2::Digest;
+
+use crate::config::Config;
+use crate::error::CallsyError;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Rule {
+    Template(String),
+    Command(String),
+    Hmac(Hmac),
+}
+
+// Signs the request with an HMAC of a string rendered from a template, as used by many webhook and API signing schemes.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hmac {
+    algorithm : Option<String>,
+    key_file : Option<PathBuf>,
+    key_command : Option<String>,
+    template : String,
+    encoding : Option<String>,
+    prefix : Option<String>,
+}
+
+pub struct Context<'a> {
+    pub method : &'a Method,
+    pub url : &'a Url,
+    pub body : &'a [u8],
+    pub date : &'a str,
+    pub config : &'a Config,
+    // Set when running with --offline, in which case tokens are only taken from the cache.
+    pub offline : bool,
+}
+
+type Builtin = fn(&Context) -> Result<String, String>;
+
+fn builtin_rule(header : &str) -> Option<Builtin> {
+    match header {
+        "content-length" => Some(content_length),
+        "date" => Some(date),
+        "host" => Some(host),
+        "idempotency-key" => Some(idempotency_key),
+        _ => None,
+    }
+}
+
+fn content_length(context : &Context) -> Result<String, String> {
+    Ok(format!("{}", context.body.len()))
+}
+
+fn date(context : &Context) -> Result<String, String> {
+    Ok(String::from(context.date))
+}
+
+fn host(context : &Context) -> Result<String, String> {
+    match (context.url.host_str(), context.url.port()) {
+        (Some(host), Some(port)) => Ok(format!("{}:{}", host, port)),
+        (Some(host), None) => Ok(String::from(host)),
+        (None, _) => Err(String::from("Cannot autocomplete value of host header as the URL has no host.")),
+    }
+}
+
+// Kept apart from the other built in rules, as fetching a token can fail for being offline.
+fn authorization(context : &Context) -> Result<String, CallsyError> {
+    match &context.config.authorization {
+        Some(authorization) => authorization.header_value(context.url, context.offline),
+        None => Err(CallsyError::InvalidRequest(String::from("Cannot autocomplete value of authorization header without an authorization section in the config file."))),
+    }
+}
+
+// A random (version 4) UUID, so that each request sent has a key of its own.
+fn idempotency_key(_ : &Context) -> Result<String, String> {
+    let mut bytes : [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex = hex::encode(bytes);
+    Ok(format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32]))
+}
+
+fn render_template(template : &str, context : &Context) -> Result<String, String> {
+    let mut rendered = String::from(template);
+
+    if rendered.contains("{host}") {
+        rendered = rendered.replace("{host}", &host(context)?);
+    }
+
+    Ok(
+        rendered
+        .replace("{method}", context.method.as_str())
+        .replace("{url}", context.url.as_str())
+        .replace("{path}", context.url.path())
+        .replace("{content_length}", &content_length(context)?)
+        .replace("{date}", &date(context)?)
+        .replace("{body_sha256}", &hex::encode(sha2::Sha256::digest(context.body)))
+    )
+}
+
+fn compute_mac<M : Mac + KeyInit>(key : &[u8], message : &[u8]) -> Result<Vec<u8>, String> {
+    let mut mac = match <M as KeyInit>::new_from_slice(key) {
+        Ok(mac) => mac,
+        Err(error) => return Err(format!("The HMAC key is invalid. {}", error)),
+    };
+    mac.update(message);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+fn sign(hmac : &Hmac, context : &Context) -> Result<String, String> {
+    let key = crate::config::read_secret(&hmac.key_file, &hmac.key_command, "key", "hmac")?;
+    let message = render_template(&hmac.template, context)?;
+
+    let signature = match hmac.algorithm.as_deref().unwrap_or("sha256") {
+        "sha1" => compute_mac::<hmac::Hmac<sha1::Sha1>>(key.as_bytes(), message.as_bytes())?,
+        "sha256" => compute_mac::<hmac::Hmac<sha2::Sha256>>(key.as_bytes(), message.as_bytes())?,
+        "sha512" => compute_mac::<hmac::Hmac<sha2::Sha512>>(key.as_bytes(), message.as_bytes())?,
+        algorithm => return Err(format!("The HMAC algorithm {} is not supported, use sha1, sha256 or sha512.", algorithm)),
+    };
+
+    let encoded = match hmac.encoding.as_deref().unwrap_or("base64") {
+        "base64" => base64::engine::general_purpose::STANDARD.encode(signature),
+        "hex" => hex::encode(signature),
+        encoding => return Err(format!("The HMAC encoding {} is not supported, use base64 or hex.", encoding)),
+    };
+
+    Ok(format!("{}{}", hmac.prefix.as_deref().unwrap_or_default(), encoded))
+}
+
+pub fn autocomplete(header : &str, context : &Context) -> Result<String, CallsyError> {
+    let name = header.to_lowercase();
+
+    // Rules from the config file take precedence, so that built in rules can be overridden.
+    let user_rule = context.config.autocomplete
+        .iter()
+        .find(|(rule_header, _)| rule_header.to_lowercase() == name)
+        .map(|(_, rule)| rule);
+
+    if user_rule.is_none() && name == "authorization" {
+        return authorization(context);
+    }
+
+    let value = match (user_rule, builtin_rule(&name)) {
+        (Some(Rule::Template(template)), _) => render_template(template, context),
+        (Some(Rule::Command(command)), _) => crate::command::run_command(command).map(|output| String::from(output.trim())),
+        (Some(Rule::Hmac(hmac)), _) => sign(hmac, context),
+        (None, Some(builtin)) => builtin(context),
+        (None, None) => Err(format!("Cannot autocomplete value of {} header. Try supplying a value directly, or adding a rule for it to the config file.", header)),
+    };
+
+    value.map_err(CallsyError::InvalidRequest)
+}
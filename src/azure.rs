@@ -0,0 +1,208 @@
+use std::path::PathBuf;
+
+use crate::error::CallsyError;
+use crate::tokens::CachedToken;
+
+// Tokens from Azure AD (Microsoft Entra ID), fetched with the client credentials flow for a service
+// with a secret, or the device code flow for a user signing in through the browser.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Azure {
+    tenant : String,
+    client_id : String,
+    scope : String,
+    client_secret_file : Option<PathBuf>,
+    client_secret_command : Option<String>,
+    flow : Option<Flow>,
+    // The sign in endpoint, for national clouds such as https://login.microsoftonline.us.
+    authority : Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+enum Flow {
+    ClientCredentials,
+    DeviceCode,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token : String,
+    expires_in : u64,
+    refresh_token : Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error : String,
+    error_description : Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DeviceCodeResponse {
+    device_code : String,
+    message : String,
+    expires_in : u64,
+    interval : Option<u64>,
+}
+
+impl Azure {
+    fn flow(&self) -> Flow {
+        match self.flow {
+            Some(flow) => flow,
+            None if self.client_secret_file.is_some() || self.client_secret_command.is_some() => Flow::ClientCredentials,
+            None => Flow::DeviceCode,
+        }
+    }
+
+    fn endpoint(&self, path : &str) -> String {
+        let authority = self.authority.as_deref().unwrap_or("https://login.microsoftonline.com");
+        format!("{}/{}/oauth2/v2.0/{}", authority.trim_end_matches('/'), self.tenant, path)
+    }
+
+    // A refresh token is only given to the device code flow when offline_access is asked for.
+    fn scope(&self) -> String {
+        match self.flow() {
+            Flow::DeviceCode if !self.scope.split_whitespace().any(|scope| scope == "offline_access") => format!("{} offline_access", self.scope),
+            _ => self.scope.clone(),
+        }
+    }
+
+    fn cache_key(&self) -> String {
+        format!("azure {} {} {} {} {:?}", self.endpoint("token"), self.tenant, self.client_id, self.scope, self.flow())
+    }
+
+    pub fn token(&self, offline : bool) -> Result<String, CallsyError> {
+        let key = self.cache_key();
+        let cached = crate::tokens::load(&key);
+
+        if let Some(cached) = cached.as_ref().filter(|cached| cached.is_fresh()) {
+            tracing::debug!("Using cached Azure AD token");
+            return Ok(cached.access_token.clone());
+        }
+        if offline {
+            return Err(CallsyError::Offline(String::from("Cannot fetch a token from Azure AD while offline, and there is no cached token which is still valid.")));
+        }
+
+        let refresh_token = cached.and_then(|cached| cached.refresh_token);
+        let token = tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(self.fetch(refresh_token))
+        }).map_err(CallsyError::InvalidRequest)?;
+
+        crate::masking::register(&token.access_token);
+        if let Some(refresh_token) = &token.refresh_token {
+            crate::masking::register(refresh_token);
+        }
+        crate::tokens::store(&key, &token);
+
+        Ok(token.access_token)
+    }
+
+    async fn fetch(&self, refresh_token : Option<String>) -> Result<CachedToken, String> {
+        let client = reqwest::Client::new();
+
+        // An expired token from the device code flow is renewed without signing in again, if it can be.
+        if let Some(refresh_token) = refresh_token {
+            let form = [
+                ("grant_type", "refresh_token"),
+                ("client_id", &self.client_id),
+                ("scope", &self.scope()),
+                ("refresh_token", &refresh_token),
+            ];
+            match request_token(&client, &self.endpoint("token"), &form).await? {
+                Ok(token) => return Ok(token),
+                Err(error) => tracing::info!(error = %error.error, "Failed to refresh Azure AD token, signing in again"),
+            }
+        }
+
+        match self.flow() {
+            Flow::ClientCredentials => {
+                let secret = crate::config::read_secret(&self.client_secret_file, &self.client_secret_command, "client_secret", "azure")?;
+                let form = [
+                    ("grant_type", "client_credentials"),
+                    ("client_id", &self.client_id),
+                    ("client_secret", &secret),
+                    ("scope", &self.scope()),
+                ];
+                match request_token(&client, &self.endpoint("token"), &form).await? {
+                    Ok(token) => Ok(token),
+                    Err(error) => Err(describe(&error)),
+                }
+            },
+            Flow::DeviceCode => self.sign_in(&client).await,
+        }
+    }
+
+    // Shows the user the code to enter in their browser, then waits for them to sign in.
+    async fn sign_in(&self, client : &reqwest::Client) -> Result<CachedToken, String> {
+        let response = client
+            .post(self.endpoint("devicecode"))
+            .form(&[("client_id", self.client_id.as_str()), ("scope", &self.scope())])
+            .send()
+            .await;
+
+        let device_code = match response {
+            Ok(response) if response.status().is_success() => match response.json::<DeviceCodeResponse>().await {
+                Ok(device_code) => device_code,
+                Err(error) => return Err(format!("Failed to read the device code from Azure AD, {}", error)),
+            },
+            Ok(response) => match response.json::<ErrorResponse>().await {
+                Ok(error) => return Err(describe(&error)),
+                Err(error) => return Err(format!("Failed to read the response from Azure AD, {}", error)),
+            },
+            Err(error) => return Err(format!("Error when requesting a device code from Azure AD, {}", error)),
+        };
+
+        eprintln!("{}", device_code.message);
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(device_code.expires_in);
+        let mut interval = device_code.interval.unwrap_or(5);
+        let form = [
+            ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+            ("client_id", &self.client_id),
+            ("device_code", &device_code.device_code),
+        ];
+
+        while std::time::Instant::now() < deadline {
+            tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+
+            match request_token(client, &self.endpoint("token"), &form).await? {
+                Ok(token) => return Ok(token),
+                Err(error) if error.error == "authorization_pending" => {},
+                Err(error) if error.error == "slow_down" => interval += 5,
+                Err(error) => return Err(describe(&error)),
+            }
+        }
+
+        Err(String::from("The Azure AD device code expired before signing in was completed."))
+    }
+}
+
+fn describe(error : &ErrorResponse) -> String {
+    match &error.error_description {
+        Some(description) => format!("Azure AD refused to issue a token, {}. {}", error.error, description),
+        None => format!("Azure AD refused to issue a token, {}.", error.error),
+    }
+}
+
+// Errors from the token endpoint are returned separately, as some of them only mean to try again later.
+async fn request_token(client : &reqwest::Client, url : &str, form : &[(&str, &str)]) -> Result<Result<CachedToken, ErrorResponse>, String> {
+    let response = match client.post(url).form(form).send().await {
+        Ok(response) => response,
+        Err(error) => return Err(format!("Error when requesting a token from Azure AD, {}", error)),
+    };
+
+    if response.status().is_success() {
+        match response.json::<TokenResponse>().await {
+            Ok(token) => Ok(Ok(CachedToken::new(token.access_token, token.expires_in, token.refresh_token))),
+            Err(error) => Err(format!("Failed to read the token from Azure AD, {}", error)),
+        }
+    }
+    else {
+        let status = response.status();
+        match response.json::<ErrorResponse>().await {
+            Ok(error) => Ok(Err(error)),
+            Err(_) => Err(format!("Azure AD responded with status {} when requesting a token.", status)),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::fs::File;
+use std::io::prelude::*;
+
+use crate::processing::OutputResponse;
+
+// Requests and the responses they received, recorded with --record so that they can be served
+// again with --replay without touching the network.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Cassette {
+    interactions : Vec<Interaction>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Interaction {
+    request : RecordedRequest,
+    response : OutputResponse,
+}
+
+// Requests are matched on their method, URL and body. Headers are recorded for reference only, as
+// many (such as date) change between runs.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RecordedRequest {
+    method : String,
+    url : String,
+    headers : Vec<(String, String)>,
+    body : String,
+}
+
+impl RecordedRequest {
+    pub fn new(method : &reqwest::Method, url : &reqwest::Url, headers : &[(String, String)], body : &[u8]) -> RecordedRequest {
+        RecordedRequest {
+            method : method.to_string(),
+            url : url.to_string(),
+            headers : headers.to_vec(),
+            body : String::from_utf8_lossy(body).into_owned(),
+        }
+    }
+
+    fn matches(&self, other : &RecordedRequest) -> bool {
+        self.method == other.method && self.url == other.url && self.body == other.body
+    }
+}
+
+impl Cassette {
+    pub fn record(&mut self, request : RecordedRequest, response : OutputResponse) {
+        self.interactions.push(Interaction {
+            request,
+            response,
+        });
+    }
+
+    pub fn replay(&self, request : &RecordedRequest) -> Option<&OutputResponse> {
+        self.interactions
+        .iter()
+        .find(|interaction| interaction.request.matches(request))
+        .map(|interaction| &interaction.response)
+    }
+}
+
+pub fn load_cassette(path : &std::path::PathBuf) -> Result<Cassette, String> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) => return Err(format!("Failed to open cassette file {:?}. {}", path, error)),
+    };
+
+    let mut content = String::new();
+    if let Err(error) = file.read_to_string(&mut content) {
+        return Err(format!("Failed to read cassette file {:?}. {}", path, error));
+    }
+
+    match serde_json::from_str(&content) {
+        Ok(cassette) => Ok(cassette),
+        Err(error) => Err(format!("Unable to parse cassette file {:?}. {}", path, error)),
+    }
+}
@@ -0,0 +1,84 @@
+use base64::Engine;
+use ciborium::value::Value as Cbor;
+use serde_json::{Map, Value};
+
+fn from_json(json : Value) -> Cbor {
+    match json {
+        Value::Null => Cbor::Null,
+        Value::Bool(boolean) => Cbor::Bool(boolean),
+        // Whole numbers are sent as integers, and anything else as a 64 bit float.
+        Value::Number(number) => match (number.as_u64(), number.as_i64()) {
+            (Some(unsigned), _) => Cbor::from(unsigned),
+            (None, Some(signed)) => Cbor::from(signed),
+            (None, None) => Cbor::Float(number.as_f64().unwrap_or_default()),
+        },
+        Value::String(string) => Cbor::Text(string),
+        Value::Array(array) => Cbor::Array(array.into_iter().map(from_json).collect()),
+        Value::Object(object) => Cbor::Map(object.into_iter().map(|(key, value)| (Cbor::Text(key), from_json(value))).collect()),
+    }
+}
+
+// CBOR can hold more than JSON, so byte strings are given in base64, tagged values as an object of their
+// tag and value, keys which aren't strings as their JSON, and integers too large for JSON as strings.
+fn to_json(cbor : Cbor) -> Value {
+    match cbor {
+        Cbor::Null => Value::Null,
+        Cbor::Bool(boolean) => Value::Bool(boolean),
+        Cbor::Integer(integer) => {
+            let integer = i128::from(integer);
+            match (u64::try_from(integer), i64::try_from(integer)) {
+                (Ok(unsigned), _) => Value::from(unsigned),
+                (Err(_), Ok(signed)) => Value::from(signed),
+                (Err(_), Err(_)) => Value::from(integer.to_string()),
+            }
+        },
+        Cbor::Float(float) => Value::from(float),
+        Cbor::Text(text) => Value::from(text),
+        Cbor::Bytes(bytes) => Value::from(base64::engine::general_purpose::STANDARD.encode(bytes)),
+        Cbor::Array(array) => Value::Array(array.into_iter().map(to_json).collect()),
+        Cbor::Map(entries) => {
+            let mut object = Map::new();
+            for (key, value) in entries {
+                let key = match to_json(key) {
+                    Value::String(key) => key,
+                    key => key.to_string(),
+                };
+                object.insert(key, to_json(value));
+            }
+            Value::Object(object)
+        },
+        Cbor::Tag(tag, value) => serde_json::json!({
+            "tag" : tag,
+            "value" : to_json(*value),
+        }),
+        // Values added to CBOR after the library was written have no JSON form.
+        _ => Value::Null,
+    }
+}
+
+// The body of a cbor request is written as JSON and sent as CBOR.
+pub fn encode_request(body : &str) -> Result<Vec<u8>, String> {
+    let json = match serde_json::from_str::<Value>(body) {
+        Ok(json) => json,
+        Err(error) => return Err(format!("The body of a cbor request must be JSON. {}", error)),
+    };
+
+    let mut encoded = Vec::new();
+    match ciborium::ser::into_writer(&from_json(json), &mut encoded) {
+        Ok(()) => Ok(encoded),
+        Err(error) => panic!("Internal error, could not serialize CBOR data for request. {}", error),
+    }
+}
+
+pub fn decode_response(body : &[u8]) -> Result<Value, String> {
+    let mut rest = body;
+    let cbor = match ciborium::de::from_reader::<Cbor, _>(&mut rest) {
+        Ok(cbor) => cbor,
+        Err(error) => return Err(format!("The response is not valid CBOR. {}", error)),
+    };
+    if !rest.is_empty() {
+        return Err(format!("The response has {} bytes after the end of its CBOR value.", rest.len()));
+    }
+
+    Ok(to_json(cbor))
+}
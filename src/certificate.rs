@@ -0,0 +1,157 @@
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use x509_parser::prelude::{FromDer, GeneralName, X509Certificate};
+
+// A summary of the certificate presented by the server, for HTTPS requests.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Certificate {
+    subject : String,
+    issuer : String,
+    subject_alternative_names : Vec<String>,
+    not_before : String,
+    not_after : String,
+    serial : String,
+}
+
+fn format_time(time : x509_parser::time::ASN1Time) -> String {
+    time.to_rfc2822().unwrap_or_else(|_| time.to_string())
+}
+
+fn format_ip_address(bytes : &[u8]) -> Option<String> {
+    match bytes.len() {
+        4 => Some(std::net::Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?).to_string()),
+        16 => Some(std::net::Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?).to_string()),
+        _ => None,
+    }
+}
+
+pub struct Expiry {
+    pub subject : String,
+    pub not_after : String,
+    // Negative once the certificate has expired.
+    pub days_remaining : i64,
+}
+
+pub fn expiry(der : &[u8]) -> Option<Expiry> {
+    let (_, certificate) = X509Certificate::from_der(der).ok()?;
+
+    let now = match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        Ok(now) => now.as_secs() as i64,
+        Err(_) => 0,
+    };
+    let not_after = certificate.validity().not_after;
+
+    Some(Expiry {
+        subject : certificate.subject().to_string(),
+        not_after : format_time(not_after),
+        days_remaining : (not_after.timestamp() - now).div_euclid(24 * 60 * 60),
+    })
+}
+
+// Pins are given as sha256// followed by the base64 encoded SHA-256 hash of either the whole certificate, or
+// its public key (as with curl's --pinnedpubkey).
+pub fn matches_pin(der : &[u8], pins : &[String]) -> Result<bool, String> {
+    let engine = base64::engine::general_purpose::STANDARD;
+
+    let mut hashes = vec![engine.encode(Sha256::digest(der))];
+    if let Ok((_, certificate)) = X509Certificate::from_der(der) {
+        hashes.push(engine.encode(Sha256::digest(certificate.public_key().raw)));
+    }
+
+    for pin in pins {
+        match pin.strip_prefix("sha256//") {
+            Some(hash) => {
+                if hashes.iter().any(|expected| expected == hash) {
+                    return Ok(true);
+                }
+            },
+            None => return Err(format!("The certificate pin {} should start with sha256//.", pin)),
+        }
+    }
+
+    Ok(false)
+}
+
+// Certificates which can't be parsed are left out of the output, rather than failing the request.
+pub fn describe(der : &[u8]) -> Option<Certificate> {
+    let (_, certificate) = X509Certificate::from_der(der).ok()?;
+
+    let subject_alternative_names = match certificate.subject_alternative_name() {
+        Ok(Some(extension)) => {
+            extension.value.general_names
+            .iter()
+            .filter_map(|name| match name {
+                GeneralName::DNSName(name) => Some(String::from(*name)),
+                GeneralName::IPAddress(bytes) => format_ip_address(bytes),
+                _ => None,
+            })
+            .collect()
+        },
+        _ => Vec::new(),
+    };
+
+    Some(Certificate {
+        subject : certificate.subject().to_string(),
+        issuer : certificate.issuer().to_string(),
+        subject_alternative_names,
+        not_before : format_time(certificate.validity().not_before),
+        not_after : format_time(certificate.validity().not_after),
+        serial : certificate.raw_serial_as_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A certificate for localhost, issued by a test CA.
+    const CERTIFICATE : &str = "\
+-----BEGIN CERTIFICATE-----
+MIIDHTCCAgWgAwIBAgIURhYr2hJRD84J5FqhPvSBHeYw3LIwDQYJKoZIhvcNAQEL
+BQAwEjEQMA4GA1UEAwwHVGVzdCBDQTAeFw0yNjEwMTYxNDEzNDdaFw0yNjEwMjYx
+NDEzNDdaMBQxEjAQBgNVBAMMCWxvY2FsaG9zdDCCASIwDQYJKoZIhvcNAQEBBQAD
+ggEPADCCAQoCggEBALwh5swOzsRJjzU2KGNcBhUCW93KEzQIHxpvCDbBne1yPWXo
+rML28j0nJLBZ1xLa/Skmgh4i8i7hu2HtAVMJYaVZGhK1r+bx7S7bw/uNwcFXvUZt
+uC9WN5ZijBUOQSRzHR4hqSPZqJAGXt9ftoMbElZvX9ZMPk2ahDwvjlBUmPyTA0h3
+fJwf2iv0W3QTPXba3tkBvntYsiMOc5ZAn4ILN/9SHX2ssWo6Cgig34vNgvTJwm07
+TZeZbI1Q6WodAMlrVIXSM4I4QWQzrVnP1WSBIabxN2LPKcmi0bjP2+aj7LS1TVNo
+AhaFaopeV81WzEx9xJLzCE0CiCmMzz/vqwKXx3kCAwEAAaNpMGcwGgYDVR0RBBMw
+EYIJbG9jYWxob3N0hwR/AAABMAkGA1UdEwQCMAAwHQYDVR0OBBYEFMIVwaj6n29Q
+foEio0rZu7d7CgyiMB8GA1UdIwQYMBaAFJ17KvMx6B8MzbIjtNyzGOCjVw5iMA0G
+CSqGSIb3DQEBCwUAA4IBAQARndBS867n1kgX1qU1WCaPPqjLPBPO5AL95OglXjIC
+9UzAO8ffvAc4QfBW6haw1RbihGD+nbPQ9d3Uxn8/ST6cnchznP/XSGe+BDrHgpNn
+v1BmmyXc3XzDibDKNeJhu79pkvKc2uGYTP0D772o3QxNCsbiWxZxZW9bdsazl7Bi
+lGAQ/sfFTbdYDbkh73duCjjbzbUhdKYO9lzMttsCNwf3IHGQyfm5nS1tIbmE4tGs
+uDhh1BXEuf60YzWWF9RvWciHjat1p1cjMdqY0+Hlb456I7VznyurHMWRaXekSYj2
+igDUh4c+UY8nt2RSj/09MuJxwK1ZYB1RZ7jQzqvTYn83
+-----END CERTIFICATE-----";
+
+    fn der() -> Vec<u8> {
+        rustls_pemfile::certs(&mut CERTIFICATE.as_bytes()).unwrap().remove(0)
+    }
+
+    #[test]
+    fn pin_matches_certificate_or_public_key() {
+        let certificate_pin = String::from("sha256//OjHfJbLJfrTF9KpKGLmkFeaVOBMf1ubEqkpUb0FsFMk=");
+        let public_key_pin = String::from("sha256//Vq/V+9bYHZ/PI+WPQG6AYNqyB7/ZJG1Z/BhJhY/4v4I=");
+        let other_pin = String::from("sha256//AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=");
+
+        assert_eq!(matches_pin(&der(), &[certificate_pin]), Ok(true));
+        assert_eq!(matches_pin(&der(), &[other_pin.clone(), public_key_pin]), Ok(true));
+        assert_eq!(matches_pin(&der(), &[other_pin]), Ok(false));
+        assert_eq!(matches_pin(&der(), &[]), Ok(false));
+    }
+
+    #[test]
+    fn pin_must_start_with_sha256() {
+        assert!(matches_pin(&der(), &[String::from("Vq/V+9bYHZ/PI+WPQG6AYNqyB7/ZJG1Z/BhJhY/4v4I=")]).is_err());
+        assert!(matches_pin(&der(), &[String::from("sha1//Vq/V+9bYHZ/PI+WPQG6AYNqyB7/ZJG1Z/BhJhY/4v4I=")]).is_err());
+    }
+
+    #[test]
+    fn pin_of_unparsable_certificate_matches_only_its_hash() {
+        let pin = format!("sha256//{}", base64::engine::general_purpose::STANDARD.encode(Sha256::digest(b"not a certificate")));
+
+        assert_eq!(matches_pin(b"not a certificate", &[pin]), Ok(true));
+    }
+}
@@ -0,0 +1,131 @@
+use std::fs::File;
+use std::io::prelude::*;
+
+use crate::processing::RawRequest;
+
+#[derive(Deserialize, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Collection {
+    requests : Vec<RawRequest>,
+}
+
+pub struct Filter<'a> {
+    pub tags : &'a [String],
+    pub names : &'a [String],
+}
+
+impl<'a> Filter<'a> {
+    fn matches(&self, raw_request : &RawRequest) -> bool {
+        let tag_matches =
+            self.tags.is_empty()
+            || self.tags.iter().any(|tag| raw_request.tags.contains(tag));
+
+        let name_matches =
+            self.names.is_empty()
+            || match &raw_request.name {
+                Some(name) => self.names.iter().any(|pattern| matches_pattern(pattern, name)),
+                None => false,
+            };
+
+        tag_matches && name_matches
+    }
+}
+
+// Matches a name against a pattern where * stands for any sequence of characters.
+pub(crate) fn matches_pattern(pattern : &str, name : &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            match name.strip_prefix(prefix) {
+                Some(remaining) => {
+                    (0..=remaining.len())
+                    .filter(|index| remaining.is_char_boundary(*index))
+                    .any(|index| matches_pattern(rest, &remaining[index..]))
+                },
+                None => false,
+            }
+        },
+    }
+}
+
+pub fn load_collection(path : &std::path::PathBuf, filter : &Filter) -> Result<Vec<RawRequest>, String> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) => return Err(format!("Failed to open collection file {:?}. {}", path, error)),
+    };
+
+    let mut content = String::new();
+    if let Err(error) = file.read_to_string(&mut content) {
+        return Err(format!("Failed to read collection file {:?}. {}", path, error));
+    }
+
+    let content = crate::include::resolve_collection(content, path)?;
+    let collection : Collection = match serde_json::from_str(&content) {
+        Ok(collection) => collection,
+        Err(error) => return Err(format!("Unable to deserialise data from collection file at line {}, column {}.", error.line(), error.column())),
+    };
+
+    let selected : Vec<bool> = collection.requests.iter().map(|raw_request| filter.matches(raw_request)).collect();
+
+    if !selected.contains(&true) {
+        return Err(String::from("No requests in the collection matched the provided filters."));
+    }
+
+    let order = order_requests(&collection.requests, &selected)?;
+
+    let mut requests : Vec<Option<RawRequest>> = collection.requests.into_iter().map(Some).collect();
+
+    Ok(
+        order
+        .into_iter()
+        .filter_map(|index| requests[index].take())
+        .collect()
+    )
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    NotVisited,
+    InProgress,
+    Done,
+}
+
+// Orders the selected requests so that each comes after the requests it depends on, pulling in
+// any dependencies which were not selected themselves. Otherwise the order of the file is kept.
+fn order_requests(requests : &[RawRequest], selected : &[bool]) -> Result<Vec<usize>, String> {
+
+    fn visit(index : usize, requests : &[RawRequest], visits : &mut Vec<Visit>, order : &mut Vec<usize>) -> Result<(), String> {
+        match visits[index] {
+            Visit::Done => return Ok(()),
+            Visit::InProgress => {
+                return Err(format!("The request {} is part of a dependency cycle.", requests[index].name.as_deref().unwrap_or("without a name")));
+            },
+            Visit::NotVisited => {},
+        }
+
+        visits[index] = Visit::InProgress;
+
+        for dependency in &requests[index].depends_on {
+            match requests.iter().position(|raw_request| raw_request.name.as_ref() == Some(dependency)) {
+                Some(dependency_index) => visit(dependency_index, requests, visits, order)?,
+                None => return Err(format!("The request {} depends on {}, which is not in the collection.", requests[index].name.as_deref().unwrap_or("without a name"), dependency)),
+            }
+        }
+
+        visits[index] = Visit::Done;
+        order.push(index);
+
+        Ok(())
+    }
+
+    let mut visits = vec![Visit::NotVisited; requests.len()];
+    let mut order = Vec::with_capacity(requests.len());
+
+    for (index, is_selected) in selected.iter().enumerate() {
+        if *is_selected {
+            visit(index, requests, &mut visits, &mut order)?;
+        }
+    }
+
+    Ok(order)
+}
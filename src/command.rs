@@ -0,0 +1,28 @@
+use std::process::Command;
+
+pub fn run_command(command : &str) -> Result<String, String> {
+    run_command_with_env(command, &[])
+}
+
+// Runs a command with some extra environment variables, such as details of what triggered it.
+pub fn run_command_with_env(command : &str, env : &[(&str, &str)]) -> Result<String, String> {
+    let output = if cfg!(target_os = "windows") {
+        Command::new("cmd").args(["/C", command]).envs(env.iter().copied()).output()
+    }
+    else {
+        Command::new("sh").args(["-c", command]).envs(env.iter().copied()).output()
+    };
+
+    match output {
+        Ok(output) if output.status.success() => {
+            match String::from_utf8(output.stdout) {
+                Ok(stdout) => Ok(stdout),
+                Err(_) => Err(format!("Output of command `{}` was not valid UTF-8.", command)),
+            }
+        },
+        Ok(output) => {
+            Err(format!("Command `{}` failed with {}. {}", command, output.status, String::from_utf8_lossy(&output.stderr).trim()))
+        },
+        Err(error) => Err(format!("Failed to run command `{}`. {}", command, error)),
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+
+use indexmap::IndexMap;
+
+// A value which isn't the same in the responses from every environment, with the value each one had.
+// Environments whose response has nothing at the path are left out.
+#[derive(Serialize)]
+pub struct Difference {
+    path : String,
+    values : IndexMap<String, serde_json::Value>,
+}
+
+// Flattens a JSON value into its leaves, keyed by the JSON pointer to each. Empty objects and arrays
+// are leaves themselves, so that they aren't lost.
+fn flatten(value : &serde_json::Value, pointer : String, leaves : &mut BTreeMap<String, serde_json::Value>) {
+    match value {
+        serde_json::Value::Object(object) if !object.is_empty() => {
+            for (key, value) in object {
+                flatten(value, format!("{}/{}", pointer, key.replace('~', "~0").replace('/', "~1")), leaves);
+            }
+        },
+        serde_json::Value::Array(array) if !array.is_empty() => {
+            for (index, value) in array.iter().enumerate() {
+                flatten(value, format!("{}/{}", pointer, index), leaves);
+            }
+        },
+        value => {
+            leaves.insert(pointer, value.clone());
+        },
+    }
+}
+
+fn is_ignored(path : &str, ignore : &[String]) -> bool {
+    ignore.iter().any(|ignored| path == ignored || path.starts_with(&format!("{}/", ignored)))
+}
+
+// Compares the responses from each environment value by value, returning every path at which they
+// don't all agree, other than those under one of the ignored paths.
+pub fn differences(responses : &[(String, serde_json::Value)], ignore : &[String]) -> Vec<Difference> {
+    let flattened = responses
+        .iter()
+        .map(|(environment, response)| {
+            let mut leaves = BTreeMap::new();
+            flatten(response, String::new(), &mut leaves);
+            (environment, leaves)
+        })
+        .collect::<Vec<_>>();
+
+    let mut paths = flattened.iter().flat_map(|(_, leaves)| leaves.keys()).collect::<Vec<_>>();
+    paths.sort();
+    paths.dedup();
+
+    let mut differences = Vec::new();
+    for path in paths {
+        if is_ignored(path, ignore) {
+            continue;
+        }
+
+        let values = flattened.iter().map(|(_, leaves)| leaves.get(path)).collect::<Vec<_>>();
+        if values.iter().all(|value| *value == values[0]) {
+            continue;
+        }
+
+        differences.push(Difference {
+            path : path.clone(),
+            values : flattened
+                .iter()
+                .filter_map(|(environment, leaves)| leaves.get(path).map(|value| ((*environment).clone(), value.clone())))
+                .collect(),
+        });
+    }
+
+    differences
+}
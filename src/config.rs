@@ -0,0 +1,299 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::error::CallsyError;
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub user_agent : Option<String>,
+    pub output_directory : Option<PathBuf>,
+    // A SQLite database which every request is recorded in, for searching with callsy history search.
+    pub history_database : Option<PathBuf>,
+    pub base_url : Option<String>,
+    // Base URLs which are tried in turn, moving on to the next when one can't be reached.
+    pub base_urls : Option<Vec<String>>,
+    pub env_file : Option<PathBuf>,
+    #[serde(default)]
+    pub defaults : Settings,
+    #[serde(default)]
+    pub headers : HashMap<String, String>,
+    pub authorization : Option<Authorization>,
+    #[serde(default)]
+    pub autocomplete : HashMap<String, crate::autocomplete::Rule>,
+    #[serde(default)]
+    pub profiles : HashMap<String, Profile>,
+    #[serde(default)]
+    pub vault : crate::vault::Vault,
+    // Set from the --offline option, to prevent any network access.
+    #[serde(skip)]
+    pub offline : bool,
+    // Set from the --trace-wire option, to log the bytes of each connection.
+    #[serde(skip)]
+    pub trace_wire : bool,
+    // Set from the --max-body-size option, in bytes.
+    #[serde(skip)]
+    pub max_body_size : Option<u64>,
+    // Set from the --speed-limit and --speed-time options.
+    #[serde(skip)]
+    pub speed_limit : Option<SpeedLimit>,
+    // Set from the --continue option, for the body output file being downloaded to.
+    #[serde(skip)]
+    pub download : Option<Download>,
+    // Set from the output file, which binary bodies are written beside rather than into.
+    #[serde(skip)]
+    pub sidecar_output : Option<PathBuf>,
+    // Set from the --fsync option.
+    #[serde(skip)]
+    pub fsync : bool,
+    // Set from the --strict option, to fail requests with fields callsy doesn't know.
+    #[serde(skip)]
+    pub strict : bool,
+    // Set from the --include-request option.
+    #[serde(skip)]
+    pub include_request : bool,
+}
+
+// A transfer which averages fewer bytes per second than the limit for the whole of the time is aborted.
+#[derive(Clone, Copy)]
+pub struct SpeedLimit {
+    pub bytes_per_second : u64,
+    pub time : std::time::Duration,
+}
+
+// A download which is streamed to the body output file as it arrives, so that an interrupted transfer
+// leaves the part downloaded so far to be continued from.
+pub struct Download {
+    pub path : PathBuf,
+    // The length of the partial file when the request was sent, which the response is expected to resume from.
+    pub offset : u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub base_url : Option<String>,
+    pub base_urls : Option<Vec<String>>,
+    pub env_file : Option<PathBuf>,
+    pub authorization : Option<Authorization>,
+    pub tls : Option<Tls>,
+}
+
+// Settings which can be given both as defaults in the config file, and in the request file itself.
+#[derive(Deserialize, Default, Clone, Debug, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    pub timeout : Option<f64>,
+    pub connect_timeout : Option<f64>,
+    pub proxy : Option<String>,
+    pub tls : Option<Tls>,
+    pub pool_max_idle_per_host : Option<usize>,
+    pub pool_idle_timeout : Option<f64>,
+    pub tcp_keepalive : Option<f64>,
+    pub dns_over_https : Option<String>,
+    pub dns_over_https_format : Option<crate::dns::Format>,
+    pub local_address : Option<std::net::IpAddr>,
+    pub connect_to : Option<std::collections::BTreeMap<String, String>>,
+    pub title_case_headers : Option<bool>,
+    pub trace_context : Option<bool>,
+    pub otlp_endpoint : Option<String>,
+    // Statuses which fail over to the next of the base_urls, as well as connection failures.
+    pub failover_statuses : Option<Vec<u16>>,
+}
+
+#[derive(Deserialize, Default, Clone, Debug, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Tls {
+    pub accept_invalid_certificates : Option<bool>,
+    pub ca_certificate : Option<PathBuf>,
+    pub pins : Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Authorization {
+    pub token_file : Option<PathBuf>,
+    pub token_command : Option<String>,
+    pub jwt : Option<crate::jwt::Jwt>,
+    pub azure : Option<crate::azure::Azure>,
+    pub google : Option<crate::google::Google>,
+    pub github_app : Option<crate::github::GithubApp>,
+    pub kerberos : Option<crate::kerberos::Kerberos>,
+    pub scheme : Option<String>,
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(config_home) if !config_home.is_empty() => Some(PathBuf::from(config_home)),
+        _ => dirs::home_dir().map(|home| home.join(".config")),
+    }.map(|config_home| config_home.join("callsy").join("config.toml"))
+}
+
+pub fn load_config(maybe_path : &Option<PathBuf>, maybe_profile : &Option<String>) -> Result<Config, String> {
+    let mut config = read_config(maybe_path)?;
+
+    if let Some(profile) = maybe_profile {
+        config.apply_profile(profile)?;
+    }
+
+    if config.base_url.is_some() && config.base_urls.is_some() {
+        return Err(String::from("Cannot provide both a base_url and base_urls in the config file."));
+    }
+
+    Ok(config)
+}
+
+fn read_config(maybe_path : &Option<PathBuf>) -> Result<Config, String> {
+    let path = match maybe_path {
+        Some(path) => path.clone(),
+        None => match default_config_path() {
+            Some(path) if path.exists() => path,
+            // No config file is not an error unless one was explicitly requested.
+            _ => return Ok(Config::default()),
+        },
+    };
+
+    let mut file = match File::open(&path) {
+        Ok(file) => file,
+        Err(error) => return Err(format!("Failed to open config file {:?}. {}", path, error)),
+    };
+
+    let mut content = String::new();
+    if let Err(error) = file.read_to_string(&mut content) {
+        return Err(format!("Failed to read config file {:?}. {}", path, error));
+    }
+
+    match toml::from_str(&content) {
+        Ok(config) => {
+            tracing::debug!(?path, "Loaded config file");
+            Ok(config)
+        },
+        Err(error) => Err(format!("Unable to parse config file {:?}. {}", path, error)),
+    }
+}
+
+// Reads a secret such as a token or key from either a file or the output of a command, where the fields
+// of the config section are named like token_file and token_command.
+pub fn read_secret(file : &Option<PathBuf>, command : &Option<String>, field : &str, section : &str) -> Result<String, String> {
+    let secret = match (file, command) {
+        (Some(_), Some(_)) => {
+            return Err(format!("Cannot provide both a {0}_file and {0}_command in the {1} config.", field, section));
+        },
+        (Some(path), None) => {
+            let mut file = match File::open(path) {
+                Ok(file) => file,
+                Err(error) => return Err(format!("Failed to open the {} file. {}", field, error)),
+            };
+
+            let mut secret = String::new();
+            match file.read_to_string(&mut secret) {
+                Ok(_) => secret,
+                Err(error) => return Err(format!("Failed to read {} file. {}", field, error)),
+            }
+        },
+        (None, Some(command)) => {
+            crate::command::run_command(command)?
+        },
+        (None, None) => {
+            return Err(format!("The {0} config must include either a {1}_file or {1}_command.", section, field));
+        },
+    };
+
+    let secret = String::from(secret.trim());
+    crate::masking::register(&secret);
+    Ok(secret)
+}
+
+impl Authorization {
+    // Tokens from identity providers are only taken from the cache when offline.
+    pub fn header_value(&self, url : &reqwest::Url, offline : bool) -> Result<String, CallsyError> {
+        let stored = self.token_file.is_some() || self.token_command.is_some();
+        let sources = [stored, self.jwt.is_some(), self.azure.is_some(), self.google.is_some(), self.github_app.is_some(), self.kerberos.is_some()];
+        if sources.iter().filter(|source| **source).count() > 1 {
+            return Err(CallsyError::InvalidRequest(String::from("Cannot provide more than one of a token_file or token_command, jwt, azure, google, github_app and kerberos in the authorization config.")));
+        }
+
+        let token = match (&self.jwt, &self.azure, &self.google, &self.github_app, &self.kerberos) {
+            (Some(jwt), _, _, _, _) => jwt.token().map_err(CallsyError::InvalidRequest)?,
+            (_, Some(azure), _, _, _) => azure.token(offline)?,
+            (_, _, Some(google), _, _) => google.token(offline)?,
+            (_, _, _, Some(github_app), _) => github_app.token(offline)?,
+            (_, _, _, _, Some(kerberos)) => kerberos.token(url).map_err(CallsyError::InvalidRequest)?,
+            (None, None, None, None, None) => read_secret(&self.token_file, &self.token_command, "token", "authorization").map_err(CallsyError::InvalidRequest)?,
+        };
+
+        // Kerberos tokens are sent with the Negotiate scheme, as SPNEGO defines.
+        match self.scheme.as_deref() {
+            Some("") => Ok(token),
+            Some(scheme) => Ok(format!("{} {}", scheme, token)),
+            None if self.kerberos.is_some() => Ok(format!("Negotiate {}", token)),
+            None => Ok(format!("Bearer {}", token)),
+        }
+    }
+}
+
+impl Config {
+    // Settings from the profile replace those at the top level of the config file.
+    fn apply_profile(&mut self, name : &str) -> Result<(), String> {
+        let profile = match self.profiles.get(name) {
+            Some(profile) => profile,
+            None => return Err(format!("The profile {} is not defined in the config file.", name)),
+        };
+
+        // A profile's base URL or URLs replace both of those at the top level.
+        if profile.base_url.is_some() || profile.base_urls.is_some() {
+            self.base_url = profile.base_url.clone();
+            self.base_urls = profile.base_urls.clone();
+        }
+
+        if let Some(env_file) = &profile.env_file {
+            self.env_file = Some(env_file.clone());
+        }
+
+        if let Some(authorization) = &profile.authorization {
+            self.authorization = Some(authorization.clone());
+        }
+
+        if let Some(tls) = &profile.tls {
+            let defaults = Settings { tls : self.defaults.tls.clone(), ..Settings::default() };
+            let profile = Settings { tls : Some(tls.clone()), ..Settings::default() };
+            self.defaults.tls = defaults.merged_under(&profile).tls;
+        }
+
+        Ok(())
+    }
+}
+
+impl Settings {
+    // Settings from the request take precedence over those from the config file.
+    pub fn merged_under(&self, request : &Settings) -> Settings {
+        let tls = match (&self.tls, &request.tls) {
+            (Some(defaults), Some(request)) => Some(Tls {
+                accept_invalid_certificates : request.accept_invalid_certificates.or(defaults.accept_invalid_certificates),
+                ca_certificate : request.ca_certificate.clone().or_else(|| defaults.ca_certificate.clone()),
+                pins : request.pins.clone().or_else(|| defaults.pins.clone()),
+            }),
+            (defaults, request) => request.clone().or_else(|| defaults.clone()),
+        };
+
+        Settings {
+            timeout : request.timeout.or(self.timeout),
+            connect_timeout : request.connect_timeout.or(self.connect_timeout),
+            proxy : request.proxy.clone().or_else(|| self.proxy.clone()),
+            tls,
+            pool_max_idle_per_host : request.pool_max_idle_per_host.or(self.pool_max_idle_per_host),
+            pool_idle_timeout : request.pool_idle_timeout.or(self.pool_idle_timeout),
+            tcp_keepalive : request.tcp_keepalive.or(self.tcp_keepalive),
+            dns_over_https : request.dns_over_https.clone().or_else(|| self.dns_over_https.clone()),
+            dns_over_https_format : request.dns_over_https_format.or(self.dns_over_https_format),
+            local_address : request.local_address.or(self.local_address),
+            connect_to : request.connect_to.clone().or_else(|| self.connect_to.clone()),
+            title_case_headers : request.title_case_headers.or(self.title_case_headers),
+            trace_context : request.trace_context.or(self.trace_context),
+            otlp_endpoint : request.otlp_endpoint.clone().or_else(|| self.otlp_endpoint.clone()),
+            failover_statuses : request.failover_statuses.clone().or_else(|| self.failover_statuses.clone()),
+        }
+    }
+}
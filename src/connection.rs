@@ -0,0 +1,39 @@
+use std::cell::RefCell;
+use std::future::Future;
+use std::time::Duration;
+
+// What is seen of a connection while it is being made. The resolver and certificate verifier report it as
+// they run, which is in the task sending the request, so each request collects it through a task local.
+// Nothing is seen when an idle connection is reused.
+#[derive(Default)]
+pub struct Observation {
+    // How long the lookup of the host took, when it needed one.
+    pub dns : Option<Duration>,
+    pub handshake : Handshake,
+}
+
+// Nothing of the handshake is seen when a TLS session is resumed either.
+#[derive(Default, Clone)]
+pub struct Handshake {
+    // The certificates presented by the server, starting with its own.
+    pub certificates : Vec<Vec<u8>>,
+    pub tls_version : Option<&'static str>,
+}
+
+tokio::task_local! {
+    static OBSERVATION : RefCell<Observation>;
+}
+
+// Runs the future sending a request, along with what was seen of any connection made for it.
+pub async fn observe<F : Future>(future : F) -> (F::Output, Observation) {
+    OBSERVATION.scope(RefCell::new(Observation::default()), async {
+        let output = future.await;
+        (output, OBSERVATION.with(RefCell::take))
+    }).await
+}
+
+// Connections which carry on being made in the background, after the request was given another
+// connection, don't report to any request.
+pub fn record(update : impl FnOnce(&mut Observation)) {
+    let _ = OBSERVATION.try_with(|observation| update(&mut observation.borrow_mut()));
+}
@@ -0,0 +1,111 @@
+use crate::variables::{Variable, Variables};
+
+pub type Row = Variables;
+
+pub fn load_dataset(path : &std::path::PathBuf) -> Result<Vec<Row>, String> {
+    let is_json = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+
+    let rows = if is_json {
+        load_json(path)?
+    }
+    else {
+        load_csv(path)?
+    };
+
+    if rows.is_empty() {
+        Err(format!("The data file {:?} does not contain any rows.", path))
+    }
+    else {
+        Ok(rows)
+    }
+}
+
+fn load_csv(path : &std::path::PathBuf) -> Result<Vec<Row>, String> {
+    let mut reader = match csv::Reader::from_path(path) {
+        Ok(reader) => reader,
+        Err(error) => return Err(format!("Failed to open data file {:?}. {}", path, error)),
+    };
+
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(error) => return Err(format!("Failed to read the header row of data file {:?}. {}", path, error)),
+    };
+
+    let mut rows = Vec::new();
+
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => return Err(format!("Failed to read data file {:?}. {}", path, error)),
+        };
+
+        let row = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(column, value)| (String::from(column), Variable::Value(String::from(value))))
+            .collect();
+
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+fn load_json(path : &std::path::PathBuf) -> Result<Vec<Row>, String> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(error) => return Err(format!("Failed to read data file {:?}. {}", path, error)),
+    };
+
+    let objects : Vec<serde_json::Map<String, serde_json::Value>> = match serde_json::from_str(&content) {
+        Ok(objects) => objects,
+        Err(error) => return Err(format!("Unable to deserialise data file {:?} at line {}, column {}. It must be an array of objects.", path, error.line(), error.column())),
+    };
+
+    Ok(
+        objects
+        .into_iter()
+        .map(|object| {
+            object
+            .into_iter()
+            .map(|(column, value)| {
+                let value = match value {
+                    serde_json::Value::String(value) => value,
+                    value => value.to_string(),
+                };
+                (column, Variable::Value(value))
+            })
+            .collect()
+        })
+        .collect()
+    )
+}
+
+// Expands a matrix of values for each variable into a row for every combination of them.
+pub fn expand_matrix(matrix : &serde_json::Map<String, serde_json::Value>) -> Result<Vec<Row>, String> {
+    let mut rows = vec![Row::new()];
+
+    for (name, values) in matrix {
+        let values = match values {
+            serde_json::Value::Array(values) if !values.is_empty() => values,
+            _ => return Err(format!("The matrix entry for {} must be a non-empty array of values.", name)),
+        };
+
+        rows = rows
+            .into_iter()
+            .flat_map(|row| {
+                values.iter().map(move |value| {
+                    let value = match value {
+                        serde_json::Value::String(value) => value.clone(),
+                        value => value.to_string(),
+                    };
+                    let mut row = row.clone();
+                    row.insert(name.clone(), Variable::Value(value));
+                    row
+                })
+            })
+            .collect();
+    }
+
+    Ok(rows)
+}
@@ -0,0 +1,311 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Instant;
+
+use base64::Engine;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+
+const RECORD_TYPE_A : u16 = 1;
+const RECORD_TYPE_AAAA : u16 = 28;
+const CLASS_IN : u16 = 1;
+
+// How queries are sent to the resolver: as the JSON API which many resolvers offer, or as DNS messages
+// in the wire format of RFC 8484, which every DNS over HTTPS resolver supports.
+#[derive(Deserialize, Debug, Clone, Copy, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    Json,
+    Message,
+}
+
+// Resolves hostnames with a DNS over HTTPS resolver (such as https://cloudflare-dns.com/dns-query), for when
+// the system resolver is unreliable or intercepted. The resolver itself is found with the system resolver,
+// unless the endpoint is given as an IP address.
+pub struct DohResolver {
+    endpoint : String,
+    format : Format,
+    client : reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct DnsResponse {
+    #[serde(rename = "Status")]
+    status : u32,
+    #[serde(rename = "Answer", default)]
+    answer : Vec<DnsAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DnsAnswer {
+    #[serde(rename = "type")]
+    record_type : u16,
+    data : String,
+}
+
+impl DohResolver {
+    pub fn new(endpoint : &str, format : Option<Format>) -> Arc<DohResolver> {
+        Arc::new(DohResolver {
+            endpoint : endpoint.to_string(),
+            format : format.unwrap_or(Format::Json),
+            client : reqwest::Client::new(),
+        })
+    }
+}
+
+fn record_type_name(record_type : u16) -> &'static str {
+    match record_type {
+        RECORD_TYPE_AAAA => "AAAA",
+        _ => "A",
+    }
+}
+
+// Status codes are those of DNS, where 3 (NXDOMAIN) means the name doesn't exist.
+fn check_status(name : &str, status : u32) -> Result<(), String> {
+    match status {
+        0 => Ok(()),
+        status => Err(format!("The DNS over HTTPS resolver failed to resolve {}, with status {}.", name, status)),
+    }
+}
+
+async fn lookup_json(client : &reqwest::Client, endpoint : &str, name : &str, record_type : u16) -> Result<Vec<IpAddr>, String> {
+    let response = match
+        client
+        .get(endpoint)
+        .query(&[("name", name), ("type", record_type_name(record_type))])
+        .header("accept", "application/dns-json")
+        .send().await {
+
+        Ok(response) => response,
+        Err(error) => return Err(format!("Failed to query the DNS over HTTPS resolver for {}. {}", name, error)),
+    };
+
+    let response = match response.json::<DnsResponse>().await {
+        Ok(response) => response,
+        Err(error) => return Err(format!("Unable to parse the response of the DNS over HTTPS resolver for {}. {}", name, error)),
+    };
+    check_status(name, response.status)?;
+
+    Ok(
+        response.answer
+        .into_iter()
+        .filter(|answer| answer.record_type == RECORD_TYPE_A || answer.record_type == RECORD_TYPE_AAAA)
+        .filter_map(|answer| answer.data.parse().ok())
+        .collect()
+    )
+}
+
+// A query for one record type, with recursion desired. The ID is 0, as RFC 8484 recommends so that
+// responses can be cached.
+fn encode_query(name : &str, record_type : u16) -> Result<Vec<u8>, String> {
+    let mut query = vec![0, 0, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(format!("Cannot resolve {}, as it is not a valid hostname.", name));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&record_type.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    Ok(query)
+}
+
+fn read_u16(message : &[u8], offset : usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*message.get(offset)?, *message.get(offset + 1)?]))
+}
+
+// Names in a message end with an empty label, or with a pointer to the rest of the name elsewhere.
+fn skip_name(message : &[u8], mut offset : usize) -> Option<usize> {
+    loop {
+        let length = *message.get(offset)?;
+        match length {
+            0 => return Some(offset + 1),
+            length if length & 0xc0 == 0xc0 => return Some(offset + 2),
+            length => offset += 1 + length as usize,
+        }
+    }
+}
+
+// The addresses in the answers of a DNS message, along with its response code. Answers of other types,
+// such as the CNAMEs leading to the addresses, are skipped.
+fn decode_response(message : &[u8]) -> Option<(u32, Vec<IpAddr>)> {
+    let status = u32::from(read_u16(message, 2)? & 0x000f);
+    let questions = read_u16(message, 4)?;
+    let answers = read_u16(message, 6)?;
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = skip_name(message, offset)? + 4;
+    }
+
+    let mut addresses = Vec::new();
+    for _ in 0..answers {
+        offset = skip_name(message, offset)?;
+        let record_type = read_u16(message, offset)?;
+        let length = read_u16(message, offset + 8)? as usize;
+        let data = message.get(offset + 10..offset + 10 + length)?;
+        offset += 10 + length;
+
+        match (record_type, data.len()) {
+            (RECORD_TYPE_A, 4) => addresses.push(IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3]))),
+            (RECORD_TYPE_AAAA, 16) => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(data);
+                addresses.push(IpAddr::V6(Ipv6Addr::from(octets)));
+            },
+            _ => {},
+        }
+    }
+
+    Some((status, addresses))
+}
+
+async fn lookup_message(client : &reqwest::Client, endpoint : &str, name : &str, record_type : u16) -> Result<Vec<IpAddr>, String> {
+    let query = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(encode_query(name, record_type)?);
+    let response = match
+        client
+        .get(endpoint)
+        .query(&[("dns", query)])
+        .header("accept", "application/dns-message")
+        .send().await {
+
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => return Err(format!("The DNS over HTTPS resolver responded to the query for {} with status code {}.", name, response.status().as_u16())),
+        Err(error) => return Err(format!("Failed to query the DNS over HTTPS resolver for {}. {}", name, error)),
+    };
+
+    let message = match response.bytes().await {
+        Ok(message) => message,
+        Err(error) => return Err(format!("Failed to read the response of the DNS over HTTPS resolver for {}. {}", name, error)),
+    };
+    let (status, addresses) = match decode_response(&message) {
+        Some(decoded) => decoded,
+        None => return Err(format!("Unable to parse the response of the DNS over HTTPS resolver for {}, which is not a valid DNS message.", name)),
+    };
+    check_status(name, status)?;
+
+    Ok(addresses)
+}
+
+impl Resolve for DohResolver {
+    fn resolve(&self, name : Name) -> Resolving {
+        let client = self.client.clone();
+        let endpoint = self.endpoint.clone();
+        let format = self.format;
+
+        Box::pin(async move {
+            let name = name.as_str();
+            let lookup = |record_type| {
+                let (client, endpoint) = (&client, &endpoint);
+                async move {
+                    match format {
+                        Format::Json => lookup_json(client, endpoint, name, record_type).await,
+                        Format::Message => lookup_message(client, endpoint, name, record_type).await,
+                    }
+                }
+            };
+
+            // Either lookup failing is only an error when the other finds no addresses, as many names have
+            // no IPv6 addresses and some resolvers fail AAAA queries for them.
+            let (ipv4, ipv6) = tokio::join!(lookup(RECORD_TYPE_A), lookup(RECORD_TYPE_AAAA));
+            let mut addresses = Vec::new();
+            let mut failure = None;
+            for lookup in [ipv4, ipv6] {
+                match lookup {
+                    Ok(found) => addresses.extend(found),
+                    Err(error) => {
+                        tracing::debug!(%error, "DNS over HTTPS lookup failed");
+                        failure.get_or_insert(error);
+                    },
+                }
+            }
+
+            if addresses.is_empty() {
+                let error = failure.unwrap_or_else(|| format!("The DNS over HTTPS resolver returned no addresses for {}.", name));
+                return Err(error.into());
+            }
+
+            // The port is filled in from the URL of the request.
+            let addrs : Addrs = Box::new(addresses.into_iter().map(|address| SocketAddr::new(address, 0)));
+            Ok(addrs)
+        })
+    }
+}
+
+// Resolves with DNS over HTTPS when it is configured and otherwise with the system resolver, timing each
+// lookup for the metrics.
+pub struct TimedResolver {
+    doh : Option<Arc<DohResolver>>,
+}
+
+impl TimedResolver {
+    pub fn new(doh : Option<Arc<DohResolver>>) -> Arc<TimedResolver> {
+        Arc::new(TimedResolver { doh })
+    }
+}
+
+fn system_lookup(name : Name) -> Resolving {
+    Box::pin(async move {
+        let addresses : Vec<_> = tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+        let addrs : Addrs = Box::new(addresses.into_iter());
+        Ok(addrs)
+    })
+}
+
+impl Resolve for TimedResolver {
+    fn resolve(&self, name : Name) -> Resolving {
+        let lookup = match &self.doh {
+            Some(doh) => doh.resolve(name),
+            None => system_lookup(name),
+        };
+
+        Box::pin(async move {
+            let started = Instant::now();
+            let addresses = lookup.await;
+            crate::connection::record(|observation| observation.dns = Some(started.elapsed()));
+            addresses
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_is_encoded_with_labels() {
+        let query = encode_query("api.somedomain.com.", RECORD_TYPE_AAAA).unwrap();
+
+        assert_eq!(&query[..12], &[0, 0, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&query[12..], b"\x03api\x0asomedomain\x03com\x00\x00\x1c\x00\x01");
+        assert!(encode_query("a..com", RECORD_TYPE_A).is_err());
+    }
+
+    #[test]
+    fn addresses_are_decoded_from_answers() {
+        let mut message = vec![0, 0, 0x81, 0x80, 0, 1, 0, 3, 0, 0, 0, 0];
+        message.extend_from_slice(b"\x03www\x0asomedomain\x03com\x00\x00\x01\x00\x01");
+        // A CNAME to api.somedomain.com, pointing back to somedomain.com in the question.
+        message.extend_from_slice(b"\xc0\x0c\x00\x05\x00\x01\x00\x00\x00\x3c\x00\x06\x03api\xc0\x10");
+        message.extend_from_slice(b"\xc0\x30\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04\x0a\x00\x00\x0c");
+        message.extend_from_slice(b"\xc0\x30\x00\x1c\x00\x01\x00\x00\x00\x3c\x00\x10\x20\x01\x0d\xb8\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01");
+
+        let (status, addresses) = decode_response(&message).unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(addresses, vec!["10.0.0.12".parse::<IpAddr>().unwrap(), "2001:db8::1".parse().unwrap()]);
+    }
+
+    #[test]
+    fn status_and_truncation_are_reported() {
+        let mut message = vec![0, 0, 0x81, 0x83, 0, 1, 0, 0, 0, 0, 0, 0];
+        message.extend_from_slice(b"\x07missing\x00\x00\x01\x00\x01");
+        assert_eq!(decode_response(&message), Some((3, Vec::new())));
+
+        let message = [0, 0, 0x81, 0x80, 0, 0, 0, 1, 0, 0, 0, 0, 0xc0, 0x0c, 0, 1];
+        assert_eq!(decode_response(&message), None);
+    }
+}
@@ -0,0 +1,176 @@
+// The ways in which callsy can fail. Errors from the libraries callsy uses are kept as the source,
+// so that they can be inspected and reported in full.
+#[derive(Debug)]
+pub enum CallsyError {
+    // Options which can't be used together, or a file which the user chose not to overwrite.
+    Usage(String),
+    Config(String),
+    // A collection, data or environment file which couldn't be loaded or saved.
+    File(String),
+    Io { message : String, source : std::io::Error },
+    Parse { message : String, source : serde_json::Error },
+    InvalidRequest(String),
+    InvalidHeader { message : String, source : Box<dyn std::error::Error + Send + Sync> },
+    Capture(String),
+    // A response body whose checksum wasn't the one given in expect_checksum.
+    ChecksumMismatch(String),
+    Secret(String),
+    Tls { message : String, source : reqwest::Error },
+    UntrustedCertificate(String),
+    CertificateExpiring(String),
+    // A request which has no recorded response in the cassette being replayed.
+    NotInCassette(String),
+    // A request which would need network access when running with --offline.
+    Offline(String),
+    Timeout { message : String, source : reqwest::Error },
+    // A response body which arrived slower than the --speed-limit for the whole of the --speed-time.
+    TooSlow(String),
+    Connection { message : String, source : reqwest::Error },
+    // A run which was stopped early by --abort-after or --abort-error-rate.
+    Aborted(String),
+    Network { message : String, source : reqwest::Error },
+}
+
+impl CallsyError {
+    pub fn from_reqwest(message : String, source : reqwest::Error) -> CallsyError {
+        if source.is_timeout() {
+            CallsyError::Timeout { message, source }
+        }
+        else if source.is_connect() {
+            CallsyError::Connection { message, source }
+        }
+        else {
+            CallsyError::Network { message, source }
+        }
+    }
+
+    pub fn category(&self) -> &'static str {
+        match self {
+            CallsyError::Usage(_) => "usage",
+            CallsyError::Config(_) => "config",
+            CallsyError::File(_) => "file",
+            CallsyError::Io { .. } => "io",
+            CallsyError::Parse { .. } => "parse",
+            CallsyError::InvalidRequest(_) => "invalid_request",
+            CallsyError::InvalidHeader { .. } => "invalid_header",
+            CallsyError::Capture(_) => "capture",
+            CallsyError::ChecksumMismatch(_) => "checksum_mismatch",
+            CallsyError::Secret(_) => "secret",
+            CallsyError::Tls { .. } => "tls",
+            CallsyError::UntrustedCertificate(_) => "untrusted_certificate",
+            CallsyError::CertificateExpiring(_) => "certificate_expiring",
+            CallsyError::NotInCassette(_) => "not_in_cassette",
+            CallsyError::Offline(_) => "offline",
+            CallsyError::Timeout { .. } => "timeout",
+            CallsyError::TooSlow(_) => "too_slow",
+            CallsyError::Connection { .. } => "connection",
+            CallsyError::Aborted(_) => "aborted",
+            CallsyError::Network { .. } => "network",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            CallsyError::Usage(message)
+            | CallsyError::Config(message)
+            | CallsyError::File(message)
+            | CallsyError::InvalidRequest(message)
+            | CallsyError::Capture(message)
+            | CallsyError::ChecksumMismatch(message)
+            | CallsyError::Secret(message)
+            | CallsyError::UntrustedCertificate(message)
+            | CallsyError::CertificateExpiring(message)
+            | CallsyError::NotInCassette(message)
+            | CallsyError::Offline(message)
+            | CallsyError::TooSlow(message)
+            | CallsyError::Aborted(message)
+            | CallsyError::Io { message, .. }
+            | CallsyError::Parse { message, .. }
+            | CallsyError::InvalidHeader { message, .. }
+            | CallsyError::Tls { message, .. }
+            | CallsyError::Timeout { message, .. }
+            | CallsyError::Connection { message, .. }
+            | CallsyError::Network { message, .. } => message,
+        }
+    }
+
+    // Errors which may not happen again if the request is retried.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, CallsyError::Timeout { .. } | CallsyError::TooSlow(_) | CallsyError::Connection { .. } | CallsyError::Network { .. })
+    }
+
+    // Exit codes follow the conventions of sysexits.h.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CallsyError::Usage(_) => 64,
+            CallsyError::Parse { .. }
+            | CallsyError::InvalidRequest(_)
+            | CallsyError::InvalidHeader { .. }
+            | CallsyError::Capture(_)
+            | CallsyError::ChecksumMismatch(_) => 65,
+            CallsyError::Secret(_)
+            | CallsyError::NotInCassette(_)
+            | CallsyError::Offline(_)
+            | CallsyError::Connection { .. }
+            | CallsyError::Aborted(_)
+            | CallsyError::Network { .. } => 69,
+            CallsyError::File(_) | CallsyError::Io { .. } => 74,
+            CallsyError::Timeout { .. } | CallsyError::TooSlow(_) => 75,
+            CallsyError::Tls { .. }
+            | CallsyError::UntrustedCertificate(_)
+            | CallsyError::CertificateExpiring(_) => 76,
+            CallsyError::Config(_) => 78,
+        }
+    }
+
+    // The message of the innermost error which caused this one.
+    fn root_cause(&self) -> Option<String> {
+        let mut cause = None;
+        let mut next = std::error::Error::source(self);
+        while let Some(error) = next {
+            cause = Some(error.to_string());
+            next = error.source();
+        }
+        cause
+    }
+}
+
+impl std::fmt::Display for CallsyError {
+    fn fmt(&self, formatter : &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "{}", self.message())
+    }
+}
+
+impl std::error::Error for CallsyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CallsyError::Io { source, .. } => Some(source),
+            CallsyError::Parse { source, .. } => Some(source),
+            CallsyError::InvalidHeader { source, .. } => Some(source.as_ref()),
+            CallsyError::Tls { source, .. }
+            | CallsyError::Timeout { source, .. }
+            | CallsyError::Connection { source, .. }
+            | CallsyError::Network { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    category : &'a str,
+    message : &'a str,
+    source : Option<String>,
+    retryable : bool,
+}
+
+impl serde::Serialize for CallsyError {
+    fn serialize<S : serde::Serializer>(&self, serializer : S) -> Result<S::Ok, S::Error> {
+        Report {
+            category : self.category(),
+            message : self.message(),
+            source : self.root_cause(),
+            retryable : self.is_retryable(),
+        }.serialize(serializer)
+    }
+}
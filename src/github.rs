@@ -0,0 +1,116 @@
+use std::path::PathBuf;
+
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+
+use crate::error::CallsyError;
+use crate::tokens::CachedToken;
+
+// Installation tokens for a GitHub App, exchanged for a JWT signed with the app's private key.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct GithubApp {
+    app_id : String,
+    installation_id : u64,
+    private_key_file : Option<PathBuf>,
+    private_key_command : Option<String>,
+    // The API of a GitHub Enterprise Server, such as https://github.example.com/api/v3.
+    api_url : Option<String>,
+}
+
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss : &'a str,
+    iat : u64,
+    exp : u64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    token : String,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    message : String,
+}
+
+impl GithubApp {
+    fn api_url(&self) -> &str {
+        self.api_url.as_deref().unwrap_or("https://api.github.com").trim_end_matches('/')
+    }
+
+    pub fn token(&self, offline : bool) -> Result<String, CallsyError> {
+        let cache_key = format!("github_app {} {} {}", self.api_url(), self.app_id, self.installation_id);
+
+        if let Some(cached) = crate::tokens::load(&cache_key).filter(|cached| cached.is_fresh()) {
+            tracing::debug!("Using cached GitHub App installation token");
+            return Ok(cached.access_token);
+        }
+        if offline {
+            return Err(CallsyError::Offline(String::from("Cannot fetch a GitHub App installation token while offline, and there is no cached token which is still valid.")));
+        }
+
+        let token = tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(self.exchange())
+        }).map_err(CallsyError::InvalidRequest)?;
+
+        crate::masking::register(&token.access_token);
+        crate::tokens::store(&cache_key, &token);
+
+        Ok(token.access_token)
+    }
+
+    // GitHub accepts app JWTs which expire within ten minutes, and the issued at time is set a minute in
+    // the past to allow for clock drift.
+    fn app_jwt(&self) -> Result<String, String> {
+        let key = crate::config::read_secret(&self.private_key_file, &self.private_key_command, "private_key", "github_app")?;
+        let encoding_key = match EncodingKey::from_rsa_pem(key.as_bytes()) {
+            Ok(encoding_key) => encoding_key,
+            Err(error) => return Err(format!("Unable to parse the private key of the GitHub App. {}", error)),
+        };
+
+        let now = crate::tokens::unix_time();
+        let claims = Claims {
+            iss : &self.app_id,
+            iat : now.saturating_sub(60),
+            exp : now + 540,
+        };
+
+        match jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &encoding_key) {
+            Ok(token) => Ok(token),
+            Err(error) => Err(format!("Failed to sign the JWT for the GitHub App. {}", error)),
+        }
+    }
+
+    async fn exchange(&self) -> Result<CachedToken, String> {
+        let app_jwt = self.app_jwt()?;
+        let url = format!("{}/app/installations/{}/access_tokens", self.api_url(), self.installation_id);
+
+        let response = reqwest::Client::new()
+            .post(url)
+            .bearer_auth(app_jwt)
+            .header("accept", "application/vnd.github+json")
+            .header("user-agent", format!("callsy/{}", env!("CARGO_PKG_VERSION")))
+            .send()
+            .await;
+
+        let response = match response {
+            Ok(response) => response,
+            Err(error) => return Err(format!("Error when requesting an installation token from GitHub, {}", error)),
+        };
+
+        // Installation tokens always last for an hour.
+        let status = response.status();
+        if status.is_success() {
+            return match response.json::<TokenResponse>().await {
+                Ok(token) => Ok(CachedToken::new(token.token, 3600, None)),
+                Err(error) => Err(format!("Failed to read the installation token from GitHub, {}", error)),
+            };
+        }
+
+        match response.json::<ErrorResponse>().await {
+            Ok(error) => Err(format!("GitHub responded with status {} when requesting an installation token. {}", status, error.message)),
+            Err(_) => Err(format!("GitHub responded with status {} when requesting an installation token.", status)),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::path::PathBuf;
+
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+
+use crate::error::CallsyError;
+use crate::tokens::CachedToken;
+
+// Access tokens for Google Cloud APIs, exchanged for a JWT signed with the key of a service account.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Google {
+    // The JSON key downloaded for the service account.
+    key_file : PathBuf,
+    #[serde(default)]
+    scopes : Vec<String>,
+    // The user to act as, for a service account with domain-wide delegation.
+    subject : Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ServiceAccountKey {
+    client_email : String,
+    private_key : String,
+    private_key_id : Option<String>,
+    token_uri : Option<String>,
+}
+
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss : &'a str,
+    scope : String,
+    aud : &'a str,
+    iat : u64,
+    exp : u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sub : Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token : String,
+    expires_in : u64,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error : String,
+    error_description : Option<String>,
+}
+
+impl Google {
+    fn scope(&self) -> String {
+        match self.scopes.is_empty() {
+            true => String::from("https://www.googleapis.com/auth/cloud-platform"),
+            false => self.scopes.join(" "),
+        }
+    }
+
+    fn read_key(&self) -> Result<ServiceAccountKey, String> {
+        let content = match std::fs::read_to_string(&self.key_file) {
+            Ok(content) => content,
+            Err(error) => return Err(format!("Failed to read Google service account key file {:?}. {}", self.key_file, error)),
+        };
+
+        match serde_json::from_str::<ServiceAccountKey>(&content) {
+            Ok(key) => {
+                crate::masking::register(&key.private_key);
+                Ok(key)
+            },
+            Err(error) => Err(format!("Unable to parse Google service account key file {:?}. {}", self.key_file, error)),
+        }
+    }
+
+    pub fn token(&self, offline : bool) -> Result<String, CallsyError> {
+        let key = self.read_key().map_err(CallsyError::InvalidRequest)?;
+        let cache_key = format!("google {} {} {}", key.client_email, self.scope(), self.subject.as_deref().unwrap_or_default());
+
+        if let Some(cached) = crate::tokens::load(&cache_key).filter(|cached| cached.is_fresh()) {
+            tracing::debug!("Using cached Google access token");
+            return Ok(cached.access_token);
+        }
+        if offline {
+            return Err(CallsyError::Offline(String::from("Cannot fetch an access token from Google while offline, and there is no cached token which is still valid.")));
+        }
+
+        let token = tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(self.exchange(&key))
+        }).map_err(CallsyError::InvalidRequest)?;
+
+        crate::masking::register(&token.access_token);
+        crate::tokens::store(&cache_key, &token);
+
+        Ok(token.access_token)
+    }
+
+    fn assertion(&self, key : &ServiceAccountKey, token_uri : &str) -> Result<String, String> {
+        let encoding_key = match EncodingKey::from_rsa_pem(key.private_key.as_bytes()) {
+            Ok(encoding_key) => encoding_key,
+            Err(error) => return Err(format!("Unable to parse the private key of the Google service account. {}", error)),
+        };
+
+        let now = crate::tokens::unix_time();
+        let claims = Claims {
+            iss : &key.client_email,
+            scope : self.scope(),
+            aud : token_uri,
+            iat : now,
+            exp : now + 3600,
+            sub : self.subject.as_deref(),
+        };
+
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = key.private_key_id.clone();
+
+        match jsonwebtoken::encode(&header, &claims, &encoding_key) {
+            Ok(assertion) => Ok(assertion),
+            Err(error) => Err(format!("Failed to sign the JWT for the Google service account. {}", error)),
+        }
+    }
+
+    async fn exchange(&self, key : &ServiceAccountKey) -> Result<CachedToken, String> {
+        let token_uri = key.token_uri.as_deref().unwrap_or("https://oauth2.googleapis.com/token");
+        let assertion = self.assertion(key, token_uri)?;
+        let form = [
+            ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+            ("assertion", assertion.as_str()),
+        ];
+
+        let response = match reqwest::Client::new().post(token_uri).form(&form).send().await {
+            Ok(response) => response,
+            Err(error) => return Err(format!("Error when requesting an access token from Google, {}", error)),
+        };
+
+        let status = response.status();
+        if status.is_success() {
+            return match response.json::<TokenResponse>().await {
+                Ok(token) => Ok(CachedToken::new(token.access_token, token.expires_in, None)),
+                Err(error) => Err(format!("Failed to read the access token from Google, {}", error)),
+            };
+        }
+
+        match response.json::<ErrorResponse>().await {
+            Ok(ErrorResponse { error, error_description : Some(description) }) => Err(format!("Google refused to issue an access token, {}. {}", error, description)),
+            Ok(ErrorResponse { error, error_description : None }) => Err(format!("Google refused to issue an access token, {}.", error)),
+            Err(_) => Err(format!("Google responded with status {} when requesting an access token.", status)),
+        }
+    }
+}
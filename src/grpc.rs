@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use base64::Engine;
+
+// gRPC-Web wraps each message in a frame of a flag byte and a four byte length, and sends the trailers
+// as a final frame in the body, as browsers can't read HTTP trailers.
+const COMPRESSED_FLAG : u8 = 0x01;
+const TRAILER_FLAG : u8 = 0x80;
+
+// What came back from a gRPC-Web call, beside the HTTP response it came in. The messages are decoded to
+// JSON when the request has a proto with the response type, and are otherwise left in base64.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GrpcWebResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status : Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message : Option<String>,
+    pub messages : Vec<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub trailers : HashMap<String, String>,
+}
+
+fn frame(message : &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(message.len() + 5);
+    framed.push(0);
+    framed.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    framed.extend_from_slice(message);
+    framed
+}
+
+// Without a proto, the body of a gRPC-Web request is written as the serialized message in base64.
+pub fn decode_message(body : &str) -> Result<Vec<u8>, String> {
+    match base64::engine::general_purpose::STANDARD.decode(body.trim()) {
+        Ok(message) => Ok(message),
+        Err(error) => Err(format!("The body of a gRPC-Web request must be the serialized message in base64, unless it has a proto. {}", error)),
+    }
+}
+
+// With the text format the whole frame is sent in base64.
+pub fn encode_request(message : &[u8], text : bool) -> Vec<u8> {
+    let framed = frame(message);
+    match text {
+        true => base64::engine::general_purpose::STANDARD.encode(framed).into_bytes(),
+        false => framed,
+    }
+}
+
+fn content_type(headers : &HashMap<String, Vec<String>>) -> Option<&str> {
+    headers
+    .iter()
+    .find(|(header, _)| header.eq_ignore_ascii_case("content-type"))
+    .and_then(|(_, values)| values.first())
+    .map(String::as_str)
+}
+
+pub fn is_grpc_web(headers : &HashMap<String, Vec<String>>) -> bool {
+    content_type(headers).is_some_and(|content_type| content_type.trim().to_lowercase().starts_with("application/grpc-web"))
+}
+
+// A server may encode each frame as it is sent, so a text body can be several pieces of base64 one after
+// another, each with its own padding.
+fn decode_text(body : &[u8]) -> Result<Vec<u8>, String> {
+    let text : Vec<u8> = body.iter().copied().filter(|byte| !byte.is_ascii_whitespace()).collect();
+
+    let mut decoded = Vec::new();
+    let mut start = 0;
+    while start < text.len() {
+        let padding = text[start..].iter().position(|byte| *byte == b'=').map(|position| start + position);
+        let end = match padding {
+            Some(padding) => padding + text[padding..].iter().take_while(|byte| **byte == b'=').count(),
+            None => text.len(),
+        };
+
+        match base64::engine::general_purpose::STANDARD.decode(&text[start..end]) {
+            Ok(piece) => decoded.extend(piece),
+            Err(error) => return Err(format!("The gRPC-Web text response is not valid base64. {}", error)),
+        }
+        start = end;
+    }
+
+    Ok(decoded)
+}
+
+fn parse_trailers(data : &[u8], trailers : &mut HashMap<String, String>) {
+    for line in String::from_utf8_lossy(data).split("\r\n") {
+        if let Some((name, value)) = line.split_once(':') {
+            trailers.insert(name.trim().to_lowercase(), String::from(value.trim()));
+        }
+    }
+}
+
+pub fn decode_response(body : &[u8], headers : &HashMap<String, Vec<String>>, proto : Option<&crate::proto::Compiled>) -> Result<GrpcWebResponse, String> {
+    let text = content_type(headers).is_some_and(|content_type| content_type.trim().to_lowercase().starts_with("application/grpc-web-text"));
+    let bytes = if text { decode_text(body)? } else { body.to_vec() };
+
+    let mut messages = Vec::new();
+    let mut trailers = HashMap::new();
+    let mut rest = bytes.as_slice();
+    while !rest.is_empty() {
+        if rest.len() < 5 {
+            return Err(String::from("The gRPC-Web response ends partway through the header of a frame."));
+        }
+        let flag = rest[0];
+        let length = u32::from_be_bytes([rest[1], rest[2], rest[3], rest[4]]) as usize;
+        let data = match rest.get(5..5 + length) {
+            Some(data) => data,
+            None => return Err(String::from("The gRPC-Web response ends partway through a frame.")),
+        };
+
+        if flag & TRAILER_FLAG != 0 {
+            parse_trailers(data, &mut trailers);
+        }
+        else if flag & COMPRESSED_FLAG != 0 {
+            return Err(String::from("The gRPC-Web response has a compressed message, which is not supported."));
+        }
+        else {
+            let decoded = match proto {
+                Some(proto) => proto.decode(data)?,
+                None => None,
+            };
+            messages.push(decoded.unwrap_or_else(|| serde_json::Value::from(base64::engine::general_purpose::STANDARD.encode(data))));
+        }
+        rest = &rest[5 + length..];
+    }
+
+    // A response without any messages can give its status in the headers instead of a trailer frame.
+    for name in ["grpc-status", "grpc-message"] {
+        if !trailers.contains_key(name) {
+            if let Some((_, values)) = headers.iter().find(|(header, _)| header.eq_ignore_ascii_case(name)) {
+                trailers.insert(String::from(name), values.first().cloned().unwrap_or_default());
+            }
+        }
+    }
+
+    let status = trailers.remove("grpc-status").and_then(|status| status.parse().ok());
+    // The message is percent encoded, as it can contain any text.
+    let message = trailers
+        .remove("grpc-message")
+        .filter(|message| !message.is_empty())
+        .map(|message| percent_encoding::percent_decode_str(&message).decode_utf8_lossy().into_owned());
+
+    Ok(GrpcWebResponse { status, message, messages, trailers })
+}
@@ -0,0 +1,254 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::CallsyError;
+use crate::processing::OutputResponse;
+
+// One record of the history, for a request and what came back from it.
+#[derive(Serialize)]
+struct Entry<'a> {
+    // Seconds since the Unix epoch, when the request was started.
+    timestamp : f64,
+    name : &'a str,
+    // The request as it was written, before variables were substituted.
+    request : &'a str,
+    // The URL the request was sent to, which a request that failed before being sent doesn't have.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url : Option<String>,
+    time : f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response : Option<&'a OutputResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error : Option<&'a CallsyError>,
+}
+
+// Where each request is recorded once it has been sent, so that a history builds up across runs. This
+// is an NDJSON file given with --append-to, and a SQLite database when history_database is configured.
+pub struct History {
+    file : Option<(PathBuf, std::fs::File)>,
+    database : Option<rusqlite::Connection>,
+    show_secrets : bool,
+}
+
+fn open_database(path : &Path) -> Result<rusqlite::Connection, String> {
+    let connection = match rusqlite::Connection::open(path) {
+        Ok(connection) => connection,
+        Err(error) => return Err(format!("Failed to open history database {:?}. {}", path, error)),
+    };
+
+    let created = connection.execute_batch("
+        CREATE TABLE IF NOT EXISTS history (
+            id INTEGER PRIMARY KEY,
+            timestamp REAL NOT NULL,
+            name TEXT NOT NULL,
+            url TEXT NOT NULL,
+            status_code INTEGER,
+            entry TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS history_timestamp ON history (timestamp);
+    ");
+
+    match created {
+        Ok(()) => Ok(connection),
+        Err(error) => Err(format!("Failed to set up history database {:?}. {}", path, error)),
+    }
+}
+
+impl History {
+    pub fn open(file_path : Option<&Path>, database_path : Option<&Path>, show_secrets : bool) -> Result<Option<History>, CallsyError> {
+        if file_path.is_none() && database_path.is_none() {
+            return Ok(None);
+        }
+
+        let file = match file_path {
+            Some(path) => {
+                match std::fs::OpenOptions::new().create(true).append(true).open(path) {
+                    Ok(file) => Some((path.to_path_buf(), file)),
+                    Err(source) => return Err(CallsyError::Io { message : format!("Failed to open history file {:?}. {}", path, source), source }),
+                }
+            },
+            None => None,
+        };
+
+        let database = match database_path {
+            Some(path) => Some(open_database(path).map_err(CallsyError::File)?),
+            None => None,
+        };
+
+        Ok(Some(History { file, database, show_secrets }))
+    }
+
+    // Failing to record a request is reported, but doesn't stop the run.
+    pub fn append(&mut self, name : &str, request : &str, started : std::time::Instant, result : Result<&OutputResponse, &CallsyError>) {
+        let time = started.elapsed();
+        let timestamp = match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+            Ok(now) => now.saturating_sub(time).as_secs_f64(),
+            Err(_) => 0.0,
+        };
+
+        let url = result.ok().and_then(OutputResponse::url).map(|url| url.to_string());
+        let entry = Entry {
+            timestamp,
+            name,
+            request,
+            url : url.clone(),
+            time : time.as_secs_f64(),
+            response : result.ok(),
+            error : result.err(),
+        };
+
+        let line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(_) => panic!("Internal error, could not serialize JSON data for history"),
+        };
+        let line = if self.show_secrets { line } else { crate::masking::mask(&line) };
+
+        if let Some((path, file)) = &mut self.file {
+            if let Err(error) = file.write_all(format!("{}\n", line).as_bytes()) {
+                tracing::warn!(?path, %error, "Failed to append to the history file");
+            }
+        }
+
+        if let Some(database) = &self.database {
+            // The URL is the one the request was sent to. A request which failed before it was sent only has
+            // the URL written in the request, which is the first line of its description.
+            let url = match url {
+                Some(url) => url,
+                None => request.lines().next().and_then(|line| line.split_once(' ')).map(|(_, url)| String::from(url)).unwrap_or_default(),
+            };
+            let url = if self.show_secrets { url } else { crate::masking::mask(&url) };
+            let status_code = result.ok().map(|output_response| output_response.status_code());
+
+            let inserted = database.execute(
+                "INSERT INTO history (timestamp, name, url, status_code, entry) VALUES (?1, ?2, ?3, ?4, ?5)",
+                rusqlite::params![timestamp, name, url, status_code, line],
+            );
+            if let Err(error) = inserted {
+                tracing::warn!(%error, "Failed to add to the history database");
+            }
+        }
+    }
+}
+
+pub struct Search {
+    pub url_contains : Option<String>,
+    pub status : Option<String>,
+    pub since : Option<String>,
+    pub name : Option<String>,
+    pub limit : usize,
+}
+
+// Parses a duration such as 30s, 15m, 12h, 2d or 1w into seconds.
+fn parse_age(age : &str) -> Result<f64, String> {
+    let age = age.trim();
+    let split = age.find(|character : char| !character.is_ascii_digit()).unwrap_or(age.len());
+    let (number, unit) = age.split_at(split);
+
+    let number = match number.parse::<u64>() {
+        Ok(number) => number as f64,
+        Err(_) => return Err(format!("The age {} is not a number followed by a unit, such as 2d.", age)),
+    };
+
+    match unit {
+        "s" => Ok(number),
+        "m" => Ok(number * 60.0),
+        "h" => Ok(number * 60.0 * 60.0),
+        "d" => Ok(number * 60.0 * 60.0 * 24.0),
+        "w" => Ok(number * 60.0 * 60.0 * 24.0 * 7.0),
+        _ => Err(format!("The unit of the age {} is not one of s, m, h, d or w.", age)),
+    }
+}
+
+// Parses a status such as 404, or a class of statuses such as 5xx, into the range of codes it covers.
+fn parse_status(status : &str) -> Result<(u16, u16), String> {
+    let status = status.trim().to_lowercase();
+
+    let range = match status.strip_suffix("xx") {
+        Some(class) => class.parse::<u16>().ok().filter(|class| (1..=5).contains(class)).map(|class| (class * 100, class * 100 + 99)),
+        None => status.parse::<u16>().ok().filter(|code| (100..=599).contains(code)).map(|code| (code, code)),
+    };
+
+    match range {
+        Some(range) => Ok(range),
+        None => Err(format!("The status {} is not a status code, or a class of them such as 5xx.", status)),
+    }
+}
+
+// Finds the requests in the history database which match every filter given, most recent first,
+// returning each as the line of JSON it was stored as.
+pub fn search(database_path : &Path, search : &Search) -> Result<Vec<String>, CallsyError> {
+    let database = open_database(database_path).map_err(CallsyError::File)?;
+
+    let mut conditions = Vec::new();
+    let mut parameters = Vec::<Box<dyn rusqlite::ToSql>>::new();
+
+    if let Some(url_contains) = &search.url_contains {
+        conditions.push("instr(url, ?) > 0");
+        parameters.push(Box::new(url_contains.clone()));
+    }
+    if let Some(name) = &search.name {
+        conditions.push("name = ?");
+        parameters.push(Box::new(name.clone()));
+    }
+    if let Some(status) = &search.status {
+        let (lowest, highest) = parse_status(status).map_err(CallsyError::Usage)?;
+        conditions.push("status_code BETWEEN ? AND ?");
+        parameters.push(Box::new(lowest));
+        parameters.push(Box::new(highest));
+    }
+    if let Some(since) = &search.since {
+        let now = match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+            Ok(now) => now.as_secs_f64(),
+            Err(_) => 0.0,
+        };
+        conditions.push("timestamp >= ?");
+        parameters.push(Box::new(now - parse_age(since).map_err(CallsyError::Usage)?));
+    }
+
+    let mut query = String::from("SELECT entry FROM history");
+    if !conditions.is_empty() {
+        query.push_str(" WHERE ");
+        query.push_str(&conditions.join(" AND "));
+    }
+    query.push_str(&format!(" ORDER BY timestamp DESC LIMIT {}", search.limit));
+
+    let mut statement = match database.prepare(&query) {
+        Ok(statement) => statement,
+        Err(error) => return Err(CallsyError::File(format!("Failed to search the history database. {}", error))),
+    };
+
+    let rows = statement.query_map(rusqlite::params_from_iter(parameters.iter()), |row| row.get::<_, String>(0));
+    match rows.and_then(|rows| rows.collect::<Result<Vec<_>, _>>()) {
+        Ok(entries) => Ok(entries),
+        Err(error) => Err(CallsyError::File(format!("Failed to search the history database. {}", error))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ages_are_parsed_into_seconds() {
+        assert_eq!(parse_age("30s"), Ok(30.0));
+        assert_eq!(parse_age("15m"), Ok(900.0));
+        assert_eq!(parse_age(" 2h "), Ok(7200.0));
+        assert_eq!(parse_age("2d"), Ok(172800.0));
+        assert_eq!(parse_age("1w"), Ok(604800.0));
+
+        for age in ["", "2", "d", "2y", "-2d", "1.5h", "2 d"] {
+            assert!(parse_age(age).is_err(), "{:?} should not parse", age);
+        }
+    }
+
+    #[test]
+    fn statuses_are_parsed_into_ranges() {
+        assert_eq!(parse_status("404"), Ok((404, 404)));
+        assert_eq!(parse_status("5xx"), Ok((500, 599)));
+        assert_eq!(parse_status("2XX"), Ok((200, 299)));
+
+        for status in ["", "xx", "0xx", "6xx", "42", "1000", "4x", "abc"] {
+            assert!(parse_status(status).is_err(), "{:?} should not parse", status);
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use crate::report::{escape, Outcome, Report};
+
+const STYLE : &str = "\
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; width: 100%; }
+th, td { text-align: left; padding: 0.4em 0.8em; border-bottom: 1px solid #ddd; vertical-align: top; }
+pre { background: #f6f6f6; padding: 0.8em; overflow-x: auto; white-space: pre-wrap; }
+.passed { color: #1a7f37; }
+.failed, .errored { color: #cf222e; }";
+
+// Formats the report as a standalone HTML page, with the request and response of each case
+// in an expandable section.
+pub fn to_html(report : &Report) -> String {
+    let failed = report.failures() + report.errors();
+
+    let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!("<title>{} - callsy report</title>\n<style>\n{}\n</style>\n</head>\n<body>\n", escape(&report.suite), STYLE));
+    html.push_str(&format!("<h1>{}</h1>\n", escape(&report.suite)));
+    html.push_str(&format!("<p>{} requests, {} passed, {} failed, in {:.3} seconds.</p>\n", report.cases.len(), report.cases.len() - failed, failed, report.time()));
+    html.push_str("<table>\n<tr><th>Request</th><th>Result</th><th>Time (s)</th><th>Details</th></tr>\n");
+
+    for case in &report.cases {
+        let (class, result) = match &case.outcome {
+            Outcome::Passed => ("passed", String::from("Passed")),
+            Outcome::Failed(message) => ("failed", message.clone()),
+            Outcome::Errored { category, message } => ("errored", format!("{} error: {}", category, message)),
+        };
+
+        html.push_str(&format!("<tr><td>{}</td><td class=\"{}\">{}</td><td>{:.3}</td><td>", escape(&case.name), class, escape(&result), case.time.as_secs_f64()));
+        html.push_str(&format!("<details><summary>Request</summary><pre>{}</pre></details>", escape(&case.request)));
+        if let Some(response) = &case.response {
+            html.push_str(&format!("<details><summary>Response</summary><pre>{}</pre></details>", escape(response)));
+        }
+        html.push_str("</td></tr>\n");
+    }
+
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
@@ -0,0 +1,328 @@
+use std::path::{Path, PathBuf};
+
+use serde_json::{Map, Value};
+
+// Fragments of request and collection files can come from other files, which are found relative to the
+// file they are used in. An object of just {"$ref" : "file.json#/pointer"} is replaced by what it refers
+// to, the whole file or the part at the JSON pointer, and files which aren't .json are included as text.
+// An object with "$include" gives the name of a file (or a list of them) holding an object whose fields
+// it takes, with its own fields given alongside taking precedence.
+const REF : &str = "$ref";
+const INCLUDE : &str = "$include";
+// A request which extends a base request file overrides only the fields which differ. Objects such as the
+// headers are merged, and anything else is replaced.
+const EXTENDS : &str = "extends";
+const BODY_FIELDS : [&str; 3] = ["body", "body_path", "body_template"];
+
+fn read(path : &Path) -> Result<String, String> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(content),
+        Err(error) => Err(format!("Failed to read the included file {:?}. {}", path, error)),
+    }
+}
+
+fn load(path : &Path, including : &mut Vec<PathBuf>) -> Result<Value, String> {
+    let canonical = match path.canonicalize() {
+        Ok(canonical) => canonical,
+        Err(error) => return Err(format!("Failed to open the included file {:?}. {}", path, error)),
+    };
+    if including.contains(&canonical) {
+        return Err(format!("The file {:?} includes or extends itself.", path));
+    }
+
+    let content = read(path)?;
+    if path.extension().is_none_or(|extension| extension != "json") {
+        return Ok(Value::String(content));
+    }
+
+    let value = match serde_json::from_str::<Value>(&crate::jsonc::strip(&content)) {
+        Ok(value) => value,
+        Err(error) => return Err(format!("Unable to deserialise data from included file {:?} at line {}, column {}.", path, error.line(), error.column())),
+    };
+
+    including.push(canonical);
+    let resolved = resolve_value(value, path, including).and_then(|value| extend(value, path, including));
+    including.pop();
+    resolved
+}
+
+fn merge(base : &mut Value, overrides : Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    },
+                }
+            }
+        },
+        (base, overrides) => *base = overrides,
+    }
+}
+
+fn extend(request : Value, file : &Path, including : &mut Vec<PathBuf>) -> Result<Value, String> {
+    let mut request = match request {
+        Value::Object(request) => request,
+        request => return Ok(request),
+    };
+    let base = match request.remove(EXTENDS) {
+        Some(Value::String(base)) => file.parent().unwrap_or(Path::new("")).join(base),
+        Some(_) => return Err(format!("extends must be the name of a request file. In {:?}.", file)),
+        None => return Ok(Value::Object(request)),
+    };
+
+    let mut base = match load(&base, including)? {
+        Value::Object(base) => base,
+        _ => return Err(format!("The file {:?} must hold a JSON object to be extended.", base)),
+    };
+    // A body of any kind replaces the body of the base, so that it isn't given two.
+    if BODY_FIELDS.iter().any(|field| request.contains_key(*field)) {
+        base.retain(|field, _| !BODY_FIELDS.contains(&field.as_str()));
+    }
+
+    let mut extended = Value::Object(base);
+    merge(&mut extended, Value::Object(request));
+    Ok(extended)
+}
+
+fn resolve_ref(reference : &str, file : &Path, including : &mut Vec<PathBuf>) -> Result<Value, String> {
+    let (target, pointer) = reference.split_once('#').unwrap_or((reference, ""));
+    let target = file.parent().unwrap_or(Path::new("")).join(target);
+    let value = load(&target, including)?;
+
+    match value.pointer(pointer) {
+        Some(fragment) => Ok(fragment.clone()),
+        None => Err(format!("There is nothing at #{} in the included file {:?}.", pointer, target)),
+    }
+}
+
+fn include(object : &mut Map<String, Value>, file : &Path, including : &mut Vec<PathBuf>) -> Result<(), String> {
+    let targets = match object.remove(INCLUDE) {
+        Some(Value::String(target)) => vec![target],
+        Some(Value::Array(targets)) => {
+            targets
+            .into_iter()
+            .map(|target| match target {
+                Value::String(target) => Ok(target),
+                _ => Err(String::from("Each file given to $include must be a string.")),
+            })
+            .collect::<Result<_, _>>()?
+        },
+        Some(_) => return Err(String::from("$include must be the name of a file, or a list of them.")),
+        None => return Ok(()),
+    };
+
+    // Later files take precedence over earlier ones, and the object itself over all of them.
+    let mut included = Map::new();
+    for target in targets {
+        let target = file.parent().unwrap_or(Path::new("")).join(target);
+        match load(&target, including)? {
+            Value::Object(fields) => included.extend(fields),
+            _ => return Err(format!("The file {:?} must hold a JSON object to be used with $include.", target)),
+        }
+    }
+    included.extend(std::mem::take(object));
+    *object = included;
+
+    Ok(())
+}
+
+fn resolve_value(value : Value, file : &Path, including : &mut Vec<PathBuf>) -> Result<Value, String> {
+    match value {
+        Value::Object(mut object) => {
+            if let Some(reference) = object.get(REF) {
+                return match (reference, object.len()) {
+                    (Value::String(reference), 1) => resolve_ref(reference, file, including),
+                    (Value::String(_), _) => Err(format!("An object with $ref cannot have other fields, use $include to add fields to an included object. In {:?}.", file)),
+                    _ => Err(format!("$ref must be the name of a file, with an optional #/pointer. In {:?}.", file)),
+                };
+            }
+
+            include(&mut object, file, including)?;
+            let resolved = object
+                .into_iter()
+                .map(|(key, value)| Ok((key, resolve_value(value, file, including)?)))
+                .collect::<Result<Map<_, _>, String>>()?;
+            Ok(Value::Object(resolved))
+        },
+        Value::Array(array) => Ok(Value::Array(array.into_iter().map(|value| resolve_value(value, file, including)).collect::<Result<_, _>>()?)),
+        value => Ok(value),
+    }
+}
+
+fn has_includes(value : &Value) -> bool {
+    match value {
+        Value::Object(object) => object.contains_key(REF) || object.contains_key(INCLUDE) || object.values().any(has_includes),
+        Value::Array(array) => array.iter().any(has_includes),
+        _ => false,
+    }
+}
+
+fn has_extends(request : &Value) -> bool {
+    request.get(EXTENDS).is_some()
+}
+
+// Gives the content of a file with everything it includes and extends filled in, and any comments taken
+// out. Content without any includes is otherwise given back as it was, as is content which isn't JSON,
+// so that errors in it point to the right line.
+fn resolve(content : String, path : &Path, collection : bool) -> Result<String, String> {
+    let content = crate::jsonc::strip(&content);
+    let value = match serde_json::from_str::<Value>(&content) {
+        Ok(value) => value,
+        Err(_) => return Ok(content),
+    };
+    let extends = match collection {
+        true => value.get("requests").and_then(Value::as_array).is_some_and(|requests| requests.iter().any(has_extends)),
+        false => has_extends(&value),
+    };
+    if !extends && !has_includes(&value) {
+        return Ok(content);
+    }
+
+    let mut including = path.canonicalize().into_iter().collect();
+    let mut resolved = resolve_value(value, path, &mut including)?;
+    match collection {
+        true => {
+            if let Some(Value::Array(requests)) = resolved.get_mut("requests") {
+                for request in requests {
+                    *request = extend(request.take(), path, &mut including)?;
+                }
+            }
+        },
+        false => resolved = extend(resolved, path, &mut including)?,
+    }
+
+    match serde_json::to_string_pretty(&resolved) {
+        Ok(serialized) => Ok(serialized),
+        Err(_) => panic!("Internal error, could not serialize JSON data for included files"),
+    }
+}
+
+pub fn resolve_request(content : String, path : &Path) -> Result<String, String> {
+    resolve(content, path, false)
+}
+
+pub fn resolve_collection(content : String, path : &Path) -> Result<String, String> {
+    resolve(content, path, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each test writes its files to a directory of its own.
+    fn directory(name : &str, files : &[(&str, &str)]) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("callsy-include-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&directory).unwrap();
+        for (file, content) in files {
+            std::fs::write(directory.join(file), content).unwrap();
+        }
+        directory
+    }
+
+    fn resolved(directory : &Path, file : &str) -> Result<Value, String> {
+        let path = directory.join(file);
+        let content = std::fs::read_to_string(&path).unwrap();
+        resolve_request(content, &path).map(|resolved| serde_json::from_str(&resolved).unwrap())
+    }
+
+    #[test]
+    fn refs_and_includes_are_filled_in() {
+        let directory = directory("refs", &[
+            ("request.json", r#"{ "$include" : "common.json", "headers" : { "$ref" : "headers.json#/json" }, "body" : { "$ref" : "body.txt" } }"#),
+            ("common.json", r#"{ "method" : "POST", "url" : "https://somedomain.com", /* shared */ }"#),
+            ("headers.json", r#"{ "json" : { "content-type" : "application/json" } }"#),
+            ("body.txt", "text"),
+        ]);
+
+        assert_eq!(resolved(&directory, "request.json").unwrap(), serde_json::json!({
+            "method" : "POST",
+            "url" : "https://somedomain.com",
+            "headers" : { "content-type" : "application/json" },
+            "body" : "text",
+        }));
+    }
+
+    #[test]
+    fn fields_given_alongside_include_take_precedence() {
+        let directory = directory("precedence", &[
+            ("request.json", r#"{ "$include" : ["a.json", "b.json"], "url" : "https://request.com" }"#),
+            ("a.json", r#"{ "method" : "GET", "url" : "https://a.com" }"#),
+            ("b.json", r#"{ "method" : "PUT" }"#),
+        ]);
+
+        assert_eq!(resolved(&directory, "request.json").unwrap(), serde_json::json!({ "method" : "PUT", "url" : "https://request.com" }));
+    }
+
+    #[test]
+    fn cycles_are_refused() {
+        let directory = directory("cycles", &[
+            ("request.json", r#"{ "headers" : { "$ref" : "a.json" } }"#),
+            ("a.json", r#"{ "$include" : "b.json" }"#),
+            ("b.json", r#"{ "x" : { "$ref" : "a.json" } }"#),
+            ("itself.json", r#"{ "headers" : { "$ref" : "itself.json" } }"#),
+        ]);
+
+        assert!(resolved(&directory, "request.json").unwrap_err().contains("includes or extends itself"));
+        assert!(resolved(&directory, "itself.json").unwrap_err().contains("includes or extends itself"));
+    }
+
+    #[test]
+    fn the_same_file_can_be_included_twice() {
+        let directory = directory("twice", &[
+            ("request.json", r#"{ "a" : { "$ref" : "shared.json" }, "b" : { "$ref" : "shared.json" } }"#),
+            ("shared.json", r#"{ "x" : 1 }"#),
+        ]);
+
+        assert_eq!(resolved(&directory, "request.json").unwrap(), serde_json::json!({ "a" : { "x" : 1 }, "b" : { "x" : 1 } }));
+    }
+
+    #[test]
+    fn extends_merges_objects_and_replaces_the_rest() {
+        let directory = directory("extends", &[
+            ("request.json", r#"{ "extends" : "base.json", "url" : "https://somedomain.com/b", "headers" : { "accept" : "text/plain" }, "body_path" : "b.json" }"#),
+            ("base.json", r#"{ "method" : "POST", "url" : "https://somedomain.com/a", "headers" : { "accept" : "application/json", "x-base" : "1" }, "body" : "a" }"#),
+        ]);
+
+        assert_eq!(resolved(&directory, "request.json").unwrap(), serde_json::json!({
+            "method" : "POST",
+            "url" : "https://somedomain.com/b",
+            "headers" : { "accept" : "text/plain", "x-base" : "1" },
+            "body_path" : "b.json",
+        }));
+    }
+
+    #[test]
+    fn extends_can_be_chained_but_not_cycled() {
+        let directory = directory("chain", &[
+            ("request.json", r#"{ "extends" : "middle.json", "url" : "https://somedomain.com" }"#),
+            ("middle.json", r#"{ "extends" : "base.json", "headers" : { "x-middle" : "1" } }"#),
+            ("base.json", r#"{ "method" : "GET", "headers" : { "x-base" : "1" } }"#),
+            ("a.json", r#"{ "extends" : "b.json" }"#),
+            ("b.json", r#"{ "extends" : "a.json" }"#),
+        ]);
+
+        assert_eq!(resolved(&directory, "request.json").unwrap(), serde_json::json!({
+            "method" : "GET",
+            "headers" : { "x-base" : "1", "x-middle" : "1" },
+            "url" : "https://somedomain.com",
+        }));
+        assert!(resolved(&directory, "a.json").unwrap_err().contains("includes or extends itself"));
+    }
+
+    #[test]
+    fn requests_of_a_collection_extend_relative_to_it() {
+        let directory = directory("collection", &[
+            ("collection.json", r#"{ "requests" : [{ "extends" : "base.json", "name" : "one" }, { "name" : "two" }] }"#),
+            ("base.json", r#"{ "method" : "GET", "url" : "https://somedomain.com" }"#),
+        ]);
+        let path = directory.join("collection.json");
+        let content = std::fs::read_to_string(&path).unwrap();
+        let resolved : Value = serde_json::from_str(&resolve_collection(content, &path).unwrap()).unwrap();
+
+        assert_eq!(resolved["requests"][0], serde_json::json!({ "method" : "GET", "url" : "https://somedomain.com", "name" : "one" }));
+        assert_eq!(resolved["requests"][1], serde_json::json!({ "name" : "two" }));
+    }
+}
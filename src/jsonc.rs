@@ -0,0 +1,102 @@
+// Request and collection files can have // and /* */ comments and trailing commas, as in JSONC. They are
+// blanked out with spaces rather than removed, so that errors in the rest of the file point to the right
+// line and column.
+pub fn strip(content : &str) -> String {
+    let mut bytes = content.as_bytes().to_vec();
+    let mut index = 0;
+    let mut in_string = false;
+    let mut trailing_comma = None;
+
+    while index < bytes.len() {
+        let byte = bytes[index];
+        if in_string {
+            match byte {
+                b'\\' => index += 1,
+                b'"' => in_string = false,
+                _ => {},
+            }
+            index += 1;
+            continue;
+        }
+
+        match (byte, bytes.get(index + 1)) {
+            (b'/', Some(b'/')) => {
+                let end = bytes[index..].iter().position(|byte| *byte == b'\n').map_or(bytes.len(), |position| index + position);
+                blank(&mut bytes[index..end]);
+                index = end;
+                continue;
+            },
+            (b'/', Some(b'*')) => {
+                let end = bytes[index + 2..].windows(2).position(|pair| pair == b"*/").map_or(bytes.len(), |position| index + 2 + position + 2);
+                blank(&mut bytes[index..end]);
+                index = end;
+                continue;
+            },
+            (b'"', _) => {
+                in_string = true;
+                trailing_comma = None;
+            },
+            (b',', _) => trailing_comma = Some(index),
+            (b'}' | b']', _) => {
+                if let Some(comma) = trailing_comma.take() {
+                    bytes[comma] = b' ';
+                }
+            },
+            (byte, _) if byte.is_ascii_whitespace() => {},
+            _ => trailing_comma = None,
+        }
+        index += 1;
+    }
+
+    match String::from_utf8(bytes) {
+        Ok(stripped) => stripped,
+        Err(_) => panic!("Internal error, could not strip comments from JSON"),
+    }
+}
+
+// Line breaks are kept so that the lines after a comment keep their numbers.
+fn blank(bytes : &mut [u8]) {
+    for byte in bytes {
+        if *byte != b'\n' && *byte != b'\r' {
+            *byte = b' ';
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content : &str) -> serde_json::Value {
+        serde_json::from_str(&strip(content)).unwrap()
+    }
+
+    #[test]
+    fn comments_are_blanked_keeping_lines() {
+        let stripped = strip("{\n    // a comment\n    \"a\" : 1 /* another\n    one */\n}");
+
+        assert_eq!(stripped.lines().count(), 5);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&stripped).unwrap(), serde_json::json!({ "a" : 1 }));
+    }
+
+    #[test]
+    fn comment_markers_in_strings_are_kept() {
+        let json = parse(r#"{ "url" : "https://somedomain.com/a", "glob" : "/*.json", "quote" : "\"//\"" }"#);
+
+        assert_eq!(json["url"], "https://somedomain.com/a");
+        assert_eq!(json["glob"], "/*.json");
+        assert_eq!(json["quote"], "\"//\"");
+    }
+
+    #[test]
+    fn unterminated_block_comment_runs_to_the_end() {
+        assert_eq!(strip("[1] /* never closed\n,2]"), "[1]                \n   ");
+    }
+
+    #[test]
+    fn trailing_commas_are_removed() {
+        assert_eq!(parse("{ \"a\" : [1, 2,], \"b\" : { \"c\" : 3, }, }"), serde_json::json!({ "a" : [1, 2], "b" : { "c" : 3 } }));
+        assert_eq!(parse("[1, // last\n]"), serde_json::json!([1]));
+        assert_eq!(parse(r#"[",]"]"#), serde_json::json!([",]"]));
+    }
+}
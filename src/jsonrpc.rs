@@ -0,0 +1,106 @@
+use serde_json::{Map, Value};
+
+// JSON-RPC 2.0, where the body of the request gives the method and params of a call (or an array of calls
+// for a batch), and the envelope is filled in around them.
+const FIELDS : [&str; 4] = ["method", "params", "id", "notification"];
+
+// Calls are numbered from 1 in the order they are given, unless they have an id of their own.
+fn envelope(call : Value, next_id : &mut u64) -> Result<Value, String> {
+    let mut call = match call {
+        Value::Object(call) => call,
+        _ => return Err(String::from("Each JSON-RPC call must be an object with a method and params.")),
+    };
+    if let Some(field) = call.keys().find(|field| !FIELDS.contains(&field.as_str())) {
+        return Err(format!("The JSON-RPC call has the unknown field {}.", field));
+    }
+
+    let method = match call.remove("method") {
+        Some(Value::String(method)) => method,
+        _ => return Err(String::from("Each JSON-RPC call must have a method, which is a string.")),
+    };
+    let params = match call.remove("params") {
+        Some(params @ (Value::Array(_) | Value::Object(_))) => Some(params),
+        Some(_) => return Err(format!("The params of the JSON-RPC call {} must be an array or an object.", method)),
+        None => None,
+    };
+    // Notifications have no id, and the server doesn't respond to them.
+    let notification = match call.remove("notification") {
+        Some(Value::Bool(notification)) => notification,
+        Some(_) => return Err(format!("The notification field of the JSON-RPC call {} must be true or false.", method)),
+        None => false,
+    };
+
+    let mut envelope = Map::new();
+    envelope.insert(String::from("jsonrpc"), Value::from("2.0"));
+    envelope.insert(String::from("method"), Value::from(method.as_str()));
+    if let Some(params) = params {
+        envelope.insert(String::from("params"), params);
+    }
+    match (notification, call.remove("id")) {
+        (true, Some(_)) => return Err(format!("The JSON-RPC call {} cannot be a notification and have an id.", method)),
+        (true, None) => {},
+        (false, Some(id)) => {
+            envelope.insert(String::from("id"), id);
+        },
+        (false, None) => {
+            *next_id += 1;
+            envelope.insert(String::from("id"), Value::from(*next_id));
+        },
+    }
+
+    Ok(Value::Object(envelope))
+}
+
+pub fn encode_request(body : &str) -> Result<Vec<u8>, String> {
+    let calls = match serde_json::from_str::<Value>(body) {
+        Ok(calls) => calls,
+        Err(error) => return Err(format!("The body of a JSON-RPC request must be JSON. {}", error)),
+    };
+
+    let mut next_id = 0;
+    let request = match calls {
+        Value::Array(calls) if calls.is_empty() => return Err(String::from("A batch of JSON-RPC calls cannot be empty.")),
+        Value::Array(calls) => Value::Array(calls.into_iter().map(|call| envelope(call, &mut next_id)).collect::<Result<_, _>>()?),
+        call => envelope(call, &mut next_id)?,
+    };
+
+    match serde_json::to_vec(&request) {
+        Ok(serialized) => Ok(serialized),
+        Err(_) => panic!("Internal error, could not serialize JSON data for JSON-RPC request"),
+    }
+}
+
+fn unwrap(response : &Value) -> Result<Value, String> {
+    let mut unwrapped = Map::new();
+    unwrapped.insert(String::from("id"), response.get("id").cloned().unwrap_or(Value::Null));
+
+    match (response.get("result"), response.get("error")) {
+        (Some(result), None) => unwrapped.insert(String::from("result"), result.clone()),
+        (None, Some(error)) => unwrapped.insert(String::from("error"), error.clone()),
+        _ => return Err(String::from("A JSON-RPC response must have either a result or an error.")),
+    };
+
+    Ok(Value::Object(unwrapped))
+}
+
+// The result or error of each call, with its id. The responses to a batch can come in any order, so they
+// are put back in the order of the calls, as far as their ids allow.
+pub fn decode_response(body : &str) -> Result<Option<Value>, String> {
+    if body.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let response = match serde_json::from_str::<Value>(body) {
+        Ok(response) => response,
+        Err(error) => return Err(format!("The JSON-RPC response is not JSON. {}", error)),
+    };
+
+    match response {
+        Value::Array(responses) => {
+            let mut unwrapped = responses.iter().map(unwrap).collect::<Result<Vec<_>, _>>()?;
+            unwrapped.sort_by_key(|response| response.get("id").and_then(Value::as_u64).unwrap_or(u64::MAX));
+            Ok(Some(Value::Array(unwrapped)))
+        },
+        response => Ok(Some(unwrap(&response)?)),
+    }
+}
@@ -0,0 +1,28 @@
+use crate::report::{escape, Outcome, Report};
+
+// Formats the report as JUnit XML, so that CI systems can show the results of a run.
+pub fn to_xml(report : &Report) -> String {
+    let failures = report.failures();
+    let errors = report.errors();
+    let time = report.time();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!("<testsuites tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">\n", report.cases.len(), failures, errors, time));
+    xml.push_str(&format!("  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">\n", escape(&report.suite), report.cases.len(), failures, errors, time));
+
+    for case in &report.cases {
+        let opening = format!("    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"", escape(&case.name), escape(&report.suite), case.time.as_secs_f64());
+        match &case.outcome {
+            Outcome::Passed => xml.push_str(&format!("{}/>\n", opening)),
+            Outcome::Failed(message) => {
+                xml.push_str(&format!("{}>\n      <failure message=\"{}\"/>\n    </testcase>\n", opening, escape(message)));
+            },
+            Outcome::Errored { category, message } => {
+                xml.push_str(&format!("{}>\n      <error type=\"{}\" message=\"{}\"/>\n    </testcase>\n", opening, category, escape(message)));
+            },
+        }
+    }
+
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
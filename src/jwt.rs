@@ -0,0 +1,105 @@
+use std::path::PathBuf;
+
+use base64::Engine;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+
+// The contents of a JWT, which are decoded without verifying its signature.
+#[derive(Serialize)]
+pub struct Decoded {
+    header : serde_json::Value,
+    claims : serde_json::Value,
+    // Seconds until the token expires, which is negative once it has.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_in : Option<i64>,
+}
+
+fn unix_time() -> u64 {
+    match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        Ok(now) => now.as_secs(),
+        Err(_) => 0,
+    }
+}
+
+fn decode_part(part : &str, name : &str) -> Result<serde_json::Value, String> {
+    let bytes = match base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(part.trim_end_matches('=')) {
+        Ok(bytes) => bytes,
+        Err(error) => return Err(format!("Unable to decode the {} of the JWT. {}", name, error)),
+    };
+
+    match serde_json::from_slice(&bytes) {
+        Ok(value) => Ok(value),
+        Err(error) => Err(format!("Unable to parse the {} of the JWT. {}", name, error)),
+    }
+}
+
+pub fn decode(token : &str) -> Result<Decoded, String> {
+    let token = token.trim();
+    let token = token.strip_prefix("Bearer ").unwrap_or(token);
+
+    let parts = token.split('.').collect::<Vec<_>>();
+    if parts.len() != 3 {
+        return Err(String::from("A JWT must have three parts separated by dots."));
+    }
+
+    let header = decode_part(parts[0], "header")?;
+    let claims = decode_part(parts[1], "claims")?;
+    let expires_in = claims
+        .get("exp")
+        .and_then(|exp| exp.as_i64())
+        .map(|exp| exp - unix_time() as i64);
+
+    Ok(Decoded {
+        header,
+        claims,
+        expires_in,
+    })
+}
+
+// A JWT which is built and signed locally for each request, rather than read from a token file or command.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Jwt {
+    algorithm : Option<String>,
+    key_file : Option<PathBuf>,
+    key_command : Option<String>,
+    key_id : Option<String>,
+    expires_in : Option<u64>,
+    #[serde(default)]
+    claims : serde_json::Map<String, serde_json::Value>,
+}
+
+impl Jwt {
+    pub fn token(&self) -> Result<String, String> {
+        let key = crate::config::read_secret(&self.key_file, &self.key_command, "key", "jwt")?;
+
+        let (algorithm, encoding_key) = match self.algorithm.as_deref().unwrap_or("HS256") {
+            "HS256" => (Algorithm::HS256, Ok(EncodingKey::from_secret(key.as_bytes()))),
+            "RS256" => (Algorithm::RS256, EncodingKey::from_rsa_pem(key.as_bytes())),
+            "ES256" => (Algorithm::ES256, EncodingKey::from_ec_pem(key.as_bytes())),
+            algorithm => return Err(format!("The JWT algorithm {} is not supported, use HS256, RS256 or ES256.", algorithm)),
+        };
+
+        let encoding_key = match encoding_key {
+            Ok(encoding_key) => encoding_key,
+            Err(error) => return Err(format!("Unable to parse the JWT key. {}", error)),
+        };
+
+        let now = unix_time();
+
+        // The issued at and expiry times are filled in, unless the claims already include them.
+        let mut claims = self.claims.clone();
+        claims.entry("iat").or_insert_with(|| serde_json::Value::from(now));
+        claims.entry("exp").or_insert_with(|| serde_json::Value::from(now + self.expires_in.unwrap_or(3600)));
+
+        let mut header = Header::new(algorithm);
+        header.kid = self.key_id.clone();
+
+        let token = match jsonwebtoken::encode(&header, &claims, &encoding_key) {
+            Ok(token) => token,
+            Err(error) => return Err(format!("Failed to sign the JWT. {}", error)),
+        };
+
+        crate::masking::register(&token);
+        Ok(token)
+    }
+}
@@ -0,0 +1,200 @@
+use std::ffi::c_void;
+use std::path::PathBuf;
+
+use base64::Engine;
+
+// Negotiate (SPNEGO) authentication with a Kerberos ticket from the ticket cache, such as one from kinit.
+// The GSSAPI library is loaded when it is first needed, so that callsy builds and runs without it.
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Kerberos {
+    // The service of the principal the ticket is for, which is HTTP for almost every web server.
+    service : Option<String>,
+    // Lets the server act as the user, for services which call others on their behalf.
+    #[serde(default)]
+    delegate : bool,
+    // The GSSAPI library to load, where it isn't one of the usual MIT or Heimdal ones.
+    library : Option<PathBuf>,
+}
+
+const LIBRARIES : [&str; 4] = ["libgssapi_krb5.so.2", "libgssapi_krb5.so", "libgssapi.so.3", "libgssapi_krb5.dylib"];
+
+// The object identifiers of host based service names (1.2.840.113554.1.2.1.4) and SPNEGO (1.3.6.1.5.5.2).
+const HOSTBASED_SERVICE : [u8; 10] = [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x12, 0x01, 0x02, 0x01, 0x04];
+const SPNEGO : [u8; 6] = [0x2b, 0x06, 0x01, 0x05, 0x05, 0x02];
+
+const MUTUAL_FLAG : u32 = 2;
+const DELEGATE_FLAG : u32 = 1;
+const GSS_CODE : i32 = 1;
+const MECH_CODE : i32 = 2;
+
+#[repr(C)]
+struct Buffer {
+    length : usize,
+    value : *mut c_void,
+}
+
+#[repr(C)]
+struct Oid {
+    length : u32,
+    elements : *mut c_void,
+}
+
+type ImportName = unsafe extern "C" fn(*mut u32, *mut Buffer, *mut Oid, *mut *mut c_void) -> u32;
+type InitSecContext = unsafe extern "C" fn(
+    *mut u32, *mut c_void, *mut *mut c_void, *mut c_void, *mut Oid, u32, u32,
+    *mut c_void, *mut Buffer, *mut *mut Oid, *mut Buffer, *mut u32, *mut u32,
+) -> u32;
+type ReleaseBuffer = unsafe extern "C" fn(*mut u32, *mut Buffer) -> u32;
+type ReleaseName = unsafe extern "C" fn(*mut u32, *mut *mut c_void) -> u32;
+type DeleteSecContext = unsafe extern "C" fn(*mut u32, *mut *mut c_void, *mut Buffer) -> u32;
+type DisplayStatus = unsafe extern "C" fn(*mut u32, u32, i32, *mut Oid, *mut u32, *mut Buffer) -> u32;
+
+struct Gssapi {
+    library : libloading::Library,
+}
+
+impl Gssapi {
+    fn load(path : Option<&PathBuf>) -> Result<Gssapi, String> {
+        // Loading a library runs its initialisers, which for GSSAPI libraries is expected to be safe.
+        let loaded = match path {
+            Some(path) => unsafe { libloading::Library::new(path) }.map_err(|error| format!("Failed to load the GSSAPI library {:?}. {}", path, error)),
+            None => {
+                LIBRARIES
+                .iter()
+                .find_map(|name| unsafe { libloading::Library::new(name) }.ok())
+                .ok_or_else(|| String::from("Failed to load a GSSAPI library for Kerberos authentication. Install MIT Kerberos or Heimdal, or set library in the kerberos config."))
+            },
+        };
+
+        loaded.map(|library| Gssapi { library })
+    }
+
+    fn function<T>(&self, name : &[u8]) -> Result<libloading::Symbol<'_, T>, String> {
+        // The types of the functions looked up match their declarations in gssapi.h.
+        match unsafe { self.library.get::<T>(name) } {
+            Ok(function) => Ok(function),
+            Err(error) => Err(format!("The GSSAPI library is missing {}. {}", String::from_utf8_lossy(name), error)),
+        }
+    }
+
+    // Collects the messages for a status code, of which there can be several.
+    fn describe(&self, status : u32, status_type : i32) -> String {
+        let display_status = match self.function::<DisplayStatus>(b"gss_display_status") {
+            Ok(display_status) => display_status,
+            Err(_) => return format!("status {:#x}", status),
+        };
+        let release_buffer = match self.function::<ReleaseBuffer>(b"gss_release_buffer") {
+            Ok(release_buffer) => release_buffer,
+            Err(_) => return format!("status {:#x}", status),
+        };
+
+        let mut messages = Vec::new();
+        let mut message_context = 0;
+        loop {
+            let mut minor = 0;
+            let mut buffer = Buffer { length : 0, value : std::ptr::null_mut() };
+            let major = unsafe { display_status(&mut minor, status, status_type, std::ptr::null_mut(), &mut message_context, &mut buffer) };
+            if major != 0 {
+                break;
+            }
+
+            if !buffer.value.is_null() {
+                let bytes = unsafe { std::slice::from_raw_parts(buffer.value as *const u8, buffer.length) };
+                // Some libraries count the terminating null in the length of the message.
+                let message = String::from_utf8_lossy(bytes).trim_matches(|character : char| character == '\0' || character.is_whitespace()).to_string();
+                if !message.is_empty() {
+                    messages.push(message);
+                }
+                unsafe { release_buffer(&mut minor, &mut buffer) };
+            }
+
+            if message_context == 0 {
+                break;
+            }
+        }
+
+        messages.join(" ")
+    }
+
+    // The minor status is specific to the mechanism, such as Kerberos, and usually says the most.
+    fn describe_error(&self, major : u32, minor : u32) -> String {
+        let mut messages = vec![self.describe(major, GSS_CODE)];
+        if minor != 0 {
+            messages.push(self.describe(minor, MECH_CODE));
+        }
+
+        messages.retain(|message| !message.is_empty());
+        format!("{}.", messages.join(". "))
+    }
+
+    // Produces the first token of a security context with the service, which is all HTTP needs.
+    fn initial_token(&self, principal : &str, delegate : bool) -> Result<Vec<u8>, String> {
+        let import_name = self.function::<ImportName>(b"gss_import_name")?;
+        let init_sec_context = self.function::<InitSecContext>(b"gss_init_sec_context")?;
+        let release_buffer = self.function::<ReleaseBuffer>(b"gss_release_buffer")?;
+        let release_name = self.function::<ReleaseName>(b"gss_release_name")?;
+        let delete_sec_context = self.function::<DeleteSecContext>(b"gss_delete_sec_context")?;
+
+        let mut minor = 0;
+        let mut name_buffer = Buffer { length : principal.len(), value : principal.as_ptr() as *mut c_void };
+        let mut name_type = Oid { length : HOSTBASED_SERVICE.len() as u32, elements : HOSTBASED_SERVICE.as_ptr() as *mut c_void };
+        let mut name = std::ptr::null_mut();
+
+        let major = unsafe { import_name(&mut minor, &mut name_buffer, &mut name_type, &mut name) };
+        if major != 0 {
+            return Err(format!("Failed to import the Kerberos principal {}. {}", principal, self.describe_error(major, minor)));
+        }
+
+        let mut mechanism = Oid { length : SPNEGO.len() as u32, elements : SPNEGO.as_ptr() as *mut c_void };
+        let mut context = std::ptr::null_mut();
+        let mut output = Buffer { length : 0, value : std::ptr::null_mut() };
+        let flags = if delegate { MUTUAL_FLAG | DELEGATE_FLAG } else { MUTUAL_FLAG };
+
+        let major = unsafe {
+            init_sec_context(
+                &mut minor, std::ptr::null_mut(), &mut context, name, &mut mechanism, flags, 0,
+                std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::null_mut(), &mut output, std::ptr::null_mut(), std::ptr::null_mut(),
+            )
+        };
+
+        // A major status is an error unless it is complete (0), or only asks for the server's reply (1).
+        let result = if major & !1 != 0 {
+            Err(format!("Failed to get a Kerberos ticket for {}. {}", principal, self.describe_error(major, minor)))
+        }
+        else if output.value.is_null() {
+            Err(format!("GSSAPI produced no token for {}.", principal))
+        }
+        else {
+            Ok(unsafe { std::slice::from_raw_parts(output.value as *const u8, output.length) }.to_vec())
+        };
+
+        let mut cleanup_minor = 0;
+        unsafe {
+            release_buffer(&mut cleanup_minor, &mut output);
+            release_name(&mut cleanup_minor, &mut name);
+            if !context.is_null() {
+                delete_sec_context(&mut cleanup_minor, &mut context, std::ptr::null_mut());
+            }
+        }
+
+        result
+    }
+}
+
+impl Kerberos {
+    pub fn token(&self, url : &reqwest::Url) -> Result<String, String> {
+        let host = match url.host_str() {
+            Some(host) => host,
+            None => return Err(String::from("Cannot use Kerberos authentication as the URL has no host.")),
+        };
+        let principal = format!("{}@{}", self.service.as_deref().unwrap_or("HTTP"), host);
+
+        let gssapi = Gssapi::load(self.library.as_ref())?;
+        let token = base64::engine::general_purpose::STANDARD.encode(gssapi.initial_token(&principal, self.delegate)?);
+        tracing::debug!(%principal, "Created Kerberos token");
+
+        crate::masking::register(&token);
+        Ok(token)
+    }
+}
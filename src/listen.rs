@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use tokio::sync::Notify;
+
+use crate::error::CallsyError;
+
+// A request received by the listener, written with the same layout as the output of a response.
+#[derive(Serialize)]
+struct ReceivedRequest {
+    method : String,
+    url : String,
+    headers : HashMap<String, Vec<String>>,
+    http_version : String,
+    remote_address : std::net::SocketAddr,
+    received : String,
+    // Seconds since the listener started.
+    elapsed : f64,
+    body : String,
+}
+
+struct Listener {
+    directory : PathBuf,
+    count : Option<usize>,
+    received : AtomicUsize,
+    started : Instant,
+    done : Notify,
+}
+
+fn request_path(directory : &std::path::Path, number : usize) -> PathBuf {
+    directory.join(format!("request-{}.json", number))
+}
+
+impl Listener {
+    fn write(&self, number : usize, request : &ReceivedRequest) -> Result<PathBuf, CallsyError> {
+        let path = request_path(&self.directory, number);
+
+        let serialized = match serde_json::to_string(request) {
+            Ok(serialized) => serialized,
+            Err(_) => panic!("Internal error, could not serialize JSON data for received request"),
+        };
+
+        crate::processing::write_atomically(&path, serialized.as_bytes(), false)?;
+        Ok(path)
+    }
+}
+
+// Received requests are numbered from 1, so any request files already in the directory would be
+// overwritten, which is checked before listening rather than as requests arrive.
+fn check_existing_files(directory : &std::path::Path, count : Option<usize>) -> Result<(), CallsyError> {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(source) => return Err(CallsyError::Io { message : format!("Failed to read the directory {:?}. {}", directory, source), source }),
+    };
+
+    let mut numbers : Vec<usize> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.strip_prefix("request-")?.strip_suffix(".json")?.parse().ok())
+        .filter(|number| count.is_none_or(|count| *number <= count))
+        .collect();
+    numbers.sort_unstable();
+
+    for number in numbers {
+        crate::processing::check_output_file(&request_path(directory, number))?;
+    }
+
+    Ok(())
+}
+
+async fn receive(listener : Arc<Listener>, remote_address : std::net::SocketAddr, request : Request<Body>) -> Result<Response<Body>, Infallible> {
+    let elapsed = listener.started.elapsed().as_secs_f64();
+    let received = crate::processing::timestamp(std::time::SystemTime::now());
+    let (parts, body) = request.into_parts();
+
+    let mut headers = HashMap::new();
+    for (name, value) in parts.headers.iter() {
+        headers
+        .entry(String::from(name.as_str()))
+        .or_insert_with(Vec::new)
+        .push(String::from(value.to_str().unwrap_or_default()));
+    }
+
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => String::from_utf8_lossy(&body).into_owned(),
+        Err(error) => {
+            tracing::warn!(method = %parts.method, uri = %parts.uri, %error, "Failed to read the body of a received request");
+            return Ok(Response::builder().status(StatusCode::BAD_REQUEST).body(Body::empty()).unwrap_or_default());
+        },
+    };
+
+    let received_request = ReceivedRequest {
+        method : parts.method.to_string(),
+        url : parts.uri.to_string(),
+        headers,
+        http_version : format!("{:?}", parts.version),
+        remote_address,
+        received,
+        elapsed,
+        body,
+    };
+
+    let number = listener.received.fetch_add(1, Ordering::SeqCst) + 1;
+    match listener.write(number, &received_request) {
+        Ok(path) => println!("Received {} {}, written to {:?}", received_request.method, received_request.url, path),
+        Err(error) => tracing::error!("{}", error),
+    }
+
+    if listener.count == Some(number) {
+        listener.done.notify_one();
+    }
+
+    Ok(Response::new(Body::empty()))
+}
+
+// Binds the listener straight away, so that requests which trigger a callback can be sent once this
+// returns. The returned future runs until the given number of requests have been received, if any.
+pub fn bind(address : Option<std::net::IpAddr>, port : u16, directory : PathBuf, count : Option<usize>) -> Result<impl Future<Output = Result<(), CallsyError>>, CallsyError> {
+    if count == Some(0) {
+        return Err(CallsyError::Usage(String::from("The number of requests to listen for must be at least 1.")));
+    }
+
+    if !directory.is_dir() {
+        return Err(CallsyError::Usage(format!("The directory {:?} to write received requests to does not exist.", directory)));
+    }
+
+    check_existing_files(&directory, count)?;
+
+    // Only local requests are received unless another address is given.
+    let address = std::net::SocketAddr::new(address.unwrap_or(std::net::IpAddr::from([127, 0, 0, 1])), port);
+    let listener = Arc::new(Listener {
+        directory,
+        count,
+        received : AtomicUsize::new(0),
+        started : Instant::now(),
+        done : Notify::new(),
+    });
+
+    let service_listener = listener.clone();
+    let make_service = make_service_fn(move |connection : &AddrStream| {
+        let listener = service_listener.clone();
+        let remote_address = connection.remote_addr();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| receive(listener.clone(), remote_address, request)))
+        }
+    });
+
+    let server = match Server::try_bind(&address) {
+        Ok(builder) => builder.serve(make_service),
+        Err(error) => return Err(CallsyError::Usage(format!("Unable to listen on {}. {}", address, error))),
+    };
+
+    println!("Listening for requests on http://{}", address);
+
+    let server = server.with_graceful_shutdown(async move { listener.done.notified().await });
+
+    Ok(async move {
+        match server.await {
+            Ok(()) => Ok(()),
+            Err(error) => Err(CallsyError::Usage(format!("The listener stopped unexpectedly. {}", error))),
+        }
+    })
+}
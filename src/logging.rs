@@ -0,0 +1,97 @@
+use std::io::{IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::EnvFilter;
+
+use crate::error::CallsyError;
+
+// Writes log lines to stderr with any secrets masked, as they can include header values and URLs.
+struct MaskingWriter;
+
+impl Write for MaskingWriter {
+    fn write(&mut self, buffer : &[u8]) -> std::io::Result<usize> {
+        let masked = crate::masking::mask(&String::from_utf8_lossy(buffer));
+        std::io::stderr().write_all(masked.as_bytes())?;
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stderr().flush()
+    }
+}
+
+// Writes the bytes read and written on each connection to the wire dump file, masking secrets unless
+// they were asked to be shown.
+#[derive(Clone)]
+struct WireWriter {
+    file : Arc<Mutex<std::fs::File>>,
+    show_secrets : bool,
+}
+
+impl Write for WireWriter {
+    fn write(&mut self, buffer : &[u8]) -> std::io::Result<usize> {
+        let text = String::from_utf8_lossy(buffer);
+        let text = if self.show_secrets { text.into_owned() } else { crate::masking::mask(&text) };
+
+        let mut file = match self.file.lock() {
+            Ok(file) => file,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        file.write_all(text.as_bytes())?;
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.file.lock() {
+            Ok(mut file) => file.flush(),
+            Err(poisoned) => poisoned.into_inner().flush(),
+        }
+    }
+}
+
+// Logs from callsy are shown at the given level, and otherwise the filter comes from RUST_LOG, with
+// only warnings shown if it isn't set either. With a wire dump file, the bytes of each connection which
+// the HTTP client logs are written there instead.
+pub fn init(level : Option<&str>, wire_dump : Option<&std::path::PathBuf>, show_secrets : bool) -> Result<(), CallsyError> {
+    let filter = match level {
+        Some(level) => EnvFilter::new(format!("warn,callsy={}", level)),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")),
+    };
+
+    let console = tracing_subscriber::fmt::layer()
+        .with_writer(|| MaskingWriter)
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_filter(filter);
+
+    let wire = match wire_dump {
+        Some(path) => {
+            let file = match std::fs::File::create(path) {
+                Ok(file) => file,
+                Err(source) => return Err(CallsyError::Io { message : format!("Failed to create wire dump file {:?}. {}", path, source), source }),
+            };
+            let writer = WireWriter {
+                file : Arc::new(Mutex::new(file)),
+                show_secrets,
+            };
+
+            Some(
+                tracing_subscriber::fmt::layer()
+                .with_writer(move || writer.clone())
+                .with_ansi(false)
+                .with_target(false)
+                .with_level(false)
+                .with_filter(Targets::new().with_target("reqwest::connect::verbose", tracing::Level::TRACE))
+            )
+        },
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(console)
+        .with(wire)
+        .init();
+
+    Ok(())
+}
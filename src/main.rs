@@ -1,4 +1,52 @@
 mod processing;
+mod config;
+mod command;
+mod autocomplete;
+mod variables;
+mod collection;
+mod include;
+mod jsonc;
+mod schema;
+mod secrets;
+mod vault;
+mod masking;
+mod template;
+mod dataset;
+mod naming;
+mod error;
+mod dns;
+mod certificate;
+mod tls;
+mod connection;
+mod mock;
+mod cassette;
+mod jwt;
+mod tokens;
+mod azure;
+mod google;
+mod github;
+mod kerberos;
+mod pagination;
+mod grpc;
+mod jsonrpc;
+mod proto;
+mod msgpack;
+mod cbor;
+mod negotiation;
+mod listen;
+mod tui;
+mod repl;
+mod report;
+mod junit;
+mod html;
+mod metrics;
+mod trace;
+mod logging;
+mod history;
+mod schedule;
+mod serve;
+mod compare;
+mod markup;
 
 extern crate serde;
 #[macro_use]
@@ -10,8 +58,22 @@ use clap::Parser;
 #[tokio::main]
 async fn main() {
     let args = crate::processing::Arguments::parse();
+    let json_errors = args.json_errors();
+    let result = match crate::logging::init(args.log_level(), args.trace_wire(), args.show_secrets()) {
+        Ok(()) => crate::processing::respond(args).await,
+        Err(error) => Err(error),
+    };
 
-    if let Err(message) = crate::processing::respond(args).await {
-        println!("Error: {}", message);
+    if let Err(error) = result {
+        if json_errors {
+            match serde_json::to_string(&error) {
+                Ok(serialized) => eprintln!("{}", crate::masking::mask(&serialized)),
+                Err(_) => panic!("Internal error, could not serialize JSON data for error"),
+            }
+        }
+        else {
+            println!("Error: {}", crate::masking::mask(&error.to_string()));
+        }
+        std::process::exit(error.exit_code());
     }
 }
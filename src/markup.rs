@@ -0,0 +1,320 @@
+// Elements of HTML which never have a closing tag, so don't increase the indentation.
+const VOID_ELEMENTS : [&str; 14] = ["area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr"];
+
+// Elements whose content is kept exactly as it is, as whitespace in it matters or it isn't markup.
+const RAW_ELEMENTS : [&str; 4] = ["script", "style", "pre", "textarea"];
+
+// The index of the > which ends the tag at the start of the markup, skipping any in quoted attributes.
+fn tag_end(markup : &str) -> Option<usize> {
+    let mut quote = None;
+
+    for (index, character) in markup.char_indices() {
+        match (quote, character) {
+            (None, '"' | '\'') => quote = Some(character),
+            (Some(open), character) if character == open => quote = None,
+            (None, '>') => return Some(index),
+            _ => {},
+        }
+    }
+
+    None
+}
+
+fn tag_name(tag : &str) -> String {
+    tag
+    .trim_start_matches(['<', '/'])
+    .split(|character : char| character.is_whitespace() || character == '/' || character == '>')
+    .next()
+    .unwrap_or_default()
+    .to_lowercase()
+}
+
+// Finds where the closing tag of the named element starts, ignoring case as HTML does.
+fn find_closing_tag(markup : &str, name : &str) -> Option<usize> {
+    markup.to_ascii_lowercase().find(&format!("</{}", name))
+}
+
+fn push_line(indented : &mut String, depth : usize, line : &str) {
+    indented.push_str(&"  ".repeat(depth));
+    indented.push_str(line);
+    indented.push('\n');
+}
+
+// Indents XML or HTML with each tag on a line of its own, nested by two spaces. Elements holding only
+// a little text stay on one line, such as <title>Home</title>, and the content of script, style, pre
+// and textarea elements is kept as it was.
+pub fn indent(markup : &str) -> String {
+    let mut indented = String::new();
+    let mut depth = 0;
+    let mut rest = markup.trim();
+
+    while !rest.is_empty() {
+        if rest.starts_with("<!--") {
+            let end = rest.find("-->").map(|end| end + 3).unwrap_or(rest.len());
+            push_line(&mut indented, depth, &rest[..end]);
+            rest = rest[end..].trim_start();
+            continue;
+        }
+
+        if !rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = rest[..end].trim();
+            if !text.is_empty() {
+                push_line(&mut indented, depth, text);
+            }
+            rest = &rest[end..];
+            continue;
+        }
+
+        let end = match tag_end(rest) {
+            Some(end) => end,
+            None => {
+                push_line(&mut indented, depth, rest);
+                break;
+            },
+        };
+        let tag = &rest[..=end];
+        let after_tag = &rest[end + 1..];
+        rest = after_tag.trim_start();
+        let name = tag_name(tag);
+
+        if tag.starts_with("</") {
+            depth = depth.saturating_sub(1);
+            push_line(&mut indented, depth, tag);
+        }
+        else if tag.starts_with("<!") || tag.starts_with("<?") || tag.ends_with("/>") || VOID_ELEMENTS.contains(&name.as_str()) {
+            push_line(&mut indented, depth, tag);
+        }
+        else if RAW_ELEMENTS.contains(&name.as_str()) {
+            let content_end = find_closing_tag(after_tag, &name).unwrap_or(after_tag.len());
+            let closing_end = tag_end(&after_tag[content_end..]).map(|end| content_end + end + 1).unwrap_or(after_tag.len());
+            push_line(&mut indented, depth, &format!("{}{}", tag, &after_tag[..closing_end]));
+            rest = after_tag[closing_end..].trim_start();
+        }
+        else {
+            // An element with nothing but text in it is kept on one line.
+            let text_end = rest.find('<').unwrap_or(rest.len());
+            let after_text = &rest[text_end..];
+            let closes = after_text.starts_with("</") && tag_name(after_text) == name;
+
+            match tag_end(after_text) {
+                Some(closing_end) if closes => {
+                    push_line(&mut indented, depth, &format!("{}{}{}", tag, rest[..text_end].trim(), &after_text[..=closing_end]));
+                    rest = after_text[closing_end + 1..].trim_start();
+                },
+                _ => {
+                    push_line(&mut indented, depth, tag);
+                    depth += 1;
+                },
+            }
+        }
+    }
+
+    indented
+}
+
+// Elements whose content isn't shown when HTML is rendered as text.
+const HIDDEN_ELEMENTS : [&str; 4] = ["script", "style", "noscript", "template"];
+
+// Elements which start on a line of their own when HTML is rendered as text.
+const BLOCK_ELEMENTS : [&str; 21] = [
+    "address", "article", "aside", "blockquote", "div", "dl", "footer", "form", "header", "hr", "main", "nav",
+    "ol", "p", "pre", "section", "table", "tbody", "thead", "title", "ul",
+];
+
+fn decode_entity(entity : &str) -> Option<String> {
+    let character = match entity {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        _ => {
+            let code = match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => entity.strip_prefix('#')?.parse::<u32>().ok()?,
+            };
+            char::from_u32(code)?
+        },
+    };
+
+    Some(String::from(character))
+}
+
+// Replaces character references such as &amp; and &#39;, leaving any which aren't recognised.
+fn decode_entities(text : &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded_entity = rest
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| decode_entity(&rest[1..end]).map(|entity| (entity, end)));
+
+        match decoded_entity {
+            Some((entity, end)) => {
+                decoded.push_str(&entity);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+fn attribute(tag : &str, name : &str) -> Option<String> {
+    let lowercase = tag.to_ascii_lowercase();
+    let start = lowercase.find(&format!(" {}=", name))? + name.len() + 2;
+    let value = &tag[start..];
+
+    let value = match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next()?,
+        _ => value.split(|character : char| character.is_whitespace() || character == '>').next()?,
+    };
+
+    Some(decode_entities(value))
+}
+
+fn start_line(text : &mut String) {
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+fn start_block(text : &mut String) {
+    start_line(text);
+    if !text.is_empty() && !text.ends_with("\n\n") {
+        text.push('\n');
+    }
+}
+
+// Renders HTML as plain text for reading in a terminal. Headings are marked with #, list items with -,
+// and links are followed by their URL in brackets. Scripts and styles are left out.
+pub fn to_text(html : &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    let mut preformatted = false;
+    // The URL of the link being rendered, with where its text starts.
+    let mut link : Option<(String, usize)> = None;
+
+    while !rest.is_empty() {
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map(|end| &rest[end + 3..]).unwrap_or_default();
+            continue;
+        }
+
+        if !rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let content = decode_entities(&rest[..end]);
+            if preformatted {
+                text.push_str(&content);
+            }
+            else {
+                let words = content.split_whitespace().collect::<Vec<_>>().join(" ");
+                let needs_space = content.starts_with(char::is_whitespace) && !text.ends_with([' ', '\n']) && !text.is_empty();
+                if needs_space {
+                    text.push(' ');
+                }
+                text.push_str(&words);
+                if content.ends_with(char::is_whitespace) && !words.is_empty() {
+                    text.push(' ');
+                }
+            }
+            rest = &rest[end..];
+            continue;
+        }
+
+        let end = match tag_end(rest) {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = &rest[..=end];
+        rest = &rest[end + 1..];
+        let name = tag_name(tag);
+        let closing = tag.starts_with("</");
+
+        if !closing && HIDDEN_ELEMENTS.contains(&name.as_str()) && !tag.ends_with("/>") {
+            let content_end = find_closing_tag(rest, &name).unwrap_or(rest.len());
+            rest = tag_end(&rest[content_end..]).map(|end| &rest[content_end + end + 1..]).unwrap_or_default();
+            continue;
+        }
+
+        match (name.as_str(), closing) {
+            ("br", _) => {
+                // Trailing spaces before a line break aren't wanted.
+                while text.ends_with(' ') {
+                    text.pop();
+                }
+                text.push('\n');
+            },
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => {
+                start_block(&mut text);
+                let level = name[1..].parse::<usize>().unwrap_or(1);
+                text.push_str(&format!("{} ", "#".repeat(level)));
+            },
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", true) => start_block(&mut text),
+            ("li", false) => {
+                start_line(&mut text);
+                text.push_str("- ");
+            },
+            ("tr" | "dt" | "dd", false) => start_line(&mut text),
+            ("td" | "th", false) if !text.ends_with('\n') && !text.is_empty() => text.push_str(" | "),
+            ("pre", false) => {
+                start_block(&mut text);
+                preformatted = true;
+            },
+            ("pre", true) => {
+                preformatted = false;
+                start_block(&mut text);
+            },
+            ("a", false) => link = attribute(tag, "href").map(|href| (href, text.len())),
+            ("a", true) => {
+                if let Some((href, start)) = link.take() {
+                    let label = text.get(start..).unwrap_or_default().trim().to_string();
+                    if !href.is_empty() && !href.starts_with('#') && label != href {
+                        while text.ends_with(' ') {
+                            text.pop();
+                        }
+                        text.push_str(&format!(" ({})", href));
+                    }
+                }
+            },
+            ("hr", _) => {
+                start_block(&mut text);
+                text.push_str("---");
+                start_block(&mut text);
+            },
+            (name, _) if BLOCK_ELEMENTS.contains(&name) => start_block(&mut text),
+            _ => {},
+        }
+    }
+
+    // Spaces at the ends of lines and runs of blank lines are left from the markup.
+    let mut rendered = String::new();
+    let mut blank = false;
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank = !rendered.is_empty();
+            continue;
+        }
+        if blank {
+            rendered.push('\n');
+            blank = false;
+        }
+        rendered.push_str(line);
+        rendered.push('\n');
+    }
+
+    rendered
+}
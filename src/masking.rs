@@ -0,0 +1,49 @@
+use std::sync::Mutex;
+
+static SECRETS : Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// Values this short would mask too much unrelated text to be worth hiding.
+const MINIMUM_LENGTH : usize = 4;
+
+fn escape(text : &str) -> String {
+    let escaped = serde_json::to_string(text).unwrap();
+    String::from(&escaped[1..escaped.len() - 1])
+}
+
+pub fn register(secret : &str) {
+    if secret.len() < MINIMUM_LENGTH {
+        return;
+    }
+
+    let mut secrets = match SECRETS.lock() {
+        Ok(secrets) => secrets,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+
+    // The value may appear in JSON output with escaped characters, including inside a JSON response body.
+    let escaped = escape(secret);
+    let double_escaped = escape(&escaped);
+
+    for value in [String::from(secret), escaped, double_escaped] {
+        if !secrets.contains(&value) {
+            secrets.push(value);
+        }
+    }
+
+    // Longer values first, so that a secret containing another is masked as a whole.
+    secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+}
+
+pub fn mask(text : &str) -> String {
+    let secrets = match SECRETS.lock() {
+        Ok(secrets) => secrets,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+
+    let mut masked = String::from(text);
+    for secret in secrets.iter() {
+        masked = masked.replace(secret.as_str(), "***");
+    }
+
+    masked
+}
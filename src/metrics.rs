@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+
+use crate::error::CallsyError;
+use crate::processing::OutputResponse;
+
+// What is measured while an attempt is sent, beyond its total time.
+#[derive(Default)]
+pub struct Timing {
+    pub dns : Option<Duration>,
+    pub first_byte : Option<Instant>,
+}
+
+// The timing of one attempt to send a request, for exporting as CSV. The DNS lookup is timed when a new
+// connection needed one, but connecting and the TLS handshake aren't measured separately, as the HTTP
+// client doesn't report when they finish, so they are only part of the time until the first byte.
+#[derive(Serialize)]
+pub struct Attempt {
+    // Seconds since the Unix epoch.
+    timestamp : f64,
+    name : Option<String>,
+    method : String,
+    url : String,
+    status_code : Option<u16>,
+    error : Option<&'static str>,
+    connection_reused : Option<bool>,
+    dns : Option<f64>,
+    time_to_first_byte : Option<f64>,
+    total : f64,
+    #[serde(skip)]
+    started : Option<Instant>,
+}
+
+impl Attempt {
+    pub fn start(name : Option<String>, method : &reqwest::Method, url : &reqwest::Url) -> Attempt {
+        let timestamp = match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+            Ok(now) => now.as_secs_f64(),
+            Err(_) => 0.0,
+        };
+
+        Attempt {
+            timestamp,
+            name,
+            method : method.to_string(),
+            url : url.to_string(),
+            status_code : None,
+            error : None,
+            connection_reused : None,
+            dns : None,
+            time_to_first_byte : None,
+            total : 0.0,
+            started : Some(Instant::now()),
+        }
+    }
+
+    pub fn finish(mut self, timing : &Timing, fetched : &Result<OutputResponse, CallsyError>) -> Attempt {
+        if let Some(started) = self.started {
+            self.time_to_first_byte = timing.first_byte.map(|first_byte| first_byte.duration_since(started).as_secs_f64());
+            self.total = started.elapsed().as_secs_f64();
+        }
+        self.dns = timing.dns.map(|dns| dns.as_secs_f64());
+
+        match fetched {
+            Ok(output_response) => {
+                self.status_code = Some(output_response.status_code());
+                self.connection_reused = output_response.connection_reused();
+            },
+            Err(error) => self.error = Some(error.category()),
+        }
+
+        self
+    }
+}
+
+pub fn to_csv(attempts : &[Attempt]) -> Result<String, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+
+    for attempt in attempts {
+        if let Err(error) = writer.serialize(attempt) {
+            return Err(format!("Failed to write timing metrics. {}", error));
+        }
+    }
+
+    match writer.into_inner() {
+        Ok(bytes) => Ok(String::from_utf8_lossy(&bytes).into_owned()),
+        Err(error) => Err(format!("Failed to write timing metrics. {}", error)),
+    }
+}
+
+const BUCKETS : [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Default)]
+struct Counts {
+    // Keyed by request name and status code.
+    responses : BTreeMap<(String, u16), u64>,
+    // Keyed by request name and error category.
+    errors : BTreeMap<(String, &'static str), u64>,
+    // The number of requests which took at most each of the bucket durations.
+    buckets : [u64; BUCKETS.len()],
+    count : u64,
+    sum : f64,
+}
+
+// Counters and a latency histogram for the requests of a run, served in the Prometheus text format
+// while the run is in progress.
+#[derive(Default)]
+pub struct Registry {
+    counts : Mutex<Counts>,
+}
+
+fn escape_label(value : &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl Registry {
+    pub fn observe(&self, attempt : &Attempt) {
+        let mut counts = match self.counts.lock() {
+            Ok(counts) => counts,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let name = attempt.name.clone().unwrap_or_default();
+        match (attempt.status_code, attempt.error) {
+            (Some(status_code), _) => *counts.responses.entry((name, status_code)).or_default() += 1,
+            (None, Some(category)) => *counts.errors.entry((name, category)).or_default() += 1,
+            (None, None) => {},
+        }
+
+        for (bucket, upper_bound) in BUCKETS.iter().enumerate() {
+            if attempt.total <= *upper_bound {
+                counts.buckets[bucket] += 1;
+            }
+        }
+        counts.count += 1;
+        counts.sum += attempt.total;
+    }
+
+    fn render(&self) -> String {
+        let counts = match self.counts.lock() {
+            Ok(counts) => counts,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let mut text = String::new();
+
+        text.push_str("# HELP callsy_responses_total Responses received, by request name and status code.\n");
+        text.push_str("# TYPE callsy_responses_total counter\n");
+        for ((name, status_code), count) in &counts.responses {
+            text.push_str(&format!("callsy_responses_total{{name=\"{}\",status_code=\"{}\"}} {}\n", escape_label(name), status_code, count));
+        }
+
+        text.push_str("# HELP callsy_errors_total Requests which failed without a response, by request name and error category.\n");
+        text.push_str("# TYPE callsy_errors_total counter\n");
+        for ((name, category), count) in &counts.errors {
+            text.push_str(&format!("callsy_errors_total{{name=\"{}\",category=\"{}\"}} {}\n", escape_label(name), category, count));
+        }
+
+        text.push_str("# HELP callsy_request_duration_seconds Total time taken by each request, including the body.\n");
+        text.push_str("# TYPE callsy_request_duration_seconds histogram\n");
+        for (bucket, upper_bound) in BUCKETS.iter().enumerate() {
+            text.push_str(&format!("callsy_request_duration_seconds_bucket{{le=\"{}\"}} {}\n", upper_bound, counts.buckets[bucket]));
+        }
+        text.push_str(&format!("callsy_request_duration_seconds_bucket{{le=\"+Inf\"}} {}\n", counts.count));
+        text.push_str(&format!("callsy_request_duration_seconds_sum {}\n", counts.sum));
+        text.push_str(&format!("callsy_request_duration_seconds_count {}\n", counts.count));
+
+        text
+    }
+}
+
+async fn respond(registry : Arc<Registry>, request : Request<Body>) -> Result<Response<Body>, Infallible> {
+    if request.uri().path() != "/metrics" {
+        let mut response = Response::new(Body::from("Metrics are served at /metrics"));
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(response);
+    }
+
+    let mut response = Response::new(Body::from(registry.render()));
+    response.headers_mut().insert(hyper::header::CONTENT_TYPE, hyper::header::HeaderValue::from_static("text/plain; version=0.0.4"));
+    Ok(response)
+}
+
+// Binds the metrics endpoint straight away, returning a future which serves it for as long as it runs. It is
+// only reachable locally unless another address is given, such as 0.0.0.0 for a scraper on another host.
+pub fn serve(address : Option<std::net::IpAddr>, port : u16, registry : Arc<Registry>) -> Result<impl Future<Output = ()>, CallsyError> {
+    let address = std::net::SocketAddr::new(address.unwrap_or(std::net::IpAddr::from([127, 0, 0, 1])), port);
+
+    let make_service = make_service_fn(move |_| {
+        let registry = registry.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| respond(registry.clone(), request)))
+        }
+    });
+
+    let server = match Server::try_bind(&address) {
+        Ok(builder) => builder.serve(make_service),
+        Err(error) => return Err(CallsyError::Usage(format!("Unable to serve metrics on {}. {}", address, error))),
+    };
+
+    Ok(async move {
+        if let Err(error) = server.await {
+            tracing::error!(%error, "The metrics endpoint stopped unexpectedly");
+        }
+    })
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fs::File;
+use std::io::prelude::*;
+use std::sync::Arc;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+
+use crate::error::CallsyError;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Routes {
+    routes : Vec<Route>,
+}
+
+// A canned response, served to requests with a matching method and path. Routes without a method
+// match any method, and * in the path matches any sequence of characters.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Route {
+    method : Option<String>,
+    path : String,
+    #[serde(default = "default_status")]
+    status : u16,
+    #[serde(default)]
+    headers : HashMap<String, String>,
+    #[serde(default)]
+    body : String,
+    delay : Option<f64>,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+impl Route {
+    fn matches(&self, method : &str, path : &str) -> bool {
+        self.method.as_ref().is_none_or(|route_method| route_method.eq_ignore_ascii_case(method))
+        && crate::collection::matches_pattern(&self.path, path)
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if StatusCode::from_u16(self.status).is_err() {
+            return Err(format!("The status {} of the mock route {} is invalid.", self.status, self.path));
+        }
+
+        for (name, value) in &self.headers {
+            if hyper::header::HeaderName::from_bytes(name.as_bytes()).is_err() || hyper::header::HeaderValue::from_str(value).is_err() {
+                return Err(format!("The header {} of the mock route {} is invalid.", name, self.path));
+            }
+        }
+
+        if let Some(delay) = self.delay {
+            if std::time::Duration::try_from_secs_f64(delay).is_err() {
+                return Err(format!("The delay of {} seconds of the mock route {} is not a number of seconds which is 0 or more.", delay, self.path));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn load_routes(path : &std::path::PathBuf) -> Result<Vec<Route>, String> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) => return Err(format!("Failed to open routes file {:?}. {}", path, error)),
+    };
+
+    let mut content = String::new();
+    if let Err(error) = file.read_to_string(&mut content) {
+        return Err(format!("Failed to read routes file {:?}. {}", path, error));
+    }
+
+    let routes = match serde_json::from_str::<Routes>(&content) {
+        Ok(routes) => routes.routes,
+        Err(error) => return Err(format!("Unable to parse routes file {:?}. {}", path, error)),
+    };
+
+    for route in &routes {
+        route.check()?;
+    }
+
+    Ok(routes)
+}
+
+fn text_response(status : StatusCode, text : String) -> Response<Body> {
+    let mut response = Response::new(Body::from(text));
+    *response.status_mut() = status;
+    response
+}
+
+async fn respond(routes : Arc<Vec<Route>>, request : Request<Body>) -> Result<Response<Body>, Infallible> {
+    let route = routes.iter().find(|route| route.matches(request.method().as_str(), request.uri().path()));
+
+    let route = match route {
+        Some(route) => route,
+        None => return Ok(text_response(StatusCode::NOT_FOUND, format!("No mock route matches {} {}", request.method(), request.uri().path()))),
+    };
+
+    // Delays are also checked when the routes are loaded.
+    if let Some(delay) = route.delay {
+        tokio::time::sleep(std::time::Duration::from_secs_f64(delay)).await;
+    }
+
+    let mut builder = Response::builder().status(route.status);
+    for (name, value) in &route.headers {
+        builder = builder.header(name, value);
+    }
+
+    // Routes are checked when they are loaded, so this shouldn't fail.
+    match builder.body(Body::from(route.body.clone())) {
+        Ok(response) => Ok(response),
+        Err(error) => Ok(text_response(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to build the mock response. {}", error))),
+    }
+}
+
+pub async fn serve(port : u16, path : &std::path::PathBuf) -> Result<(), CallsyError> {
+    let routes = Arc::new(load_routes(path).map_err(CallsyError::File)?);
+    let address = std::net::SocketAddr::from(([127, 0, 0, 1], port));
+
+    let make_service = make_service_fn(move |_| {
+        let routes = routes.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| respond(routes.clone(), request)))
+        }
+    });
+
+    let server = match Server::try_bind(&address) {
+        Ok(builder) => builder.serve(make_service),
+        Err(error) => return Err(CallsyError::Usage(format!("Unable to listen on {}. {}", address, error))),
+    };
+
+    println!("Serving mock routes from {:?} on http://{}", path, address);
+
+    match server.await {
+        Ok(()) => Ok(()),
+        Err(error) => Err(CallsyError::Usage(format!("The mock server stopped unexpectedly. {}", error))),
+    }
+}
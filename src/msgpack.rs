@@ -0,0 +1,83 @@
+use base64::Engine;
+use rmpv::Value as Msgpack;
+use serde_json::{Map, Value};
+
+fn from_json(json : Value) -> Msgpack {
+    match json {
+        Value::Null => Msgpack::Nil,
+        Value::Bool(boolean) => Msgpack::Boolean(boolean),
+        // Whole numbers are sent as integers, and anything else as a 64 bit float.
+        Value::Number(number) => match (number.as_u64(), number.as_i64()) {
+            (Some(unsigned), _) => Msgpack::from(unsigned),
+            (None, Some(signed)) => Msgpack::from(signed),
+            (None, None) => Msgpack::F64(number.as_f64().unwrap_or_default()),
+        },
+        Value::String(string) => Msgpack::from(string),
+        Value::Array(array) => Msgpack::Array(array.into_iter().map(from_json).collect()),
+        Value::Object(object) => Msgpack::Map(object.into_iter().map(|(key, value)| (Msgpack::from(key), from_json(value))).collect()),
+    }
+}
+
+// MessagePack can hold more than JSON, so binary data is given in base64, extension types as an object
+// of their type and data, and keys which aren't strings as their JSON.
+fn to_json(msgpack : Msgpack) -> Value {
+    match msgpack {
+        Msgpack::Nil => Value::Null,
+        Msgpack::Boolean(boolean) => Value::Bool(boolean),
+        Msgpack::Integer(integer) => match (integer.as_u64(), integer.as_i64()) {
+            (Some(unsigned), _) => Value::from(unsigned),
+            (None, Some(signed)) => Value::from(signed),
+            (None, None) => Value::Null,
+        },
+        Msgpack::F32(float) => Value::from(float as f64),
+        Msgpack::F64(float) => Value::from(float),
+        Msgpack::String(string) => match string.into_str() {
+            Some(string) => Value::from(string),
+            None => Value::Null,
+        },
+        Msgpack::Binary(bytes) => Value::from(base64::engine::general_purpose::STANDARD.encode(bytes)),
+        Msgpack::Array(array) => Value::Array(array.into_iter().map(to_json).collect()),
+        Msgpack::Map(entries) => {
+            let mut object = Map::new();
+            for (key, value) in entries {
+                let key = match to_json(key) {
+                    Value::String(key) => key,
+                    key => key.to_string(),
+                };
+                object.insert(key, to_json(value));
+            }
+            Value::Object(object)
+        },
+        Msgpack::Ext(kind, data) => serde_json::json!({
+            "type" : kind,
+            "data" : base64::engine::general_purpose::STANDARD.encode(data),
+        }),
+    }
+}
+
+// The body of a msgpack request is written as JSON and sent as MessagePack.
+pub fn encode_request(body : &str) -> Result<Vec<u8>, String> {
+    let json = match serde_json::from_str::<Value>(body) {
+        Ok(json) => json,
+        Err(error) => return Err(format!("The body of a msgpack request must be JSON. {}", error)),
+    };
+
+    let mut encoded = Vec::new();
+    match rmpv::encode::write_value(&mut encoded, &from_json(json)) {
+        Ok(()) => Ok(encoded),
+        Err(error) => panic!("Internal error, could not serialize MessagePack data for request. {}", error),
+    }
+}
+
+pub fn decode_response(body : &[u8]) -> Result<Value, String> {
+    let mut rest = body;
+    let msgpack = match rmpv::decode::read_value(&mut rest) {
+        Ok(msgpack) => msgpack,
+        Err(error) => return Err(format!("The response is not valid MessagePack. {}", error)),
+    };
+    if !rest.is_empty() {
+        return Err(format!("The response has {} bytes after the end of its MessagePack value.", rest.len()));
+    }
+
+    Ok(to_json(msgpack))
+}
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use sha2::Digest;
+
+pub fn extension_for_content_type(content_type : &str) -> Option<&'static str> {
+    let media_type = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+
+    match media_type.as_str() {
+        "application/json" => Some("json"),
+        "application/xml" | "text/xml" => Some("xml"),
+        "text/html" => Some("html"),
+        "text/plain" => Some("txt"),
+        "text/csv" => Some("csv"),
+        "text/css" => Some("css"),
+        "application/javascript" | "text/javascript" => Some("js"),
+        "application/pdf" => Some("pdf"),
+        "application/zip" => Some("zip"),
+        "application/gzip" => Some("gz"),
+        "application/x-tar" => Some("tar"),
+        "application/wasm" => Some("wasm"),
+        "application/octet-stream" => Some("bin"),
+        "image/png" => Some("png"),
+        "image/jpeg" => Some("jpg"),
+        "image/gif" => Some("gif"),
+        "image/webp" => Some("webp"),
+        "image/svg+xml" => Some("svg"),
+        "audio/mpeg" => Some("mp3"),
+        "video/mp4" => Some("mp4"),
+        "font/woff2" => Some("woff2"),
+        _ => {
+            // Structured syntax suffixes, such as application/problem+json.
+            match media_type.rsplit_once('+') {
+                Some((_, "json")) => Some("json"),
+                Some((_, "xml")) => Some("xml"),
+                _ => None,
+            }
+        },
+    }
+}
+
+fn filename_from_content_disposition(content_disposition : &str) -> Option<String> {
+    let mut filename = None;
+
+    for parameter in content_disposition.split(';').skip(1) {
+        let (key, value) = match parameter.split_once('=') {
+            Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+            None => continue,
+        };
+
+        match key.as_str() {
+            // The extended form, such as filename*=UTF-8''report%202022.pdf, takes precedence.
+            "filename*" => {
+                if let Some((_, encoded)) = value.split_once("''") {
+                    let decoded = percent_encoding::percent_decode_str(encoded).decode_utf8_lossy().into_owned();
+                    return sanitize(&decoded);
+                }
+            },
+            "filename" => {
+                filename = sanitize(value.trim_matches('"'));
+            },
+            _ => {},
+        }
+    }
+
+    filename
+}
+
+// Only the final component of a server provided name is used, so that it cannot write elsewhere.
+fn sanitize(filename : &str) -> Option<String> {
+    let filename = filename.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+
+    match filename {
+        "" | "." | ".." => None,
+        filename => Some(String::from(filename)),
+    }
+}
+
+fn find_header<'a>(headers : &'a HashMap<String, Vec<String>>, name : &str) -> Option<&'a str> {
+    headers
+    .iter()
+    .find(|(header, _)| header.eq_ignore_ascii_case(name))
+    .and_then(|(_, values)| values.first())
+    .map(|value| value.as_str())
+}
+
+// The extension matching the Content-Type of a response, if it is a known one, which is also used to
+// tell how to format its body.
+pub fn content_type_extension(headers : &HashMap<String, Vec<String>>) -> Option<&'static str> {
+    find_header(headers, "content-type").and_then(extension_for_content_type)
+}
+
+// Whether a body of this content type is binary data, such as an image, PDF or archive, rather than text.
+pub fn is_binary_content_type(content_type : &str) -> bool {
+    let media_type = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+
+    match media_type.split_once('/') {
+        Some(("image", "svg+xml")) => false,
+        Some(("image" | "audio" | "video" | "font", _)) => true,
+        Some(("application", subtype)) => matches!(
+            subtype,
+            "pdf" | "zip" | "gzip" | "x-gzip" | "x-tar" | "x-bzip2" | "x-xz" | "x-7z-compressed" | "x-rar-compressed"
+            | "zstd" | "wasm" | "octet-stream" | "x-protobuf" | "protobuf" | "vnd.google.protobuf" | "grpc-web" | "grpc-web+proto"
+            | "msgpack" | "x-msgpack" | "vnd.msgpack" | "cbor" | "cose" | "vnd.ms-excel" | "msword"
+        ) || subtype.starts_with("vnd.openxmlformats-officedocument.") || subtype.ends_with("+cbor"),
+        _ => false,
+    }
+}
+
+pub fn has_binary_content_type(headers : &HashMap<String, Vec<String>>) -> bool {
+    find_header(headers, "content-type").is_some_and(is_binary_content_type)
+}
+
+pub fn has_protobuf_content_type(headers : &HashMap<String, Vec<String>>) -> bool {
+    find_header(headers, "content-type").is_some_and(|content_type| {
+        let media_type = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+        matches!(media_type.as_str(), "application/x-protobuf" | "application/protobuf" | "application/vnd.google.protobuf")
+    })
+}
+
+pub fn has_msgpack_content_type(headers : &HashMap<String, Vec<String>>) -> bool {
+    find_header(headers, "content-type").is_some_and(|content_type| {
+        let media_type = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+        matches!(media_type.as_str(), "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack")
+    })
+}
+
+// COSE messages are CBOR, so they are decoded too.
+pub fn has_cbor_content_type(headers : &HashMap<String, Vec<String>>) -> bool {
+    find_header(headers, "content-type").is_some_and(|content_type| {
+        let media_type = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+        media_type == "application/cbor" || media_type == "application/cose" || media_type.ends_with("+cbor")
+    })
+}
+
+// The path of the file beside the output file which a binary body is written to, named after the output
+// file and the SHA-256 of the body so that each distinct body gets a file of its own.
+pub fn sidecar_path(output_path : &Path, body : &[u8], extension : Option<&str>) -> PathBuf {
+    let stem = output_path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let hash = hex::encode(sha2::Sha256::digest(body));
+    output_path.with_file_name(format!("{}.{}.{}", stem, &hash[..12], extension.unwrap_or("bin")))
+}
+
+// Turns a request name into something which can be used in a file name, replacing anything other than
+// letters, digits, dashes, underscores and dots.
+pub fn file_stem(name : &str) -> String {
+    name
+    .chars()
+    .map(|character| if character.is_ascii_alphanumeric() || matches!(character, '-' | '_' | '.') { character } else { '_' })
+    .collect()
+}
+
+// Derives the path to write the response body to from the response headers, using the filename
+// from Content-Disposition if there is one, and otherwise an extension matching the Content-Type.
+pub fn infer_body_output_path(path : &Path, headers : &HashMap<String, Vec<String>>) -> PathBuf {
+    if let Some(filename) = find_header(headers, "content-disposition").and_then(filename_from_content_disposition) {
+        return path.with_file_name(filename);
+    }
+
+    match content_type_extension(headers) {
+        Some(extension) => path.with_extension(extension),
+        None => path.to_path_buf(),
+    }
+}
+
+// Text is decoded with the charset of its Content-Type, or as UTF-8 when there isn't one, in the same way
+// as reqwest decodes it. Bytes which aren't valid in the charset are replaced rather than failing.
+pub fn decode_text(bytes : &[u8], headers : &HashMap<String, Vec<String>>) -> String {
+    let encoding = find_header(headers, "content-type")
+        .and_then(|content_type| {
+            content_type
+            .split(';')
+            .skip(1)
+            .filter_map(|parameter| parameter.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+            .map(|(_, charset)| charset.trim().trim_matches('"'))
+        })
+        .and_then(|charset| encoding_rs::Encoding::for_label(charset.as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+
+    let (text, _, _) = encoding.decode(bytes);
+    text.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(content_type : &str) -> HashMap<String, Vec<String>> {
+        HashMap::from([(String::from("content-type"), vec![String::from(content_type)])])
+    }
+
+    #[test]
+    fn text_is_decoded_with_charset_of_content_type() {
+        let latin1 = b"caf\xe9";
+
+        assert_eq!(decode_text(latin1, &headers("text/plain; charset=iso-8859-1")), "café");
+        assert_eq!(decode_text(latin1, &headers("text/plain; Charset=\"ISO-8859-1\"")), "café");
+        assert_eq!(decode_text("café".as_bytes(), &headers("application/json")), "café");
+        assert_eq!(decode_text(latin1, &HashMap::new()), "caf\u{fffd}");
+    }
+}
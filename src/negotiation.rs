@@ -0,0 +1,87 @@
+// The formats a request can ask for with prefer, each of which can be served under a few media types.
+#[derive(Deserialize, Debug, Clone, Copy, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    Json,
+    Xml,
+    Html,
+    Text,
+    Csv,
+    Yaml,
+    Msgpack,
+    Cbor,
+    Protobuf,
+}
+
+// One format, or several in order of preference.
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema)]
+#[serde(untagged)]
+pub enum Prefer {
+    One(Format),
+    Many(Vec<Format>),
+}
+
+impl Format {
+    fn media_types(&self) -> &'static [&'static str] {
+        match self {
+            Format::Json => &["application/json"],
+            Format::Xml => &["application/xml", "text/xml"],
+            Format::Html => &["text/html"],
+            Format::Text => &["text/plain"],
+            Format::Csv => &["text/csv"],
+            Format::Yaml => &["application/yaml", "application/x-yaml", "text/yaml"],
+            Format::Msgpack => &["application/msgpack", "application/x-msgpack", "application/vnd.msgpack"],
+            Format::Cbor => &["application/cbor"],
+            Format::Protobuf => &["application/x-protobuf", "application/protobuf", "application/vnd.google.protobuf"],
+        }
+    }
+
+    // Structured syntax suffixes, so that application/problem+json is still JSON.
+    fn suffix(&self) -> Option<&'static str> {
+        match self {
+            Format::Json => Some("+json"),
+            Format::Xml => Some("+xml"),
+            Format::Yaml => Some("+yaml"),
+            Format::Cbor => Some("+cbor"),
+            _ => None,
+        }
+    }
+
+    fn matches(&self, media_type : &str) -> bool {
+        self.media_types().contains(&media_type) || self.suffix().is_some_and(|suffix| media_type.ends_with(suffix))
+    }
+}
+
+impl Prefer {
+    fn formats(&self) -> &[Format] {
+        match self {
+            Prefer::One(format) => std::slice::from_ref(format),
+            Prefer::Many(formats) => formats,
+        }
+    }
+
+    // Each format after the first is given a q-value 0.1 lower than the one before it, down to 0.1.
+    pub fn accept(&self) -> Result<String, String> {
+        if self.formats().is_empty() {
+            return Err(String::from("The prefer field must give at least one format."));
+        }
+
+        let mut accept = Vec::new();
+        for (index, format) in self.formats().iter().enumerate() {
+            let quality = 10usize.saturating_sub(index).max(1);
+            for media_type in format.media_types() {
+                match quality {
+                    10 => accept.push(String::from(*media_type)),
+                    quality => accept.push(format!("{};q=0.{}", media_type, quality)),
+                }
+            }
+        }
+
+        Ok(accept.join(", "))
+    }
+
+    pub fn matches(&self, content_type : &str) -> bool {
+        let media_type = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+        self.formats().iter().any(|format| format.matches(&media_type))
+    }
+}
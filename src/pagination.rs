@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use reqwest::Url;
+
+// Follows the pages of a paginated response, combining the items from each page into one array.
+// The next page is found from a cursor in the body if one is given, and otherwise from a Link header
+// with rel="next" (RFC 5988).
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Paginate {
+    // JSON pointer to the array of items in each page, which is the whole body if not given.
+    items : Option<String>,
+    // JSON pointer to the cursor for the next page, which is sent as the query parameter.
+    cursor : Option<String>,
+    parameter : Option<String>,
+    max_pages : Option<usize>,
+}
+
+impl Paginate {
+    pub fn max_pages(&self) -> usize {
+        self.max_pages.unwrap_or(10)
+    }
+
+    pub fn items(&self, body : &str) -> Result<Vec<serde_json::Value>, String> {
+        let pointer = self.items.as_deref().unwrap_or_default();
+
+        let body = match serde_json::from_str::<serde_json::Value>(body) {
+            Ok(body) => body,
+            Err(error) => return Err(format!("Cannot paginate as the response body is not JSON. {}", error)),
+        };
+
+        match body.pointer(pointer) {
+            Some(serde_json::Value::Array(items)) => Ok(items.clone()),
+            _ => Err(format!("Cannot paginate as the response body has no array at {:?}.", pointer)),
+        }
+    }
+
+    pub fn next_url(&self, url : &Url, headers : &HashMap<String, Vec<String>>, body : &str) -> Result<Option<Url>, String> {
+        match (&self.cursor, &self.parameter) {
+            (Some(cursor), Some(parameter)) => {
+                let body = serde_json::from_str::<serde_json::Value>(body).ok();
+                let cursor = match body.as_ref().and_then(|body| body.pointer(cursor)) {
+                    Some(serde_json::Value::String(cursor)) if cursor.is_empty() => return Ok(None),
+                    Some(serde_json::Value::String(cursor)) => cursor.clone(),
+                    Some(serde_json::Value::Null) | None => return Ok(None),
+                    Some(cursor) => cursor.to_string(),
+                };
+
+                let mut next = url.clone();
+                let pairs = url.query_pairs().filter(|(key, _)| key != parameter.as_str()).collect::<Vec<_>>();
+                next.query_pairs_mut().clear().extend_pairs(pairs).append_pair(parameter, &cursor);
+                Ok(Some(next))
+            },
+            (Some(_), None) | (None, Some(_)) => Err(String::from("A paginate block must give both a cursor and parameter, or neither to follow Link headers.")),
+            (None, None) => Ok(next_link(url, headers)),
+        }
+    }
+}
+
+// Links are split at the angle brackets around each target rather than at commas, as a target can hold
+// commas of its own, such as ?ids=1,2.
+fn next_link(url : &Url, headers : &HashMap<String, Vec<String>>) -> Option<Url> {
+    let values = headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case("link"))
+        .map(|(_, values)| values)?;
+
+    for value in values {
+        let mut rest = value.as_str();
+        while let Some((_, link)) = rest.split_once('<') {
+            let (target, after) = link.split_once('>')?;
+            let parameters = after.split_once('<').map_or(after, |(parameters, _)| parameters);
+            rest = &after[parameters.len()..];
+
+            // The relation can list several types, such as rel="next last".
+            let is_next = parameters
+                .split([';', ','])
+                .filter_map(|parameter| parameter.split_once('='))
+                .filter(|(key, _)| key.trim().eq_ignore_ascii_case("rel"))
+                .any(|(_, value)| value.trim().trim_matches('"').split_whitespace().any(|rel| rel.eq_ignore_ascii_case("next")));
+
+            if is_next {
+                return url.join(target.trim()).ok();
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next(links : &[&str]) -> Option<String> {
+        let url = Url::parse("https://somedomain.com/api/items?page=1").unwrap();
+        let headers = HashMap::from([(String::from("Link"), links.iter().map(|link| String::from(*link)).collect())]);
+        next_link(&url, &headers).map(String::from)
+    }
+
+    #[test]
+    fn next_link_is_found_among_others() {
+        assert_eq!(next(&[r#"<https://somedomain.com/api/items?page=1>; rel="prev", <https://somedomain.com/api/items?page=3>; rel="next""#]).as_deref(), Some("https://somedomain.com/api/items?page=3"));
+        assert_eq!(next(&[r#"<?page=5>; rel="last""#, r#"<?page=2>; rel=next"#]).as_deref(), Some("https://somedomain.com/api/items?page=2"));
+        assert_eq!(next(&[r#"</api/items?page=2>; title="more"; REL="next last""#]).as_deref(), Some("https://somedomain.com/api/items?page=2"));
+    }
+
+    #[test]
+    fn next_link_can_hold_commas() {
+        assert_eq!(next(&[r#"<https://somedomain.com/api/items?ids=1,2,3>; rel="next", <https://somedomain.com/api/items?ids=9>; rel="last""#]).as_deref(), Some("https://somedomain.com/api/items?ids=1,2,3"));
+    }
+
+    #[test]
+    fn no_next_link() {
+        assert_eq!(next(&[r#"<https://somedomain.com/api/items?page=1>; rel="prev""#]), None);
+        assert_eq!(next(&["not a link"]), None);
+        assert_eq!(next_link(&Url::parse("https://somedomain.com").unwrap(), &HashMap::new()), None);
+    }
+}
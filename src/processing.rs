@@ -3,318 +3,3105 @@ use std::io::prelude::*;
 use std::collections::HashMap;
 
 use reqwest::{Method, Response, Url, Client};
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use sha2::Digest;
+
+use crate::error::CallsyError;
 
 #[derive(Parser)]
 pub struct Arguments {
+    #[clap(subcommand)]
+    command : Option<Command>,
+
     #[clap(parse(from_os_str), short, default_value = "request.json")]
     request_file : std::path::PathBuf,
 
-    #[clap(parse(from_os_str), short, default_value = "response.json")]
-    output_file : std::path::PathBuf,
+    #[clap(parse(from_os_str), short)]
+    output_file : Option<std::path::PathBuf>,
+
+    #[clap(parse(from_os_str), short, long = "response-body-file")]
+    body_output_file : Option<std::path::PathBuf>,
+
+    #[clap(parse(from_os_str), long)]
+    config : Option<std::path::PathBuf>,
+
+    #[clap(long)]
+    profile : Option<String>,
+
+    #[clap(parse(from_os_str), short, long)]
+    env : Option<std::path::PathBuf>,
+
+    #[clap(parse(from_os_str), short, long, conflicts_with = "request-file")]
+    collection : Option<std::path::PathBuf>,
+
+    #[clap(long = "tag")]
+    tags : Vec<String>,
+
+    #[clap(long = "name")]
+    names : Vec<String>,
+
+    #[clap(long)]
+    save_captures : bool,
+
+    #[clap(long)]
+    show_secrets : bool,
+
+    #[clap(long)]
+    fsync : bool,
+
+    #[clap(long)]
+    strict : bool,
+
+    #[clap(long)]
+    include_request : bool,
+
+    #[clap(long = "include-headers", use_value_delimiter = true)]
+    include_headers : Vec<String>,
+
+    #[clap(long = "exclude-headers", use_value_delimiter = true)]
+    exclude_headers : Vec<String>,
+
+    #[clap(long)]
+    pretty : bool,
+
+    #[clap(long, possible_values = &["text"])]
+    render : Option<String>,
+
+    #[clap(parse(from_os_str), long, conflicts_with = "collection")]
+    data_file : Option<std::path::PathBuf>,
+
+    #[clap(long, requires = "body-output-file")]
+    infer_body_name : bool,
+
+    #[clap(long = "continue", requires = "body-output-file", conflicts_with_all = &["infer-body-name", "replay"])]
+    continue_download : bool,
+
+    #[clap(long)]
+    output_pattern : Option<String>,
+
+    #[clap(long, conflicts_with = "output-pattern")]
+    ndjson : bool,
+
+    #[clap(long, conflicts_with = "ndjson")]
+    canonical : bool,
+
+    #[clap(parse(from_os_str), long, conflicts_with_all = &["output-pattern", "ndjson", "body-output-file"])]
+    output_dir : Option<std::path::PathBuf>,
+
+    #[clap(long, default_value = "text", possible_values = &["text", "json"])]
+    error_format : String,
+
+    #[clap(parse(from_os_str), long, conflicts_with = "replay")]
+    record : Option<std::path::PathBuf>,
+
+    #[clap(parse(from_os_str), long)]
+    replay : Option<std::path::PathBuf>,
+
+    #[clap(long)]
+    offline : bool,
+
+    #[clap(parse(from_os_str), long)]
+    junit : Option<std::path::PathBuf>,
+
+    #[clap(parse(from_os_str), long)]
+    report : Option<std::path::PathBuf>,
+
+    #[clap(parse(from_os_str), long)]
+    metrics_csv : Option<std::path::PathBuf>,
+
+    #[clap(long)]
+    metrics_port : Option<u16>,
+
+    #[clap(long, requires = "metrics-port")]
+    metrics_address : Option<std::net::IpAddr>,
+
+    #[clap(long, possible_values = &["error", "warn", "info", "debug", "trace"])]
+    log_level : Option<String>,
+
+    #[clap(parse(from_os_str), long)]
+    trace_wire : Option<std::path::PathBuf>,
+
+    #[clap(parse(from_os_str), long)]
+    request_echo : Option<std::path::PathBuf>,
+
+    #[clap(long)]
+    max_body_size : Option<String>,
+
+    #[clap(long)]
+    max_inline_body : Option<String>,
+
+    #[clap(parse(from_os_str), long)]
+    append_to : Option<std::path::PathBuf>,
+
+    #[clap(long)]
+    speed_limit : Option<String>,
+
+    #[clap(long)]
+    speed_time : Option<u64>,
+
+    #[clap(long)]
+    abort_after : Option<usize>,
+
+    #[clap(long)]
+    abort_error_rate : Option<f64>,
+
+    #[clap(long)]
+    think_time : Option<f64>,
+}
+
+impl Arguments {
+    pub fn json_errors(&self) -> bool {
+        self.error_format == "json"
+    }
+
+    pub fn log_level(&self) -> Option<&str> {
+        self.log_level.as_deref()
+    }
+
+    pub fn trace_wire(&self) -> Option<&std::path::PathBuf> {
+        self.trace_wire.as_ref()
+    }
+
+    pub fn show_secrets(&self) -> bool {
+        self.show_secrets
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[clap(about = "Manage secrets stored in the OS keychain")]
+    Secret {
+        #[clap(subcommand)]
+        action : SecretAction,
+    },
+    #[clap(about = "Report how many days remain until the certificate of a server expires")]
+    Certcheck {
+        url : String,
+        #[clap(long, default_value = "30", help = "Fail if the certificate expires within this many days")]
+        threshold : i64,
+    },
+    #[clap(about = "Inspect JSON web tokens")]
+    Jwt {
+        #[clap(subcommand)]
+        action : JwtAction,
+    },
+    #[clap(about = "Serve canned responses for developing request files without a real backend")]
+    Mock {
+        #[clap(long, default_value = "8080")]
+        port : u16,
+        #[clap(long, default_value = "mock.json")]
+        routes : std::path::PathBuf,
+    },
+    #[clap(about = "Receive requests, such as webhook callbacks, writing each to a file")]
+    Listen {
+        #[clap(long, default_value = "9000")]
+        port : u16,
+        #[clap(long, help = "The address to listen on, such as 0.0.0.0 to receive requests from other hosts")]
+        address : Option<std::net::IpAddr>,
+        #[clap(parse(from_os_str), long, default_value = ".", help = "The directory to write received requests to")]
+        directory : std::path::PathBuf,
+        #[clap(long, help = "Stop after receiving this many requests")]
+        count : Option<usize>,
+        #[clap(parse(from_os_str), long, help = "A request file to send once listening, such as one which triggers a callback")]
+        send : Option<std::path::PathBuf>,
+    },
+    #[clap(about = "Browse and send the requests of a collection in a terminal interface")]
+    Tui {
+        #[clap(parse(from_os_str))]
+        collection : std::path::PathBuf,
+        #[clap(parse(from_os_str), short, long)]
+        env : Option<std::path::PathBuf>,
+    },
+    #[clap(about = "Build, send and inspect requests from an interactive prompt")]
+    Repl {
+        #[clap(parse(from_os_str), short, long)]
+        env : Option<std::path::PathBuf>,
+    },
+    #[clap(about = "Send a request with each of several environments at once, and show how the responses differ")]
+    Compare {
+        #[clap(parse(from_os_str), short, long, default_value = "request.json")]
+        request_file : std::path::PathBuf,
+        #[clap(parse(from_os_str), short, long, required = true, min_values = 2, multiple_occurrences = true)]
+        env : Vec<std::path::PathBuf>,
+        #[clap(long, multiple_occurrences = true, help = "A JSON pointer to leave out of the comparison, such as /headers/date")]
+        ignore : Vec<String>,
+    },
+    #[clap(about = "Serve a local HTTP API for sending the requests of a collection and fetching their results")]
+    Serve {
+        #[clap(parse(from_os_str))]
+        collection : std::path::PathBuf,
+        #[clap(parse(from_os_str), short, long)]
+        env : Option<std::path::PathBuf>,
+        #[clap(long, default_value = "8090")]
+        port : u16,
+    },
+    #[clap(about = "Send a request on a cron schedule, recording each result and alerting when it fails")]
+    Schedule {
+        #[clap(long, help = "When to send the request, such as \"*/5 * * * *\" for every five minutes")]
+        cron : String,
+        #[clap(parse(from_os_str), short, long, default_value = "request.json")]
+        request_file : std::path::PathBuf,
+        #[clap(parse(from_os_str), short, long)]
+        env : Option<std::path::PathBuf>,
+        #[clap(parse(from_os_str), long, help = "An NDJSON file to append each request and its response to")]
+        append_to : Option<std::path::PathBuf>,
+        #[clap(long, help = "A command to run when the request fails")]
+        on_failure_command : Option<String>,
+        #[clap(long, help = "A URL to POST details of the failure to when the request fails")]
+        on_failure_webhook : Option<reqwest::Url>,
+    },
+    #[clap(about = "Print the JSON schema of request files, or check files against it")]
+    Schema {
+        #[clap(long, help = "Use the schema of collection files rather than request files")]
+        collection : bool,
+        #[clap(parse(from_os_str), help = "Files to check against the schema, rather than printing it")]
+        files : Vec<std::path::PathBuf>,
+    },
+    #[clap(about = "Search the requests recorded in the history database")]
+    History {
+        #[clap(subcommand)]
+        action : HistoryAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum HistoryAction {
+    #[clap(about = "Print the recorded requests which match all of the filters, most recent first")]
+    Search {
+        #[clap(long, help = "Only requests whose URL contains this text")]
+        url_contains : Option<String>,
+        #[clap(long, help = "Only responses with this status code, or class of them such as 5xx")]
+        status : Option<String>,
+        #[clap(long, help = "Only requests sent within this long, such as 30m, 12h or 2d")]
+        since : Option<String>,
+        #[clap(long, help = "Only requests with this name")]
+        name : Option<String>,
+        #[clap(long, default_value = "20")]
+        limit : usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum SecretAction {
+    #[clap(about = "Store a secret, prompting for its value")]
+    Set {
+        name : String,
+    },
+    #[clap(about = "Print the value of a secret")]
+    Get {
+        name : String,
+    },
+}
+
+#[derive(Subcommand)]
+enum JwtAction {
+    #[clap(about = "Print the header and claims of a token, without verifying its signature")]
+    Decode {
+        token : String,
+    },
+}
+
+pub async fn respond(args : Arguments) -> Result<(), CallsyError> {
+    
+    if let Some(command) = args.command {
+        return run_subcommand(command, &args.config, &args.profile).await;
+    }
+
+    let mut config = crate::config::load_config(&args.config, &args.profile).map_err(CallsyError::Config)?;
+    config.offline = args.offline;
+    config.trace_wire = args.trace_wire.is_some();
+    config.max_body_size = match &args.max_body_size {
+        Some(size) => Some(parse_size(size).map_err(CallsyError::Usage)?),
+        None => None,
+    };
+    // As with curl, either option alone turns on the check, with a limit of 1 byte per second or a time of 30 seconds.
+    config.speed_limit = match (&args.speed_limit, args.speed_time) {
+        (None, None) => None,
+        (_, Some(0)) => return Err(CallsyError::Usage(String::from("The speed time must be at least 1 second."))),
+        (speed_limit, speed_time) => Some(crate::config::SpeedLimit {
+            bytes_per_second : match speed_limit {
+                Some(speed_limit) => parse_size(speed_limit).map_err(CallsyError::Usage)?,
+                None => 1,
+            },
+            time : std::time::Duration::from_secs(speed_time.unwrap_or(30)),
+        }),
+    };
+    config.download = match (&args.body_output_file, args.continue_download) {
+        (Some(path), true) => Some(crate::config::Download {
+            path : path.clone(),
+            offset : std::fs::metadata(path).map(|metadata| metadata.len()).unwrap_or_default(),
+        }),
+        _ => None,
+    };
+    if let Some(directory) = &args.output_dir {
+        if let Err(source) = std::fs::create_dir_all(directory) {
+            return Err(CallsyError::Io { message : format!("Failed to create output directory {:?}. {}", directory, source), source });
+        }
+    }
+    // With an output directory the summary of the run is written there too, unless -o is given.
+    let output_path = match (&args.output_file, &args.output_dir) {
+        (None, Some(directory)) => directory.join("summary.json"),
+        _ => get_output_path(&args.output_file, &config),
+    };
+    check_output_file(&output_path)?;
+    // A binary body goes to the body file when there is one, and otherwise to a file beside the output.
+    if args.output_dir.is_none() && args.body_output_file.is_none() {
+        config.sidecar_output = Some(output_path.clone());
+    }
+    config.fsync = args.fsync;
+    config.strict = args.strict;
+    config.include_request = args.include_request;
+    // A partial file being continued is appended to, so there is no need to ask before overwriting it.
+    if !args.infer_body_name && !args.continue_download {
+        check_body_output_file(&args.body_output_file)?; 
+    }
+    let mut variables = load_variables(&args.env, &config)?;
+    let save_captures_path = get_save_captures_path(args.save_captures, &args.env, &config)?;
+    let mut captured_variables = crate::variables::Variables::new();
+    for path in [&args.record, &args.junit, &args.report, &args.metrics_csv, &args.request_echo].into_iter().flatten() {
+        check_output_file(path)?;
+    }
+    let replay = match &args.replay {
+        Some(path) => Some(crate::cassette::load_cassette(path).map_err(CallsyError::File)?),
+        None => None,
+    };
+
+    let mut session = Session {
+        config : &config,
+        clients : Clients::default(),
+        recording : args.record.as_ref().map(|_| crate::cassette::Cassette::default()),
+        replay,
+        report : args.junit.as_ref().or(args.report.as_ref()).map(|_| {
+            let suite = args.collection.as_ref().unwrap_or(&args.request_file).file_stem().unwrap_or_default();
+            crate::report::Report::new(&suite.to_string_lossy())
+        }),
+        metrics : args.metrics_csv.as_ref().map(|_| Vec::new()),
+        live_metrics : args.metrics_port.map(|_| std::sync::Arc::new(crate::metrics::Registry::default())),
+        sent : args.request_echo.as_ref().map(|_| Vec::new()),
+        history : crate::history::History::open(args.append_to.as_deref(), config.history_database.as_deref(), args.show_secrets)?,
+        render_text : args.render.is_some(),
+        show_secrets : args.show_secrets,
+        breaker : CircuitBreaker::new(args.abort_after, args.abort_error_rate)?,
+        think_time : match args.think_time {
+            Some(think_time) => Some(delay_duration(think_time).map_err(CallsyError::Usage)?),
+            None => None,
+        },
+    };
+
+    // The metrics endpoint is served in the background until the run finishes.
+    if let (Some(port), Some(registry)) = (args.metrics_port, &session.live_metrics) {
+        tokio::spawn(crate::metrics::serve(args.metrics_address, port, registry.clone())?);
+    }
+
+    let output_options = OutputOptions {
+        path : &output_path,
+        pattern : args.output_pattern.as_deref(),
+        directory : args.output_dir.as_deref(),
+        ndjson : args.ndjson,
+        show_secrets : args.show_secrets,
+        sync : args.fsync,
+        pretty : args.pretty,
+        canonical : args.canonical,
+        headers : HeaderFilter::new(&args.include_headers, &args.exclude_headers),
+        max_inline_body : match &args.max_inline_body {
+            Some(size) => Some(parse_size(size).map_err(CallsyError::Usage)? as usize),
+            None => None,
+        },
+    };
+
+    let serialized_response = match (&args.collection, &args.data_file) {
+        (Some(collection_path), _) => {
+            if args.body_output_file.is_some() {
+                return Err(CallsyError::Usage(String::from("The body output file option cannot be used when running a collection.")));
+            }
+
+            let filter = crate::collection::Filter {
+                tags : &args.tags,
+                names : &args.names,
+            };
+            let raw_requests = crate::collection::load_collection(collection_path, &filter).map_err(CallsyError::File)?;
+            tracing::info!(collection = ?collection_path, requests = raw_requests.len(), "Running collection");
+
+            // Variables captured from each response are available to the requests which follow it.
+            let mut output_responses = Vec::with_capacity(raw_requests.len());
+            let mut results = Vec::with_capacity(raw_requests.len());
+            let mut lines = Vec::new();
+            for (index, raw_request) in raw_requests.into_iter().enumerate() {
+                if index > 0 {
+                    session.think().await;
+                }
+                let case_name = raw_request.name.clone().unwrap_or_else(|| format!("request {}", index + 1));
+                let description = raw_request.describe();
+                let started = std::time::Instant::now();
+                let sent = send_request(raw_request, &mut session, &mut variables).await;
+                session.add_result(&case_name, description, started, &sent);
+                let tripped = session.is_tripped();
+
+                // Later requests may depend on this one, so the run stops, but the report is still written.
+                let (output_response, captured) = match sent {
+                    Ok(sent) => sent,
+                    Err(error) => {
+                        write_reports(&args, &session)?;
+                        return Err(error);
+                    },
+                };
+                variables.extend(captured.clone());
+                captured_variables.extend(captured);
+
+                // With an output pattern each response is written to its own file, and the output file holds a summary.
+                if output_options.ndjson {
+                    lines.push(ndjson_line(output_response.name.as_deref(), index + 1, Ok(output_options.shown(&output_response)?.as_ref())));
+                }
+                else if output_options.writes_separately() {
+                    let name = output_response.name.clone();
+                    let output_file = output_options.write_batch_output(name.as_deref().unwrap_or("request"), index + 1, &output_response)?;
+                    results.push(RunResult {
+                        iteration : index + 1,
+                        name,
+                        variables : crate::dataset::Row::new(),
+                        output_file : Some(output_file),
+                        status_code : Some(output_response.status_code),
+                        error : None,
+                    });
+                }
+                else {
+                    let mut output_response = output_response;
+                    output_options.prepare(&mut output_response)?;
+                    output_responses.push(output_response);
+                }
+
+                if tripped {
+                    break;
+                }
+            }
+
+            if output_options.ndjson {
+                join_lines(lines)
+            }
+            else if output_options.writes_separately() {
+                output_options.serialize(&RunSummary::new(results).aborted(&session))
+            }
+            else {
+                output_options.serialize(&output_responses)
+            }
+        },
+        (None, maybe_data_path) => {
+            let file_contents = read_request_file(&args.request_file)?;
+            let raw_request = deserialize_request_data(&file_contents)?;
+
+            let rows = match (maybe_data_path, &raw_request.matrix) {
+                (Some(_), Some(_)) => return Err(CallsyError::Usage(String::from("Cannot use a data file with a request which has a matrix."))),
+                (Some(data_path), None) => Some(crate::dataset::load_dataset(data_path).map_err(CallsyError::File)?),
+                (None, Some(matrix)) => Some(crate::dataset::expand_matrix(matrix).map_err(CallsyError::InvalidRequest)?),
+                (None, None) => None,
+            };
+
+            match rows {
+                Some(rows) => {
+                    if args.body_output_file.is_some() {
+                        return Err(CallsyError::Usage(String::from("The body output file option cannot be used with a data file or matrix.")));
+                    }
+
+                    let name = match &raw_request.name {
+                        Some(name) => name.clone(),
+                        None => args.request_file.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default(),
+                    };
+
+                    run_iterations(rows, &file_contents, &name, &output_options, &mut session, &variables, &mut captured_variables).await?
+                },
+                None => {
+                    let mut raw_request = raw_request;
+                    if let Some(download) = config.download.as_ref().filter(|download| download.offset > 0) {
+                        raw_request.set_header("Range", Some(format!("bytes={}-", download.offset)));
+                        if let Some(etag) = read_etag(&download.path) {
+                            raw_request.set_header("If-Range", Some(etag));
+                        }
+                    }
+
+                    let case_name = raw_request.name.clone().unwrap_or_else(|| args.request_file.to_string_lossy().into_owned());
+                    let description = raw_request.describe();
+                    let started = std::time::Instant::now();
+                    let sent = send_request(raw_request, &mut session, &mut variables).await;
+                    session.add_result(&case_name, description, started, &sent);
+
+                    let (output_response, captured) = match sent {
+                        Ok(sent) => sent,
+                        Err(error) => {
+                            write_reports(&args, &session)?;
+                            return Err(error);
+                        },
+                    };
+                    captured_variables.extend(captured);
+                    let body_output_file = match &args.body_output_file {
+                        Some(path) if args.infer_body_name => {
+                            let path = crate::naming::infer_body_output_path(path, &output_response.headers);
+                            check_output_file(&path)?;
+                            Some(path)
+                        },
+                        maybe_path => maybe_path.clone(),
+                    };
+                    // When continuing a download, the body has already been written as it arrived.
+                    if config.download.is_none() {
+                        open_and_write_to_body_output_file(&body_output_file, output_response.body_bytes(), args.fsync)?;
+                    }
+                    let mut output_response = output_response;
+                    if output_response.binary_body.is_some() && body_output_file.is_some() {
+                        output_response.body_file = body_output_file;
+                    }
+                    if output_options.ndjson {
+                        join_lines(vec![ndjson_line(None, 1, Ok(output_options.shown(&output_response)?.as_ref()))])
+                    }
+                    else if output_options.directory.is_some() {
+                        let name = output_response.name.clone();
+                        let file_name = args.request_file.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+                        let output_file = output_options.write_batch_output(name.as_deref().unwrap_or(&file_name), 1, &output_response)?;
+                        output_options.serialize(&RunSummary::new(vec![
+                            RunResult {
+                                iteration : 1,
+                                name,
+                                variables : crate::dataset::Row::new(),
+                                output_file : Some(output_file),
+                                status_code : Some(output_response.status_code),
+                                error : None,
+                            }
+                        ]))
+                    }
+                    else {
+                        output_options.serialize(&output_options.shown(&output_response)?)
+                    }
+                },
+            }
+        },
+    };
+
+    write_output(&output_path, serialized_response, args.show_secrets, args.fsync)?;
+
+    if let (Some(path), Some(cassette)) = (&args.record, &session.recording) {
+        write_output(path, serialize_response(cassette), args.show_secrets, args.fsync)?;
+    }
+
+    write_reports(&args, &session)?;
+
+    if let Some(path) = save_captures_path {
+        crate::variables::save_to_environment(path, &captured_variables, args.fsync).map_err(CallsyError::File)?;
+    }
+
+    // The output of a run which was stopped early is still written, with the reason in the summary.
+    match session.breaker.and_then(|breaker| breaker.tripped) {
+        Some(reason) => Err(CallsyError::Aborted(reason)),
+        None => Ok(()),
+    }
+}
+
+struct OutputOptions<'a> {
+    path : &'a std::path::Path,
+    pattern : Option<&'a str>,
+    // Writes the body and the rest of each response to separate files in this directory.
+    directory : Option<&'a std::path::Path>,
+    // Writes every response to the output file as one line of JSON each, instead of to separate files.
+    ndjson : bool,
+    show_secrets : bool,
+    sync : bool,
+    // Formats bodies written to their own file for reading.
+    pretty : bool,
+    // Writes output files with sorted keys and one value to a line, so that they diff well between runs.
+    canonical : bool,
+    headers : HeaderFilter,
+    // Bodies longer than this many bytes are cut short in the output, with the whole body in a file beside it.
+    max_inline_body : Option<usize>,
+}
+
+// The response headers to write to the output, from --include-headers and --exclude-headers, where *
+// matches any sequence of characters. Other headers are still there for captures and naming files.
+struct HeaderFilter {
+    include : Vec<String>,
+    exclude : Vec<String>,
+}
+
+impl HeaderFilter {
+    fn new(include : &[String], exclude : &[String]) -> HeaderFilter {
+        let lowercase = |patterns : &[String]| patterns.iter().map(|pattern| pattern.to_lowercase()).collect();
+        HeaderFilter {
+            include : lowercase(include),
+            exclude : lowercase(exclude),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    // A header is written if it matches any of the include patterns, when there are some, and none of the
+    // exclude patterns.
+    fn shows(&self, header : &str) -> bool {
+        let header = header.to_lowercase();
+        let included = self.include.is_empty() || self.include.iter().any(|pattern| crate::collection::matches_pattern(pattern, &header));
+        included && !self.exclude.iter().any(|pattern| crate::collection::matches_pattern(pattern, &header))
+    }
+
+    fn apply(&self, output_response : &mut OutputResponse) {
+        if self.is_empty() {
+            return;
+        }
+        output_response.headers.retain(|header, _| self.shows(header));
+        if let Some(location_response) = &mut output_response.location_response {
+            self.apply(location_response);
+        }
+    }
+}
+
+impl<'a> OutputOptions<'a> {
+    // Bodies written to a directory are already in files of their own, so are never cut short.
+    fn truncates(&self, output_response : &OutputResponse) -> bool {
+        self.directory.is_none() && self.max_inline_body.is_some_and(|limit| output_response.body.len() > limit)
+    }
+
+    // Leaves out filtered headers, and cuts the body short if it is over --max-inline-body, after writing
+    // the whole of it to a file beside the output file. Secrets are masked before the body is written or
+    // cut, so that neither the file nor a secret cut in two at the end of the body gives one away.
+    fn prepare(&self, output_response : &mut OutputResponse) -> Result<(), CallsyError> {
+        if let Some(limit) = self.max_inline_body.filter(|_| self.truncates(output_response)) {
+            let mut body = if self.show_secrets { output_response.body.clone() } else { crate::masking::mask(&output_response.body) };
+            if body.len() > limit {
+                let path = crate::naming::sidecar_path(self.path, body.as_bytes(), crate::naming::content_type_extension(&output_response.headers));
+                write_atomically(&path, body.as_bytes(), self.sync)?;
+                tracing::debug!(?path, size = body.len(), "Wrote the whole of a truncated body to a file");
+
+                output_response.body_truncated = Some(TruncatedBody { size : body.len(), file : path });
+                let mut end = limit;
+                while !body.is_char_boundary(end) {
+                    end -= 1;
+                }
+                body.truncate(end);
+            }
+            output_response.body = body;
+        }
+        self.headers.apply(output_response);
+
+        Ok(())
+    }
+
+    // The response as it is written out, which is only copied when it needs changing.
+    fn shown<'r>(&self, output_response : &'r OutputResponse) -> Result<std::borrow::Cow<'r, OutputResponse>, CallsyError> {
+        if self.headers.is_empty() && !self.truncates(output_response) {
+            return Ok(std::borrow::Cow::Borrowed(output_response));
+        }
+
+        let mut shown = output_response.clone();
+        self.prepare(&mut shown)?;
+        Ok(std::borrow::Cow::Owned(shown))
+    }
+
+    fn serialize<T : serde::Serialize>(&self, value : &T) -> String {
+        match self.canonical {
+            true => serialize_canonical(value),
+            false => serialize_response(value),
+        }
+    }
+
+    // Whether each response is written to its own file, with a summary of the run in the output file.
+    fn writes_separately(&self) -> bool {
+        self.pattern.is_some() || self.directory.is_some()
+    }
+
+    // The path to write the response of one request in a batch to, which comes from the output pattern
+    // if there is one, and is otherwise numbered after the output file, such as response-1.json.
+    fn batch_output_path(&self, name : &str, iteration : usize, status_code : u16) -> std::path::PathBuf {
+        match self.pattern {
+            Some(pattern) => {
+                std::path::PathBuf::from(
+                    pattern
+                    .replace("{name}", name)
+                    .replace("{iteration}", &iteration.to_string())
+                    .replace("{status}", &status_code.to_string())
+                )
+            },
+            None => get_iteration_output_path(self.path, iteration),
+        }
+    }
+
+    fn write_batch_output(&self, name : &str, iteration : usize, output_response : &OutputResponse) -> Result<std::path::PathBuf, CallsyError> {
+        if let Some(directory) = self.directory {
+            return self.write_to_directory(directory, name, iteration, output_response);
+        }
+
+        let path = self.batch_output_path(name, iteration, output_response.status_code);
+
+        // Paths from a pattern depend on the response, so cannot be checked before the run.
+        if self.pattern.is_some() {
+            check_output_file(&path)?;
+
+            if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                if let Err(source) = std::fs::create_dir_all(parent) {
+                    return Err(CallsyError::Io { message : format!("Failed to create output directory {:?}. {}", parent, source), source });
+                }
+            }
+        }
+
+        write_output(&path, self.serialize(&self.shown(output_response)?), self.show_secrets, self.sync)?;
+
+        Ok(path)
+    }
+
+    // Writes the body of a response with an extension matching its Content-Type, and the rest of the
+    // response beside it, such as 001-login.json and 001-login.body.json. Returns the path of the latter.
+    fn write_to_directory(&self, directory : &std::path::Path, name : &str, iteration : usize, output_response : &OutputResponse) -> Result<std::path::PathBuf, CallsyError> {
+        let stem = format!("{:03}-{}", iteration, crate::naming::file_stem(name));
+        let body_file_name = match crate::naming::content_type_extension(&output_response.headers) {
+            Some(extension) => format!("{}.body.{}", stem, extension),
+            None => format!("{}.body", stem),
+        };
+        let body_path = directory.join(&body_file_name);
+        let path = directory.join(format!("{}.json", stem));
+        check_output_file(&body_path)?;
+        check_output_file(&path)?;
+
+        let mut metadata = match serde_json::to_value(self.shown(output_response)?) {
+            Ok(serde_json::Value::Object(metadata)) => metadata,
+            _ => panic!("Internal error, could not serialize JSON data for response"),
+        };
+        metadata.remove("body");
+        metadata.insert(String::from("body_file"), serde_json::Value::from(body_file_name));
+
+        match &output_response.binary_body {
+            Some(bytes) => write_atomically(&body_path, bytes, self.sync)?,
+            None => write_output(&body_path, output_response.formatted_body(self.pretty), self.show_secrets, self.sync)?,
+        }
+        write_output(&path, self.serialize(&metadata), self.show_secrets, self.sync)?;
+
+        Ok(path)
+    }
+}
+
+// Runs the request once for each row of variables, writing the output of each iteration to its
+// own file and returning the serialized summary of the run.
+async fn run_iterations(rows : Vec<crate::dataset::Row>, file_contents : &str, name : &str, output_options : &OutputOptions<'_>, session : &mut Session<'_>, variables : &crate::variables::Variables, captured_variables : &mut crate::variables::Variables) -> Result<String, CallsyError> {
+    if !output_options.writes_separately() && !output_options.ndjson {
+        for iteration in 1..=rows.len() {
+            check_output_file(&get_iteration_output_path(output_options.path, iteration))?;
+        }
+    }
+
+    tracing::info!(name, iterations = rows.len(), "Running data driven request");
+
+    let mut results = Vec::with_capacity(rows.len());
+    let mut lines = Vec::new();
+    for (index, row) in rows.into_iter().enumerate() {
+        let iteration = index + 1;
+        if index > 0 {
+            session.think().await;
+        }
+        tracing::debug!(name, iteration, "Starting iteration");
+
+        let mut iteration_variables = variables.clone();
+        iteration_variables.extend(row.clone());
+
+        let raw_request = deserialize_request_data(file_contents)?;
+        let description = raw_request.describe();
+        let started = std::time::Instant::now();
+        let sent = send_request(raw_request, session, &mut iteration_variables).await;
+        session.add_result(&format!("{} {}", name, iteration), description, started, &sent);
+
+        if output_options.ndjson {
+            match sent {
+                Ok((output_response, captured)) => {
+                    captured_variables.extend(captured);
+                    lines.push(ndjson_line(Some(name), iteration, Ok(output_options.shown(&output_response)?.as_ref())));
+                },
+                Err(error) => lines.push(ndjson_line(Some(name), iteration, Err(&error))),
+            }
+            if session.is_tripped() {
+                break;
+            }
+            continue;
+        }
+
+        let result = match sent {
+            Ok((output_response, captured)) => {
+                captured_variables.extend(captured);
+                let output_file = output_options.write_batch_output(name, iteration, &output_response)?;
+                RunResult {
+                    iteration,
+                    name : None,
+                    variables : row,
+                    output_file : Some(output_file),
+                    status_code : Some(output_response.status_code),
+                    error : None,
+                }
+            },
+            Err(error) => RunResult {
+                iteration,
+                name : None,
+                variables : row,
+                output_file : None,
+                status_code : None,
+                error : Some(error),
+            },
+        };
+        results.push(result);
+
+        if session.is_tripped() {
+            break;
+        }
+    }
+
+    if output_options.ndjson {
+        return Ok(join_lines(lines));
+    }
+
+    Ok(output_options.serialize(&RunSummary::new(results).aborted(session)))
+}
+
+// One line of NDJSON output, which is the response (or the error) annotated with the request name and iteration.
+fn ndjson_line(name : Option<&str>, iteration : usize, result : Result<&OutputResponse, &CallsyError>) -> String {
+    let mut line = serde_json::Map::new();
+    if let Some(name) = name {
+        line.insert(String::from("name"), serde_json::Value::from(name));
+    }
+    line.insert(String::from("iteration"), serde_json::Value::from(iteration));
+
+    let fields = match result {
+        Ok(output_response) => serde_json::to_value(output_response),
+        Err(error) => serde_json::to_value(error).map(|error| serde_json::json!({ "error" : error })),
+    };
+    match fields {
+        Ok(serde_json::Value::Object(fields)) => line.extend(fields),
+        _ => panic!("Internal error, could not serialize JSON data for response"),
+    }
+
+    serialize_response(&line)
+}
+
+fn join_lines(lines : Vec<String>) -> String {
+    lines.into_iter().map(|line| line + "\n").collect()
+}
+
+async fn run_subcommand(command : Command, config_path : &Option<std::path::PathBuf>, profile : &Option<String>) -> Result<(), CallsyError> {
+    match command {
+        Command::Secret { action : SecretAction::Set { name } } => {
+            let secret = match rpassword::prompt_password(format!("Value for secret {}: ", name)) {
+                Ok(secret) => secret,
+                Err(source) => return Err(CallsyError::Io { message : format!("Failed to read the secret. {}", source), source }),
+            };
+            crate::secrets::set_secret(&name, &secret).map_err(CallsyError::Secret)
+        },
+        Command::Secret { action : SecretAction::Get { name } } => {
+            println!("{}", crate::secrets::get_secret(&name).map_err(CallsyError::Secret)?);
+            Ok(())
+        },
+        Command::Certcheck { url, threshold } => {
+            let config = crate::config::load_config(config_path, profile).map_err(CallsyError::Config)?;
+            check_certificate_expiry(&url, threshold, &config).await
+        },
+        Command::Mock { port, routes } => crate::mock::serve(port, &routes).await,
+        Command::Listen { port, address, directory, count, send : None } => crate::listen::bind(address, port, directory, count)?.await,
+        Command::Listen { port, address, directory, count, send : Some(request_file) } => {
+            let config = crate::config::load_config(config_path, profile).map_err(CallsyError::Config)?;
+            let output_path = get_output_path(&None, &config);
+            check_output_file(&output_path)?;
+
+            // The listener runs in the background, and is abandoned if the request fails.
+            let listener = tokio::spawn(crate::listen::bind(address, port, directory, count)?);
+            send_request_file(&request_file, &output_path, &config).await?;
+            match listener.await {
+                Ok(listened) => listened,
+                Err(error) => Err(CallsyError::Io { message : format!("The listener stopped unexpectedly. {}", error), source : std::io::Error::from(error) }),
+            }
+        },
+        Command::Tui { collection, env } => {
+            let config = crate::config::load_config(config_path, profile).map_err(CallsyError::Config)?;
+            let variables = load_variables(&env, &config)?;
+            let filter = crate::collection::Filter {
+                tags : &[],
+                names : &[],
+            };
+            let raw_requests = crate::collection::load_collection(&collection, &filter).map_err(CallsyError::File)?;
+
+            let mut session = Session::new(&config);
+
+            crate::tui::run(raw_requests, variables, &mut session).await
+        },
+        Command::Repl { env } => {
+            let config = crate::config::load_config(config_path, profile).map_err(CallsyError::Config)?;
+            let variables = load_variables(&env, &config)?;
+
+            let mut session = Session::new(&config);
+
+            crate::repl::run(variables, &mut session).await
+        },
+        Command::Compare { request_file, env, ignore } => {
+            let config = crate::config::load_config(config_path, profile).map_err(CallsyError::Config)?;
+            let file_contents = read_request_file(&request_file)?;
+
+            // Each environment has a session of its own, and the requests are sent concurrently.
+            let sends = env.iter().map(|env_path| {
+                let config = &config;
+                let file_contents = &file_contents;
+                async move {
+                    let raw_request = deserialize_request_data(file_contents)?;
+                    let mut variables = load_variables(&Some(env_path.clone()), config)?;
+                    let mut session = Session::new(config);
+                    send_request(raw_request, &mut session, &mut variables).await
+                }
+            });
+            let results = futures_util::future::join_all(sends).await;
+
+            let responses = env
+                .iter()
+                .zip(results)
+                .map(|(env_path, result)| {
+                    let response = match result {
+                        Ok((output_response, _)) => output_response.comparable(),
+                        Err(error) => serde_json::json!({ "error" : error }),
+                    };
+                    (env_path.to_string_lossy().into_owned(), response)
+                })
+                .collect::<Vec<_>>();
+
+            let comparison = serde_json::json!({
+                "environments" : responses.iter().map(|(environment, _)| environment).collect::<Vec<_>>(),
+                "differences" : crate::compare::differences(&responses, &ignore),
+            });
+            match serde_json::to_string_pretty(&comparison) {
+                Ok(serialized) => println!("{}", crate::masking::mask(&serialized)),
+                Err(_) => panic!("Internal error, could not serialize JSON data for comparison"),
+            }
+            Ok(())
+        },
+        Command::Serve { collection, env, port } => {
+            let config = crate::config::load_config(config_path, profile).map_err(CallsyError::Config)?;
+            let variables = load_variables(&env, &config)?;
+            let filter = crate::collection::Filter {
+                tags : &[],
+                names : &[],
+            };
+            let raw_requests = crate::collection::load_collection(&collection, &filter).map_err(CallsyError::File)?;
+
+            let mut session = Session::new(&config);
+            session.history = crate::history::History::open(None, config.history_database.as_deref(), false)?;
+
+            crate::serve::serve(port, raw_requests, variables, &mut session).await
+        },
+        Command::Schedule { cron, request_file, env, append_to, on_failure_command, on_failure_webhook } => {
+            let cron = crate::schedule::parse(&cron)?;
+            let config = crate::config::load_config(config_path, profile).map_err(CallsyError::Config)?;
+            let file_contents = read_request_file(&request_file)?;
+            let raw_request = deserialize_request_data(&file_contents)?;
+            let variables = load_variables(&env, &config)?;
+            let name = raw_request.name.clone().unwrap_or_else(|| request_file.to_string_lossy().into_owned());
+
+            let mut session = Session::new(&config);
+            session.history = crate::history::History::open(append_to.as_deref(), config.history_database.as_deref(), false)?;
+
+            let alerts = crate::schedule::Alerts {
+                command : on_failure_command,
+                webhook : on_failure_webhook,
+            };
+            crate::schedule::run(&cron, &name, raw_request, &variables, &mut session, &alerts).await
+        },
+        Command::History { action : HistoryAction::Search { url_contains, status, since, name, limit } } => {
+            let config = crate::config::load_config(config_path, profile).map_err(CallsyError::Config)?;
+            let database_path = match &config.history_database {
+                Some(path) => path,
+                None => return Err(CallsyError::Config(String::from("There is no history_database set in the config file to search."))),
+            };
+
+            let search = crate::history::Search { url_contains, status, since, name, limit };
+            for entry in crate::history::search(database_path, &search)? {
+                println!("{}", entry);
+            }
+            Ok(())
+        },
+        Command::Schema { collection, files } if files.is_empty() => {
+            match serde_json::to_string_pretty(&crate::schema::schema(collection)) {
+                Ok(serialized) => println!("{}", serialized),
+                Err(_) => panic!("Internal error, could not serialize JSON data for schema"),
+            }
+            Ok(())
+        },
+        Command::Schema { collection, files } => crate::schema::validate(&files, collection),
+        Command::Jwt { action : JwtAction::Decode { token } } => {
+            let decoded = crate::jwt::decode(&token).map_err(CallsyError::InvalidRequest)?;
+            match serde_json::to_string_pretty(&decoded) {
+                Ok(serialized) => println!("{}", serialized),
+                Err(_) => panic!("Internal error, could not serialize JSON data for token"),
+            }
+            Ok(())
+        },
+    }
+}
+
+// Sends a single request alongside another command, writing the response to the output file.
+async fn send_request_file(request_file : &std::path::PathBuf, output_path : &std::path::PathBuf, config : &crate::config::Config) -> Result<(), CallsyError> {
+    let file_contents = read_request_file(request_file)?;
+    let raw_request = deserialize_request_data(&file_contents)?;
+    let mut variables = load_variables(&None, config)?;
+
+    let mut session = Session::new(config);
+
+    let (output_response, _) = send_request(raw_request, &mut session, &mut variables).await?;
+    write_output(output_path, serialize_response(&output_response), false, false)
+}
+
+pub(crate) async fn send_request(mut raw_request : RawRequest, session : &mut Session<'_>, variables : &mut crate::variables::Variables) -> Result<(OutputResponse, crate::variables::Variables), CallsyError> {
+    let config = session.config;
+    check_extra_fields(&raw_request, config.strict)?;
+    if let Some(range) = raw_request.range.clone() {
+        if raw_request.headers.keys().any(|header| header.eq_ignore_ascii_case("range")) {
+            return Err(CallsyError::InvalidRequest(String::from("Cannot provide a range along with a Range header.")));
+        }
+        raw_request.set_header("Range", Some(range.header()?));
+    }
+    if let Some(prefer) = &raw_request.prefer {
+        if raw_request.headers.keys().any(|header| header.eq_ignore_ascii_case("accept")) {
+            return Err(CallsyError::InvalidRequest(String::from("Cannot provide prefer along with an Accept header.")));
+        }
+        let accept = prefer.accept().map_err(CallsyError::InvalidRequest)?;
+        raw_request.set_header("Accept", Some(accept));
+    }
+    let delay_after = match raw_request.delay_after {
+        Some(delay_after) => Some(delay_duration(delay_after).map_err(CallsyError::InvalidRequest)?),
+        None => None,
+    };
+    if let Some(delay_before) = raw_request.delay_before {
+        session.pause(delay_duration(delay_before).map_err(CallsyError::InvalidRequest)?).await;
+    }
+    let has_range = raw_request.range.is_some();
+    let prefer = raw_request.prefer.clone();
+    let expect_checksum = raw_request.expect_checksum.clone();
+    let name = raw_request.name.clone();
+    let captures = raw_request.capture.clone();
+    let body = match &raw_request.body_template {
+        Some(path) => {
+            if raw_request.body.is_some() || raw_request.body_path.is_some() {
+                return Err(CallsyError::InvalidRequest(String::from("Cannot provide a body_template along with a body or body_path.")));
+            }
+            crate::template::render_body_template(path, variables, config).map_err(CallsyError::InvalidRequest)?
+        },
+        None => {
+            let body = get_body(&raw_request)?;
+            crate::variables::substitute(&body, variables, config).map_err(CallsyError::InvalidRequest)?
+        },
+    };
+    let proto = match &raw_request.proto {
+        Some(proto) => Some(proto.compile().map_err(CallsyError::InvalidRequest)?),
+        None => None,
+    };
+    let body = match raw_request.body_type {
+        Some(body_type) => {
+            for (header, value) in body_type.headers() {
+                if !raw_request.headers.keys().any(|existing| existing.eq_ignore_ascii_case(header)) {
+                    raw_request.set_header(header, Some(String::from(*value)));
+                }
+            }
+            body_type.encode(&body, proto.as_ref()).map_err(CallsyError::InvalidRequest)?
+        },
+        None => body.into_bytes(),
+    };
+    let body_type = raw_request.body_type;
+    let raw_request = substitute_variables(raw_request, variables, config)?;
+    tracing::debug!(name = ?name, "Substituted variables into the request");
+    let location_request = if raw_request.follow_location { Some(raw_request.clone()) } else { None };
+
+    let mut output_response = match raw_request.paginate.clone() {
+        Some(paginate) => fetch_pages(&paginate, name, raw_request, body, session).await?,
+        None => exchange_with_failover(name, raw_request, body, session).await?.0,
+    };
+    match body_type {
+        Some(BodyType::GrpcWeb | BodyType::GrpcWebText) => output_response.decode_grpc_web(proto.as_ref()),
+        Some(BodyType::Jsonrpc) => output_response.decode_jsonrpc(),
+        Some(BodyType::Protobuf | BodyType::Msgpack | BodyType::Cbor) | None => {
+            if let Some(proto) = proto.as_ref().filter(|proto| proto.has_response()) {
+                output_response.decode_protobuf(proto);
+            }
+        },
+    }
+    output_response.decode_msgpack();
+    output_response.decode_cbor();
+    if has_range {
+        output_response.range_satisfied = Some(output_response.status_code == 206);
+    }
+    if let Some(prefer) = &prefer {
+        output_response.check_negotiation(prefer);
+    }
+
+    let output_response = match location_request {
+        Some(location_request) => follow_location(output_response, location_request, session).await?,
+        None => output_response,
+    };
+
+    // The body which was downloaded is that of the location, when it was followed.
+    if let Some(checksum) = expect_checksum {
+        checksum.verify(output_response.location_response.as_deref().unwrap_or(&output_response))?;
+    }
+
+    let captured = capture_variables(&captures, &output_response).map_err(CallsyError::Capture)?;
+    if let Some(delay_after) = delay_after {
+        session.pause(delay_after).await;
+    }
+    Ok((output_response, captured))
+}
+
+fn delay_duration(seconds : f64) -> Result<std::time::Duration, String> {
+    match std::time::Duration::try_from_secs_f64(seconds) {
+        Ok(duration) => Ok(duration),
+        Err(_) => Err(format!("The delay of {} seconds is not a number of seconds which is 0 or more.", seconds)),
+    }
+}
+
+// Fetches the resource given by the Location header of a 201 or 3xx response with a GET, using the
+// headers of the original request other than those describing its body.
+async fn follow_location(output_response : OutputResponse, mut raw_request : RawRequest, session : &mut Session<'_>) -> Result<OutputResponse, CallsyError> {
+    let config = session.config;
+
+    if !(output_response.status_code == 201 || (300..400).contains(&output_response.status_code)) {
+        return Ok(output_response);
+    }
+
+    let location = output_response.headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case("location"))
+        .and_then(|(_, values)| values.first());
+
+    let location = match location {
+        Some(location) => location,
+        None => return Ok(output_response),
+    };
+
+    let url = parse_url(&raw_request.url, &config.base_url).map_err(CallsyError::InvalidRequest)?;
+    let location_url = match url.join(location) {
+        Ok(location_url) => location_url,
+        Err(error) => return Err(CallsyError::InvalidRequest(format!("The location {} of the response is not a valid URL. {}", location, error))),
+    };
+
+    tracing::debug!(location = %location_url, "Following location of response");
+    raw_request.url = location_url.to_string();
+    raw_request.method = String::from("GET");
+    raw_request.headers.retain(|header, _| !header.eq_ignore_ascii_case("content-type") && !header.eq_ignore_ascii_case("content-length"));
+
+    let processed_request = process_request_data(raw_request, Vec::new(), config)?;
+    let location_response = exchange(None, processed_request, session).await?;
+
+    Ok(OutputResponse {
+        location_response : Some(Box::new(location_response)),
+        ..output_response
+    })
+}
+
+// Errors for which the request never reached the server, so it is safe to send it to another one.
+fn is_connection_failure(error : &CallsyError) -> bool {
+    match error {
+        CallsyError::Connection { .. } => true,
+        CallsyError::Timeout { source, .. } => source.is_connect(),
+        _ => false,
+    }
+}
+
+// Sends a request with a relative URL to each of the base URLs in turn, moving on to the next when the
+// connection fails or the response has one of the failover statuses. The last is used whatever happens.
+// The request is prepared once, with the first base URL, and only its URL is changed for the others, so
+// that each attempt has the same autocompleted headers, such as its idempotency key, date and signature.
+// Returns the response along with the URL it was sent to.
+async fn exchange_with_failover(name : Option<String>, raw_request : RawRequest, body : Vec<u8>, session : &mut Session<'_>) -> Result<(OutputResponse, Url), CallsyError> {
+    let config = session.config;
+    let base_urls = match (&raw_request.base_urls, &config.base_urls) {
+        (Some(base_urls), _) | (None, Some(base_urls)) if Url::parse(&raw_request.url).is_err() => base_urls.clone(),
+        _ => Vec::new(),
+    };
+
+    let urls = base_urls
+        .iter()
+        .map(|base_url| parse_url(&raw_request.url, &Some(base_url.clone())).map_err(CallsyError::InvalidRequest))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let failover_statuses = config.defaults.merged_under(&raw_request.settings).failover_statuses.unwrap_or_default();
+
+    let mut attempt = raw_request;
+    if let Some(first) = urls.first() {
+        attempt.url = first.to_string();
+    }
+    let processed_request = process_request_data(attempt, body, config)?;
+
+    let (last, others) = match base_urls.split_last() {
+        Some(split) => split,
+        None => {
+            let url = processed_request.url.clone();
+            return Ok((exchange(name, processed_request, session).await?, url));
+        },
+    };
+
+    for (base_url, url) in others.iter().zip(&urls) {
+        let processed_request = ProcessedRequest { url : url.clone(), ..processed_request.clone() };
+
+        match exchange(name.clone(), processed_request, session).await {
+            Ok(output_response) if failover_statuses.contains(&output_response.status_code) => {
+                tracing::warn!(%base_url, status_code = output_response.status_code, "Failing over to the next base URL");
+            },
+            Ok(output_response) => return Ok((OutputResponse { served_by : Some(base_url.clone()), ..output_response }, url.clone())),
+            Err(error) if is_connection_failure(&error) => {
+                tracing::warn!(%base_url, %error, "Failing over to the next base URL");
+            },
+            Err(error) => return Err(error),
+        }
+    }
+
+    let url = urls[others.len()].clone();
+    let output_response = exchange(name, ProcessedRequest { url : url.clone(), ..processed_request }, session).await?;
+
+    Ok((OutputResponse { served_by : Some(last.clone()), ..output_response }, url))
+}
+
+// Sends the request, or replays its response from the cassette, recording the response if needed.
+async fn exchange(name : Option<String>, processed_request : ProcessedRequest, session : &mut Session<'_>) -> Result<OutputResponse, CallsyError> {
+    let config = session.config;
+    let recorded_request = crate::cassette::RecordedRequest::new(&processed_request.method, &processed_request.url, &processed_request.headers, &processed_request.body);
+    let processed_request_url = processed_request.url.clone();
+    let idempotency_key = processed_request.headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case("idempotency-key"))
+        .map(|(_, value)| value.clone());
+
+    let output_response = match &session.replay {
+        Some(cassette) => {
+            match cassette.replay(&recorded_request) {
+                Some(output_response) => {
+                    tracing::info!(method = %processed_request.method, url = %processed_request.url, "Replayed response from cassette");
+                    OutputResponse { name, ..output_response.clone() }
+                },
+                None => return Err(CallsyError::NotInCassette(format!("The cassette has no recorded response to {} {}.", processed_request.method, processed_request.url))),
+            }
+        },
+        None if config.offline => {
+            return Err(CallsyError::Offline(format!("Cannot send {} {} while offline.", processed_request.method, processed_request.url)));
+        },
+        None => {
+            if let Some(sent) = &mut session.sent {
+                sent.push(recorded_request.clone());
+            }
+            let output_response = fetch_response(name, processed_request, session).await?;
+            if let Some(cassette) = &mut session.recording {
+                cassette.record(recorded_request.clone(), output_response.clone());
+            }
+            output_response
+        },
+    };
+
+    let request = config.include_request.then_some(recorded_request);
+    Ok(OutputResponse { idempotency_key, request, url : Some(processed_request_url), ..output_response })
+}
+
+// Requests each page in turn, up to the page limit, and combines their items into the body of the
+// first response. Each page is prepared again, so that autocompleted headers match its URL.
+async fn fetch_pages(paginate : &crate::pagination::Paginate, name : Option<String>, mut raw_request : RawRequest, body : Vec<u8>, session : &mut Session<'_>) -> Result<OutputResponse, CallsyError> {
+    let mut combined : Option<OutputResponse> = None;
+    let mut items = Vec::new();
+    let mut pages = 0;
+
+    while pages < paginate.max_pages() {
+        let (output_response, url) = exchange_with_failover(name.clone(), raw_request.clone(), body.clone(), session).await?;
+        pages += 1;
+
+        if output_response.status_code >= 400 {
+            return Err(CallsyError::InvalidRequest(format!("Stopped paginating as page {} ({}) responded with status code {}.", pages, url, output_response.status_code)));
+        }
+
+        tracing::debug!(page = pages, %url, "Fetched page");
+        items.extend(paginate.items(&output_response.body).map_err(CallsyError::InvalidRequest)?);
+        let next_url = paginate.next_url(&url, &output_response.headers, &output_response.body).map_err(CallsyError::InvalidRequest)?;
+
+        if combined.is_none() {
+            combined = Some(output_response);
+        }
+
+        match next_url {
+            Some(next_url) if next_url != url => raw_request.url = next_url.to_string(),
+            _ => break,
+        }
+    }
+
+    match combined {
+        Some(combined) => {
+            let body = serialize_response(&items);
+            Ok(OutputResponse {
+                body_sha256 : Some(sha256_hex(body.as_bytes())),
+                body,
+                received_body : None,
+                pages : Some(pages),
+                ..combined
+            })
+        },
+        None => Err(CallsyError::InvalidRequest(String::from("Cannot paginate with a max_pages of 0."))),
+    }
+}
+
+async fn fetch_response(name : Option<String>, processed_request : ProcessedRequest, session : &mut Session<'_>) -> Result<OutputResponse, CallsyError> {
+    let measured = session.metrics.is_some() || session.live_metrics.is_some();
+    let attempt = if measured { Some(crate::metrics::Attempt::start(name.clone(), &processed_request.method, &processed_request.url)) } else { None };
+    let mut timing = crate::metrics::Timing::default();
+    let started = std::time::SystemTime::now();
+    let span = match (&processed_request.trace, &processed_request.settings.otlp_endpoint) {
+        (Some(trace), Some(endpoint)) => Some((trace.clone(), endpoint.clone(), processed_request.method.to_string(), processed_request.url.to_string())),
+        _ => None,
+    };
+
+    let fetched = fetch_and_convert(name, processed_request, session, &mut timing).await;
+    let completed = std::time::SystemTime::now();
+
+    if let Some((trace, endpoint, method, url)) = span {
+        let client = session.clients.get(session.config, &session.config.defaults)?;
+        let span = crate::trace::Span {
+            method : &method,
+            url : &url,
+            start : started,
+            end : completed,
+            status_code : fetched.as_ref().ok().map(|output_response| output_response.status_code),
+            error : fetched.as_ref().err().map(|error| error.category()),
+        };
+        trace.export(&client, &endpoint, span).await;
+    }
+
+    if let Some(attempt) = attempt {
+        let attempt = attempt.finish(&timing, &fetched);
+        if let Some(registry) = &session.live_metrics {
+            registry.observe(&attempt);
+        }
+        if let Some(metrics) = &mut session.metrics {
+            metrics.push(attempt);
+        }
+    }
+
+    fetched.map(|output_response| OutputResponse {
+        started : Some(timestamp(started)),
+        completed : Some(timestamp(completed)),
+        duration : Some(completed.duration_since(started).unwrap_or_default().as_secs_f64()),
+        ..output_response
+    })
+}
+
+pub(crate) fn timestamp(time : std::time::SystemTime) -> String {
+    chrono::DateTime::<chrono::Local>::from(time).to_rfc3339_opts(chrono::SecondsFormat::Millis, false)
+}
+
+async fn fetch_and_convert(name : Option<String>, processed_request : ProcessedRequest, session : &mut Session<'_>, timing : &mut crate::metrics::Timing) -> Result<OutputResponse, CallsyError> {
+    let client = session.clients.get(session.config, &processed_request.settings)?;
+    // Pins are checked during the TLS handshake, so a request without TLS would never be checked.
+    let pinned = processed_request.settings.tls.as_ref().is_some_and(|tls| tls.pins.is_some());
+    if pinned && processed_request.url.scheme() != "https" {
+        return Err(CallsyError::UntrustedCertificate(format!("Refused to send the request to {}, as certificate pins are given but the URL is not HTTPS.", processed_request.url)));
+    }
+    let method = processed_request.method.clone();
+    let url = processed_request.url.clone();
+    let started = std::time::Instant::now();
+
+    tracing::info!(%method, %url, headers = processed_request.headers.len(), body_length = processed_request.body.len(), "Sending request");
+    let (response, observation) = crate::connection::observe(make_request(&client, processed_request)).await;
+    timing.dns = observation.dns;
+    let response = response?;
+    timing.first_byte = Some(std::time::Instant::now());
+    let handshake = session.clients.handshake(&url, observation.handshake);
+    tracing::info!(%method, %url, status = response.status().as_u16(), elapsed = ?started.elapsed(), "Received response headers");
+
+    let connection = response.extensions().get::<hyper::client::connect::HttpInfo>().map(|info| (info.local_addr(), info.remote_addr()));
+    let connection_reused = connection.map(|connection| session.clients.is_reused(connection));
+
+    let output_response = convert_response(name, response, session.config).await?;
+    Ok(OutputResponse {
+        local_address : connection.map(|(local_address, _)| local_address),
+        connection_reused,
+        certificate_chain : handshake.as_ref().map(|handshake| handshake.certificates.iter().filter_map(|der| crate::certificate::describe(der)).collect()),
+        tls_version : handshake.and_then(|handshake| handshake.tls_version).map(String::from),
+        ..output_response
+    })
+}
+
+// Parses a size such as 512, 10MB or 1.5GiB into bytes.
+fn parse_size(size : &str) -> Result<u64, String> {
+    let size = size.trim();
+    let split = size.find(|character : char| !character.is_ascii_digit() && character != '.').unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+
+    let multiplier = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "kb" => 1e3,
+        "mb" => 1e6,
+        "gb" => 1e9,
+        "kib" => 1024.0,
+        "mib" => 1024.0 * 1024.0,
+        "gib" => 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(format!("The size unit {} is not supported, use B, KB, MB, GB, KiB, MiB or GiB.", unit.trim())),
+    };
+
+    match number.parse::<f64>() {
+        Ok(number) => Ok((number * multiplier) as u64),
+        Err(_) => Err(format!("The size {} is invalid.", size)),
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema)]
+#[schemars(rename = "Request", deny_unknown_fields, transform = crate::schema::file_fields)]
+pub(crate) struct RawRequest {
+    pub(crate) name : Option<String>,
+    #[serde(default)]
+    pub(crate) tags : Vec<String>,
+    #[serde(default)]
+    pub(crate) depends_on : Vec<String>,
+    #[serde(default)]
+    capture : HashMap<String, Capture>,
+    // The URL can instead be given as a path and query, which are joined onto the base_url (or the one from
+    // the config file) and encoded.
+    #[serde(default)]
+    url : String,
+    base_url : Option<String>,
+    path : Option<String>,
+    // Like headers, a parameter given a list of values is repeated for each of them.
+    #[serde(default)]
+    query : indexmap::IndexMap<String, HeaderValues>,
+    method : String,
+    headers : indexmap::IndexMap<String, Option<HeaderValues>>,
+    body : Option<String>,
+    body_path : Option<std::path::PathBuf>,
+    body_template : Option<std::path::PathBuf>,
+    body_type : Option<BodyType>,
+    proto : Option<crate::proto::Proto>,
+    prefer : Option<crate::negotiation::Prefer>,
+    matrix : Option<serde_json::Map<String, serde_json::Value>>,
+    #[serde(default)]
+    follow_location : bool,
+    paginate : Option<crate::pagination::Paginate>,
+    range : Option<ByteRange>,
+    expect_checksum : Option<Checksum>,
+    base_urls : Option<Vec<String>>,
+    // Seconds to wait before sending the request and after receiving its response.
+    delay_before : Option<f64>,
+    delay_after : Option<f64>,
+    #[serde(flatten)]
+    settings : crate::config::Settings,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    other : serde_json::Value,
+}
+
+// Changes made to a request by the REPL.
+impl RawRequest {
+    pub(crate) fn new(method : &str, url : &str) -> RawRequest {
+        match serde_json::from_value(serde_json::json!({ "method" : method, "url" : url, "headers" : {} })) {
+            Ok(raw_request) => raw_request,
+            Err(_) => panic!("Internal error, could not create request"),
+        }
+    }
+
+    pub(crate) fn set_method(&mut self, method : &str) {
+        self.method = String::from(method);
+    }
+
+    pub(crate) fn set_url(&mut self, url : &str) {
+        self.url = String::from(url);
+        self.base_url = None;
+        self.path = None;
+    }
+
+    pub(crate) fn set_header(&mut self, name : &str, value : Option<String>) {
+        self.headers.insert(String::from(name), value.map(HeaderValues::One));
+    }
+
+    pub(crate) fn remove_header(&mut self, name : &str) {
+        self.headers.retain(|header, _| !header.eq_ignore_ascii_case(name));
+    }
+
+    pub(crate) fn set_body(&mut self, body : &str) {
+        self.body = Some(String::from(body));
+        self.body_path = None;
+        self.body_template = None;
+    }
+
+    pub(crate) fn describe(&self) -> String {
+        let url = match self.url.is_empty() {
+            true => format!("{}{}", self.base_url.as_deref().unwrap_or_default(), self.path.as_deref().unwrap_or_default()),
+            false => self.url.clone(),
+        };
+        let mut description = format!("{} {}", self.method.to_uppercase(), url);
+        for (header, values) in &self.headers {
+            match values {
+                Some(HeaderValues::One(value)) => description.push_str(&format!("\n{}: {}", header, value)),
+                Some(HeaderValues::Many(values)) => {
+                    for value in values {
+                        description.push_str(&format!("\n{}: {}", header, value));
+                    }
+                },
+                None => description.push_str(&format!("\n{}: (autocompleted)", header)),
+            }
+        }
+        match (&self.body, &self.body_path, &self.body_template) {
+            (Some(body), _, _) => description.push_str(&format!("\n\n{}", body)),
+            (None, Some(path), _) | (None, None, Some(path)) => description.push_str(&format!("\n\n(body from {:?})", path)),
+            (None, None, None) => {},
+        }
+        description
+    }
+}
+
+// A header can be given more than once, in which case each value is sent as a separate header.
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema)]
+#[serde(untagged)]
+enum HeaderValues {
+    One(String),
+    Many(Vec<String>),
+}
+
+// Bodies which are written in the request file in one form and sent in another.
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema, Copy)]
+#[serde(rename_all = "snake_case")]
+enum BodyType {
+    // A protobuf message, framed for gRPC-Web in binary or in base64 text. The message is given as JSON
+    // when the request has a proto, and otherwise in base64.
+    GrpcWeb,
+    GrpcWebText,
+    // The method and params of a JSON-RPC call, or an array of them for a batch.
+    Jsonrpc,
+    // A protobuf message given as JSON, which needs a proto to encode it with.
+    Protobuf,
+    // JSON which is sent as MessagePack or CBOR.
+    Msgpack,
+    Cbor,
+}
+
+impl BodyType {
+    // Headers which are sent unless the request gives them itself.
+    fn headers(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            BodyType::GrpcWeb => &[("Content-Type", "application/grpc-web+proto"), ("Accept", "application/grpc-web+proto"), ("X-Grpc-Web", "1")],
+            BodyType::GrpcWebText => &[("Content-Type", "application/grpc-web-text"), ("Accept", "application/grpc-web-text"), ("X-Grpc-Web", "1")],
+            BodyType::Jsonrpc => &[("Content-Type", "application/json"), ("Accept", "application/json")],
+            BodyType::Protobuf => &[("Content-Type", "application/x-protobuf"), ("Accept", "application/x-protobuf")],
+            BodyType::Msgpack => &[("Content-Type", "application/msgpack"), ("Accept", "application/msgpack")],
+            BodyType::Cbor => &[("Content-Type", "application/cbor"), ("Accept", "application/cbor")],
+        }
+    }
+
+    fn encode(&self, body : &str, proto : Option<&crate::proto::Compiled>) -> Result<Vec<u8>, String> {
+        match self {
+            BodyType::GrpcWeb | BodyType::GrpcWebText => {
+                let message = match proto {
+                    Some(proto) => proto.encode(body)?,
+                    None => crate::grpc::decode_message(body)?,
+                };
+                Ok(crate::grpc::encode_request(&message, matches!(self, BodyType::GrpcWebText)))
+            },
+            BodyType::Jsonrpc => crate::jsonrpc::encode_request(body),
+            BodyType::Protobuf => match proto {
+                Some(proto) => proto.encode(body),
+                None => Err(String::from("A body_type of protobuf needs a proto with the file and message types to encode the body with.")),
+            },
+            BodyType::Msgpack => crate::msgpack::encode_request(body),
+            BodyType::Cbor => crate::cbor::encode_request(body),
+        }
+    }
+}
+
+// The checksum which the body of the response must have, for verifying a download.
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct Checksum {
+    sha256 : String,
+}
+
+impl Checksum {
+    fn verify(&self, output_response : &OutputResponse) -> Result<(), CallsyError> {
+        let expected = self.sha256.trim().to_lowercase();
+        if expected.len() != 64 || !expected.chars().all(|character| character.is_ascii_hexdigit()) {
+            return Err(CallsyError::InvalidRequest(format!("The expected checksum {} is not a SHA-256, which is 64 hexadecimal digits.", self.sha256)));
+        }
+
+        match &output_response.body_sha256 {
+            Some(actual) if *actual == expected => Ok(()),
+            Some(actual) => Err(CallsyError::ChecksumMismatch(format!("The SHA-256 of the response body is {}, but {} was expected.", actual, expected))),
+            None => Err(CallsyError::ChecksumMismatch(String::from("The checksum of the response body could not be verified, as it exceeded the maximum body size."))),
+        }
+    }
+}
+
+// The part of the resource to ask for, either as the value of the Range header, such as bytes=0-1023,
+// or as an offset and an optional length in bytes.
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema)]
+#[serde(untagged)]
+enum ByteRange {
+    Header(String),
+    Span {
+        offset : u64,
+        length : Option<u64>,
+    },
+}
+
+impl ByteRange {
+    fn header(&self) -> Result<String, CallsyError> {
+        match self {
+            ByteRange::Header(value) => Ok(value.clone()),
+            ByteRange::Span { offset, length : None } => Ok(format!("bytes={}-", offset)),
+            ByteRange::Span { length : Some(0), .. } => Err(CallsyError::InvalidRequest(String::from("The length of the range must be at least 1 byte."))),
+            ByteRange::Span { offset, length : Some(length) } => Ok(format!("bytes={}-{}", offset, offset + length - 1)),
+        }
+    }
+}
+
+impl HeaderValues {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            HeaderValues::One(value) => vec![value],
+            HeaderValues::Many(values) => values,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum Capture {
+    Body(String),
+    Header(String),
+    StatusCode,
+}
+
+#[derive(Serialize)]
+struct RunResult {
+    iteration : usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name : Option<String>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    variables : crate::dataset::Row,
+    output_file : Option<std::path::PathBuf>,
+    status_code : Option<u16>,
+    error : Option<CallsyError>,
+}
+
+impl RunResult {
+    fn is_success(&self) -> bool {
+        match self.status_code {
+            Some(status_code) => status_code < 400,
+            None => false,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RunSummary {
+    iterations : usize,
+    succeeded : usize,
+    failed : usize,
+    // Why the run was stopped before every iteration was sent, by --abort-after or --abort-error-rate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aborted : Option<String>,
+    results : Vec<RunResult>,
+}
+
+impl RunSummary {
+    fn new(results : Vec<RunResult>) -> RunSummary {
+        let failed = results.iter().filter(|result| !result.is_success()).count();
+
+        RunSummary {
+            iterations : results.len(),
+            succeeded : results.len() - failed,
+            failed,
+            aborted : None,
+            results,
+        }
+    }
+
+    fn aborted(self, session : &Session) -> RunSummary {
+        RunSummary {
+            aborted : session.breaker.as_ref().and_then(|breaker| breaker.tripped.clone()),
+            ..self
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone)]
+struct ProcessedRequest {
+    url : reqwest::Url,
+    method : reqwest::Method,
+    headers : Vec<(String, String)>,
+    body : Vec<u8>,
+    settings : crate::config::Settings,
+    trace : Option<crate::trace::TraceContext>,
+}
+
+// The size of a body which was cut short in the output, and the file the whole of it was written to.
+#[derive(Serialize, Deserialize, Clone)]
+struct TruncatedBody {
+    size : usize,
+    file : std::path::PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct OutputResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name : Option<String>,
+    headers : HashMap<String, Vec<String>>,
+    http_version : String,
+    status_code : u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason : Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remote_address : Option<std::net::SocketAddr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    local_address : Option<std::net::SocketAddr>,
+    // Whether the request was sent on a connection kept open from an earlier request of the run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connection_reused : Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    certificate : Option<crate::certificate::Certificate>,
+    // Every certificate the server presented, starting with its own, and the TLS version of the connection.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    certificate_chain : Option<Vec<crate::certificate::Certificate>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls_version : Option<String>,
+    // When the request was sent and the response finished, in local time with its offset, and the
+    // seconds in between.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    started : Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    completed : Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration : Option<f64>,
+    // The number of pages which were combined into the body, for a paginated request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pages : Option<usize>,
+    body : String,
+    // The SHA-256 of the body, or of the whole file when continuing a download. This is left out when
+    // the body exceeded --max-body-size.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_sha256 : Option<String>,
+    // The file a binary body was written to, in which case the body is left empty.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_file : Option<std::path::PathBuf>,
+    // The bytes of a binary body, which are written to files but never into the output.
+    #[serde(skip)]
+    binary_body : Option<Vec<u8>>,
+    // The bytes of a text body as they were received, before being decoded, which are what -b writes.
+    #[serde(skip)]
+    received_body : Option<Vec<u8>>,
+    // Set when the body was larger than --max-body-size, in which case it is left empty.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_size_exceeded : Option<bool>,
+    // Set when the body was cut short in the output by --max-inline-body.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_truncated : Option<TruncatedBody>,
+    // The length of the partial file which the body was appended to, when continuing a download.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resumed_from : Option<u64>,
+    // The base URL which the response came from, when the request could fail over between several.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    served_by : Option<String>,
+    // The status and messages of a gRPC-Web response, which are framed in its body.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    grpc_web : Option<crate::grpc::GrpcWebResponse>,
+    // The result or error of each JSON-RPC call, unwrapped from the envelopes of the response.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jsonrpc : Option<serde_json::Value>,
+    // The Idempotency-Key header the request was sent with, so that it can be retried with the same key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    idempotency_key : Option<String>,
+    // Whether the server sent only the range which was asked for, with 206 Partial Content.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    range_satisfied : Option<bool>,
+    // The URL the request was sent to, with variables filled in and any base URL joined on.
+    #[serde(skip)]
+    url : Option<Url>,
+    // Whether the response had one of the content types asked for with prefer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    negotiated : Option<bool>,
+    // The request as it was sent, with --include-request, so that the output stands on its own.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request : Option<crate::cassette::RecordedRequest>,
+    // The response to a GET of the Location of this response, for a request with follow_location.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location_response : Option<Box<OutputResponse>>,
+}
+
+fn get_output_path(maybe_path : &Option<std::path::PathBuf>, config : &crate::config::Config) -> std::path::PathBuf {
+    match (maybe_path, &config.output_directory) {
+        (Some(path), _) => path.clone(),
+        (None, Some(directory)) => directory.join("response.json"),
+        (None, None) => std::path::PathBuf::from("response.json"),
+    }
+}
+
+// The output of each iteration of a data driven run is written alongside the summary, such as response-1.json.
+fn get_iteration_output_path(output_path : &std::path::Path, iteration : usize) -> std::path::PathBuf {
+    let stem = output_path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+
+    let file_name = match output_path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, iteration, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, iteration),
+    };
+
+    output_path.with_file_name(file_name)
+}
+
+pub(crate) fn check_output_file(path : &std::path::PathBuf) -> Result<bool, CallsyError> {
+
+    if path.exists() {
+        loop {
+            print!("Output file {:?} already exists, would you like to overwrite [Y/N]: ", path);
+
+            std::io::stdout().flush().expect("Stdin flush failed.");
+            
+            let stdin = std::io::stdin();
+            let mut buffer = String::with_capacity(2);
+
+            match stdin.read_line(&mut buffer) {
+                Ok(_) => {},
+                Err(_) => {
+                    println!("Failed to read line.");
+                    continue;
+                },
+            }
+
+            match buffer.to_lowercase().trim_end().to_owned().as_str() {
+                "y" | "yes" => break Ok(true),
+                "n" | "no" => break Err(CallsyError::Usage(String::from("Exited due to inability to overwrite existing file."))),
+                _ => {},
+            }
+        }
+    }
+    else {
+        Ok(true)
+    }
+}
+
+
+fn check_body_output_file(maybe_path : &Option<std::path::PathBuf>) -> Result<bool, CallsyError> {
+    if let Some(path) = maybe_path {
+        check_output_file(path)
+    }
+    else {
+        Ok(true)
+    }
+}
+
+fn open_input_file(path : &std::path::PathBuf) -> Result<std::fs::File, CallsyError> {
+    match File::open(path) {
+        Ok(file) => Ok(file),
+        Err(source) => Err(CallsyError::Io { message : format!("Failed to open input file {:?}. {}", path, source), source }),
+    }
+}
+
+fn read_input_file(mut file : std::fs::File) -> Result<String, CallsyError> {
+    let mut content = String::new();
+
+    match file.read_to_string(&mut content) {
+        Ok(_) => Ok(content),
+        Err(source) => Err(CallsyError::Io { message : format!("Failed to read input file. {}", source), source }),
+    }
+}
+
+// Reads a request file, with the fragments it includes from other files filled in.
+fn read_request_file(path : &std::path::PathBuf) -> Result<String, CallsyError> {
+    let content = read_input_file(open_input_file(path)?)?;
+    crate::include::resolve_request(content, path).map_err(CallsyError::File)
+}
+
+fn deserialize_request_data(request_data : &str) -> Result<RawRequest, CallsyError> {
+    match serde_json::from_str(request_data) {
+        Ok(data) => Ok(data),
+        Err(source) => {
+            // A typo in a required field leaves it missing, so any unknown fields are likely to be the cause.
+            let unknown = match serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(request_data) {
+                Ok(fields) => describe_fields(fields.keys().filter(|field| !REQUEST_FIELDS.contains(&field.as_str()))),
+                Err(_) => String::new(),
+            };
+            let message = match unknown.is_empty() {
+                true => format!("Unable to deserialise data from input file. {}.", source),
+                false => format!("Unable to deserialise data from input file. {}. The file has fields unknown to callsy: {}.", source, unknown),
+            };
+            Err(CallsyError::Parse { message, source })
+        },
+    }
+}
+
+// The fields of a request file, along with the settings which can also be given in the config file, which
+// unknown fields are compared with to suggest what was meant.
+const REQUEST_FIELDS : [&str; 39] = [
+    "name", "tags", "depends_on", "capture", "url", "base_url", "path", "query", "method", "headers", "body", "body_path", "body_template",
+    "body_type", "proto", "prefer", "matrix", "follow_location", "paginate", "range", "expect_checksum",
+    "base_urls", "delay_before", "delay_after", "timeout", "connect_timeout", "proxy", "tls",
+    "pool_max_idle_per_host", "pool_idle_timeout", "tcp_keepalive", "dns_over_https", "dns_over_https_format", "local_address",
+    "connect_to", "title_case_headers", "trace_context", "otlp_endpoint", "failover_statuses",
+];
+
+// Lists unknown fields, each with the known field it is likely a typo of, such as mehtod for method or
+// Headers for headers.
+fn describe_fields<'a>(fields : impl Iterator<Item = &'a String>) -> String {
+    fields
+    .map(|field| {
+        let suggestion = REQUEST_FIELDS
+            .iter()
+            .map(|known| (known, strsim::levenshtein(&field.to_lowercase(), known)))
+            .filter(|(_, distance)| *distance <= 2)
+            .min_by_key(|(_, distance)| *distance);
+        match suggestion {
+            Some((known, _)) => format!("{} (did you mean {}?)", field, known),
+            None => field.clone(),
+        }
+    })
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+// Unknown fields are ignored with a warning, unless --strict is given.
+fn check_extra_fields(raw_request : &RawRequest, strict : bool) -> Result<(), CallsyError> {
+    let fields = match &raw_request.other {
+        serde_json::Value::Object(fields) if fields.is_empty() => return Ok(()),
+        serde_json::Value::Object(fields) => describe_fields(fields.keys()),
+        _ => return Err(CallsyError::InvalidRequest(String::from("Request JSON file included fields unknown to callsy."))),
+    };
+
+    match strict {
+        true => Err(CallsyError::InvalidRequest(format!("Request JSON file included fields unknown to callsy: {}", fields))),
+        false => {
+            tracing::warn!(%fields, "The request file has fields unknown to callsy, which are ignored");
+            Ok(())
+        },
+    }
+}
+
+fn get_body(raw_request : &RawRequest) -> Result<String, CallsyError> {
+    match (&raw_request.body_path, &raw_request.body) {
+        (Some(_), Some(_)) => {
+            Err(CallsyError::InvalidRequest(String::from("Cannot provide both a body and body_path.")))
+        },
+        (Some(path), None) => {
+            let mut file = match File::open(path) {
+                Ok(file) => file,
+                Err(source) => { return Err(CallsyError::Io { message : format!("Failed to open the body file {:?}. {}", path, source), source }); }
+            };
+
+            let mut body = String::new();
+            match file.read_to_string(&mut body) {
+                Ok(_) => Ok(body),
+                Err(source) => Err(CallsyError::Io { message : format!("Failed to read body file. {}", source), source }),
+            }
+        },
+        (None, Some(body)) => {
+            Ok(body.to_string())
+        },
+        (None, None) => {
+            Ok(String::from(""))
+        }
+    }    
+}
+
+fn load_variables(maybe_env_path : &Option<std::path::PathBuf>, config : &crate::config::Config) -> Result<crate::variables::Variables, CallsyError> {
+    let mut variables = crate::variables::Variables::new();
+
+    // Variables from an environment file given on the command line take precedence over the config file.
+    for path in [&config.env_file, maybe_env_path].into_iter().flatten() {
+        variables.extend(crate::variables::load_environment(path).map_err(CallsyError::File)?);
+    }
+
+    Ok(variables)
+}
+
+fn get_save_captures_path<'a>(save_captures : bool, maybe_env_path : &'a Option<std::path::PathBuf>, config : &'a crate::config::Config) -> Result<Option<&'a std::path::PathBuf>, CallsyError> {
+    if !save_captures {
+        return Ok(None);
+    }
+
+    match (maybe_env_path, &config.env_file) {
+        (Some(path), _) | (None, Some(path)) => Ok(Some(path)),
+        (None, None) => Err(CallsyError::Usage(String::from("Cannot save captured variables without an environment file."))),
+    }
+}
+
+fn substitute_variables(mut raw_request : RawRequest, variables : &mut crate::variables::Variables, config : &crate::config::Config) -> Result<RawRequest, CallsyError> {
+    raw_request.url = crate::variables::substitute(&raw_request.url, variables, config).map_err(CallsyError::InvalidRequest)?;
+    for url in [&mut raw_request.base_url, &mut raw_request.path].into_iter().flatten() {
+        *url = crate::variables::substitute(url, variables, config).map_err(CallsyError::InvalidRequest)?;
+    }
+    for values in raw_request.query.values_mut() {
+        let substituted = match values {
+            HeaderValues::One(value) => std::slice::from_mut(value),
+            HeaderValues::Many(values) => values.as_mut_slice(),
+        };
+        for value in substituted {
+            *value = crate::variables::substitute(value, variables, config).map_err(CallsyError::InvalidRequest)?;
+        }
+    }
+    raw_request.url = compose_url(&mut raw_request).map_err(CallsyError::InvalidRequest)?;
+
+    for values in raw_request.headers.values_mut().flatten() {
+        let substituted = match values {
+            HeaderValues::One(value) => std::slice::from_mut(value),
+            HeaderValues::Many(values) => values.as_mut_slice(),
+        };
+        for value in substituted {
+            *value = crate::variables::substitute(value, variables, config).map_err(CallsyError::InvalidRequest)?;
+        }
+    }
+
+    Ok(raw_request)
+}
+
+// Characters which are percent encoded in each segment of a path, so that a path such as
+// reports/{{name}} is sent as given whatever the variable holds.
+const PATH_SEGMENT : &percent_encoding::AsciiSet = &percent_encoding::CONTROLS
+    .add(b' ').add(b'"').add(b'#').add(b'%').add(b'<').add(b'>').add(b'?').add(b'`').add(b'{').add(b'}');
+
+// Joins the base_url, path and query of a request into its URL, with exactly one slash between the base
+// URL and the path. Without a base_url, the path is left relative for the base_url of the config file.
+fn compose_url(raw_request : &mut RawRequest) -> Result<String, String> {
+    let base_url = raw_request.base_url.take();
+    let query = std::mem::take(&mut raw_request.query);
+    if base_url.is_some() && raw_request.base_urls.is_some() {
+        return Err(String::from("Cannot provide both a base_url and base_urls."));
+    }
+
+    let relative = match (raw_request.path.take(), raw_request.url.is_empty()) {
+        (Some(_), false) => return Err(String::from("Cannot provide both a url and a path.")),
+        (Some(path), true) => {
+            path
+            .split('/')
+            .map(|segment| percent_encoding::utf8_percent_encode(segment, PATH_SEGMENT).to_string())
+            .collect::<Vec<_>>()
+            .join("/")
+        },
+        (None, false) => std::mem::take(&mut raw_request.url),
+        (None, true) if base_url.is_some() => String::new(),
+        (None, true) => return Err(String::from("A request must have a url, or a path or base_url to build one from.")),
+    };
+
+    let url = match base_url {
+        Some(base_url) if relative.is_empty() => base_url,
+        Some(base_url) if Url::parse(&relative).is_err() => format!("{}/{}", base_url.trim_end_matches('/'), relative.trim_start_matches('/')),
+        _ => relative,
+    };
+    if query.is_empty() {
+        return Ok(url);
+    }
+
+    let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+    for (name, values) in &query {
+        match values {
+            HeaderValues::One(value) => serializer.append_pair(name, value),
+            HeaderValues::Many(values) => serializer.extend_pairs(values.iter().map(|value| (name, value))),
+        };
+    }
+    // The query goes before any fragment, after whatever query the URL already has.
+    let (url, fragment) = match url.split_once('#') {
+        Some((url, fragment)) => (url, format!("#{}", fragment)),
+        None => (url.as_str(), String::new()),
+    };
+    let separator = if url.contains('?') { "&" } else { "?" };
+    Ok(format!("{}{}{}{}", url, separator, serializer.finish(), fragment))
+}
+
+fn parse_url(url : &str, base_url : &Option<String>) -> Result<reqwest::Url, String> {
+    match (Url::parse(url), base_url) {
+        (Ok(url), _) => Ok(url),
+        (Err(url::ParseError::RelativeUrlWithoutBase), Some(base_url)) => {
+            let joined = format!("{}/{}", base_url.trim_end_matches('/'), url.trim_start_matches('/'));
+            match Url::parse(&joined) {
+                Ok(url) => Ok(url),
+                Err(error) => Err(format!("Error while parsing URL. {}", error)),
+            }
+        },
+        (Err(error), _) => Err(format!("Error while parsing URL. {}", error)),
+    }
+}
+
+fn process_request_data(raw_request : RawRequest, body : Vec<u8>, config : &crate::config::Config) -> Result<ProcessedRequest, CallsyError> {
+    
+    fn convert_http_method(raw_request : &RawRequest) -> Result<Method, String> {
+        match Method::from_bytes(raw_request.method.to_uppercase().as_bytes()) {
+            Ok(method) => Ok(method),
+            Err(_) => Err(format!("The provided HTTP method of {} is invalid.", raw_request.method)),
+        }
+    }
+
+    let method = convert_http_method(&raw_request).map_err(CallsyError::InvalidRequest)?;
+    let url = parse_url(&raw_request.url, &config.base_url).map_err(CallsyError::InvalidRequest)?;
+
+    // Headers are sent in the order they are given in the request file, followed by the defaults from the config file.
+    let mut headers = Vec::new();
+
+    // Every autocompleted header (such as a signature) uses the same date, which is the one given in the request if there is one.
+    let date = raw_request.headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case("date"))
+        .and_then(|(_, values)| match values {
+            Some(HeaderValues::One(value)) => Some(value.clone()),
+            _ => None,
+        })
+        .unwrap_or_else(|| httpdate::fmt_http_date(std::time::SystemTime::now()));
+
+    for (header, values) in raw_request.headers {
+        match values {
+            Some(values) => {
+                for value in values.into_vec() {
+                    headers.push((header.clone(), value));
+                }
+            },
+            None => {
+                let context = crate::autocomplete::Context {
+                    method : &method,
+                    url : &url,
+                    body : &body,
+                    date : &date,
+                    config,
+                    offline : config.offline,
+                };
+                let value = crate::autocomplete::autocomplete(&header, &context)?;
+                headers.push((header, value));
+            },
+        }
+    }
+
+    // Default headers from the config file, unless the request includes the same header.
+    for (header, value) in &config.headers {
+        if !headers.iter().any(|(key, _)| key.eq_ignore_ascii_case(header)) {
+            headers.push((header.clone(), value.clone()));
+        }
+    }
+
+    let settings = config.defaults.merged_under(&raw_request.settings);
+
+    // A traceparent given in the request is sent as is, and no span is exported for it.
+    let has_traceparent = headers.iter().any(|(key, _)| key.eq_ignore_ascii_case("traceparent"));
+    let trace = if settings.trace_context.unwrap_or(false) && !has_traceparent {
+        let trace = crate::trace::TraceContext::new();
+        headers.push((String::from("traceparent"), trace.traceparent()));
+        Some(trace)
+    }
+    else {
+        None
+    };
+
+    Ok(ProcessedRequest {
+        url,
+        method,
+        headers,
+        body,
+        settings,
+        trace,
+    })
+}
+
+
+// State which is shared by all of the requests in a run.
+pub(crate) struct Session<'a> {
+    config : &'a crate::config::Config,
+    clients : Clients,
+    recording : Option<crate::cassette::Cassette>,
+    replay : Option<crate::cassette::Cassette>,
+    report : Option<crate::report::Report>,
+    metrics : Option<Vec<crate::metrics::Attempt>>,
+    live_metrics : Option<std::sync::Arc<crate::metrics::Registry>>,
+    // Every request sent, as it was sent, for --request-echo.
+    sent : Option<Vec<crate::cassette::RecordedRequest>>,
+    // Where each request and its response is recorded, for --append-to and the history database.
+    history : Option<crate::history::History>,
+    // Whether each response body is printed as it arrives, with HTML rendered as text, for --render.
+    render_text : bool,
+    show_secrets : bool,
+    breaker : Option<CircuitBreaker>,
+    // How long to wait between the requests of a collection or data driven run, for --think-time.
+    think_time : Option<std::time::Duration>,
+}
+
+// Stops a run once too many of its requests have failed, rather than carrying on against a service
+// which is down. A request fails if it gets no response or one with a status of 400 or more.
+struct CircuitBreaker {
+    abort_after : Option<usize>,
+    // A percentage, which is only checked once there have been enough requests for it to mean something.
+    error_rate : Option<f64>,
+    sent : usize,
+    failed : usize,
+    // The reason the breaker tripped, once it has.
+    tripped : Option<String>,
+}
+
+impl CircuitBreaker {
+    const ERROR_RATE_MINIMUM : usize = 10;
+
+    fn new(abort_after : Option<usize>, error_rate : Option<f64>) -> Result<Option<CircuitBreaker>, CallsyError> {
+        if abort_after == Some(0) {
+            return Err(CallsyError::Usage(String::from("The number of failures to abort after must be at least 1.")));
+        }
+        if error_rate.is_some_and(|error_rate| !(error_rate > 0.0 && error_rate <= 100.0)) {
+            return Err(CallsyError::Usage(String::from("The error rate to abort at must be a percentage above 0 and at most 100.")));
+        }
+        if abort_after.is_none() && error_rate.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(CircuitBreaker { abort_after, error_rate, sent : 0, failed : 0, tripped : None }))
+    }
+
+    fn record(&mut self, success : bool) {
+        self.sent += 1;
+        if !success {
+            self.failed += 1;
+        }
+        if self.tripped.is_some() {
+            return;
+        }
+
+        let rate = self.failed as f64 * 100.0 / self.sent as f64;
+        if self.abort_after.is_some_and(|abort_after| self.failed >= abort_after) {
+            self.tripped = Some(format!("Stopped the run after {} of {} requests failed.", self.failed, self.sent));
+        }
+        else if self.error_rate.is_some_and(|error_rate| self.sent >= Self::ERROR_RATE_MINIMUM && rate >= error_rate) {
+            self.tripped = Some(format!("Stopped the run as {} of {} requests failed ({:.0}%).", self.failed, self.sent, rate));
+        }
+    }
+}
+
+impl<'a> Session<'a> {
+    // A session which only sends requests, without recording, replaying or reporting on them.
+    fn new(config : &'a crate::config::Config) -> Session<'a> {
+        Session {
+            config,
+            clients : Clients::default(),
+            recording : None,
+            replay : None,
+            report : None,
+            metrics : None,
+            live_metrics : None,
+            sent : None,
+            history : None,
+            render_text : false,
+            show_secrets : false,
+            breaker : None,
+            think_time : None,
+        }
+    }
+
+    // Waits between requests, unless responses are being replayed, as then there is nothing to wait for.
+    async fn pause(&self, duration : std::time::Duration) {
+        if self.replay.is_none() {
+            tokio::time::sleep(duration).await;
+        }
+    }
+
+    async fn think(&self) {
+        if let Some(think_time) = self.think_time {
+            self.pause(think_time).await;
+        }
+    }
+
+    fn is_tripped(&self) -> bool {
+        self.breaker.as_ref().is_some_and(|breaker| breaker.tripped.is_some())
+    }
+
+    // Adds the result of a request to the report and history, for those which are being kept.
+    pub(crate) fn add_result(&mut self, name : &str, request : String, started : std::time::Instant, sent : &Result<(OutputResponse, crate::variables::Variables), CallsyError>) {
+        if let Some(history) = &mut self.history {
+            history.append(name, &request, started, sent.as_ref().map(|(output_response, _)| output_response));
+        }
+
+        if let Some(breaker) = &mut self.breaker {
+            breaker.record(sent.as_ref().is_ok_and(|(output_response, _)| output_response.status_code < 400));
+        }
+
+        if let (true, Ok((output_response, _))) = (self.render_text, sent) {
+            let text = output_response.rendered_text();
+            println!("{}", if self.show_secrets { text } else { crate::masking::mask(&text) });
+        }
+
+        if let Some(report) = &mut self.report {
+            match sent {
+                Ok((output_response, _)) => report.add_response(name, started.elapsed(), request, output_response.status_code, output_response.summary()),
+                Err(error) => report.add_error(name, started.elapsed(), request, error),
+            }
+        }
+    }
+}
+
+fn write_reports(args : &Arguments, session : &Session) -> Result<(), CallsyError> {
+    if let Some(report) = &session.report {
+        if let Some(path) = &args.junit {
+            write_output(path, crate::junit::to_xml(report), args.show_secrets, args.fsync)?;
+        }
+        if let Some(path) = &args.report {
+            write_output(path, crate::html::to_html(report), args.show_secrets, args.fsync)?;
+        }
+    }
+
+    if let (Some(path), Some(sent)) = (&args.request_echo, &session.sent) {
+        write_output(path, serialize_response(sent), args.show_secrets, args.fsync)?;
+    }
+
+    if let (Some(path), Some(metrics)) = (&args.metrics_csv, &session.metrics) {
+        write_output(path, crate::metrics::to_csv(metrics).map_err(CallsyError::File)?, args.show_secrets, args.fsync)?;
+    }
+
+    Ok(())
+}
+
+// Clients are shared between the requests of a run, so that connections (and their TLS handshakes) are
+// reused. Requests with different connection settings need their own client.
+#[derive(Default)]
+struct Clients {
+    clients : HashMap<String, Client>,
+    // The local and remote addresses of each connection which has been used, which tell connections apart.
+    connections : std::collections::HashSet<(std::net::SocketAddr, std::net::SocketAddr)>,
+    // The last TLS handshake seen with each server, by its origin.
+    handshakes : HashMap<String, crate::connection::Handshake>,
+}
+
+impl Clients {
+    fn get(&mut self, config : &crate::config::Config, settings : &crate::config::Settings) -> Result<Client, CallsyError> {
+        // The timeout is set on each request and failover happens between them, so neither is part of the key.
+        let key = format!("{:?}", crate::config::Settings { timeout : None, failover_statuses : None, ..settings.clone() });
+
+        if let Some(client) = self.clients.get(&key) {
+            return Ok(client.clone());
+        }
+
+        let client = build_client(config, settings)?;
+        tracing::debug!(clients = self.clients.len() + 1, "Built a new HTTP client");
+        self.clients.insert(key, client.clone());
+        Ok(client)
+    }
+
+    // A reused connection or resumed TLS session shows nothing of the handshake, in which case the last one
+    // seen with the same server is used.
+    fn handshake(&mut self, url : &Url, handshake : crate::connection::Handshake) -> Option<crate::connection::Handshake> {
+        let origin = url.origin().ascii_serialization();
+        if handshake.certificates.is_empty() {
+            return self.handshakes.get(&origin).cloned();
+        }
+        self.handshakes.insert(origin, handshake.clone());
+        Some(handshake)
+    }
+
+    // Whether a response came on a connection which an earlier response of the run also came on.
+    fn is_reused(&mut self, connection : (std::net::SocketAddr, std::net::SocketAddr)) -> bool {
+        !self.connections.insert(connection)
+    }
+}
+
+// Timeouts are given in seconds, which must be a number that is 0 or more.
+fn seconds_duration(field : &str, seconds : f64) -> Result<std::time::Duration, CallsyError> {
+    match std::time::Duration::try_from_secs_f64(seconds) {
+        Ok(duration) => Ok(duration),
+        Err(_) => Err(CallsyError::InvalidRequest(format!("The {} of {} seconds is not a number of seconds which is 0 or more.", field, seconds))),
+    }
+}
+
+fn build_client(config : &crate::config::Config, settings : &crate::config::Settings) -> Result<Client, CallsyError> {
+    let user_agent = match &config.user_agent {
+        Some(user_agent) => user_agent.clone(),
+        None => format!("callsy/{}", env!("CARGO_PKG_VERSION")),
+    };
+
+    let mut builder = Client::builder().user_agent(user_agent).tls_info(true).connection_verbose(config.trace_wire);
+
+    if let Some(connect_timeout) = settings.connect_timeout {
+        builder = builder.connect_timeout(seconds_duration("connect_timeout", connect_timeout)?);
+    }
+
+    if let Some(max_idle) = settings.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max_idle);
+    }
+
+    if let Some(idle_timeout) = settings.pool_idle_timeout {
+        builder = builder.pool_idle_timeout(seconds_duration("pool_idle_timeout", idle_timeout)?);
+    }
+
+    if let Some(keepalive) = settings.tcp_keepalive {
+        builder = builder.tcp_keepalive(seconds_duration("tcp_keepalive", keepalive)?);
+    }
+
+    if settings.title_case_headers == Some(true) {
+        builder = builder.http1_title_case_headers();
+    }
+
+    if let Some(local_address) = settings.local_address {
+        builder = builder.local_address(local_address);
+    }
+
+    // Connections to a host are made to another address instead, while the URL (and so the host header and SNI) is unchanged.
+    // The port of the target is ignored here, as the connector always takes it from the URL, so is applied in make_request.
+    for (host, target) in settings.connect_to.iter().flatten() {
+        let (address, _) = connect_to_target(target);
+        let addresses = match std::net::ToSocketAddrs::to_socket_addrs(&(address, 0)) {
+            Ok(addresses) => addresses.collect::<Vec<_>>(),
+            Err(source) => return Err(CallsyError::Io { message : format!("Failed to resolve {} to connect to in place of {}. {}", target, host, source), source }),
+        };
+        builder = builder.resolve_to_addrs(host, &addresses);
+    }
+
+    // Lookups are timed for the metrics, and are made with DNS over HTTPS when it is configured.
+    let doh = settings.dns_over_https.as_ref().map(|endpoint| crate::dns::DohResolver::new(endpoint, settings.dns_over_https_format));
+    builder = builder.dns_resolver(crate::dns::TimedResolver::new(doh));
+
+    if let Some(proxy) = &settings.proxy {
+        match reqwest::Proxy::all(proxy) {
+            Ok(proxy) => builder = builder.proxy(proxy),
+            Err(error) => return Err(CallsyError::Config(format!("The provided proxy of {} is invalid. {}", proxy, error))),
+        }
+    }
+
+    builder = builder.use_preconfigured_tls(crate::tls::client_config(settings.tls.as_ref())?);
 
-    #[clap(parse(from_os_str), short)]
-    body_output_file : Option<std::path::PathBuf>,
+    match builder.build() {
+        Ok(client) => Ok(client),
+        Err(source) => Err(CallsyError::Tls { message : format!("Failed to build the HTTP client, {}", source), source }),
+    }
 }
 
-pub async fn respond(args : Arguments) -> Result<(), String> {
-    
-    check_output_file(&args.output_file)?;
-    check_body_output_file(&args.body_output_file)?; 
-    let input_file = open_input_file(&args.request_file)?;
-    let file_contents = read_input_file(input_file)?;
-    let raw_request = deserialize_request_data(&file_contents)?;
-    check_extra_fields(&raw_request)?;
-    let body = get_body(&raw_request)?;
-    let processed_request = process_request_data(raw_request, body)?;
-    let response = make_request(processed_request).await?;
-    let output_response = convert_response(response).await?;
-    let body_for_file = output_response.body.clone();
-    let serialized_response = serialize_response(output_response);
-    let output_file = open_output_file(&args.output_file)?;
-    write_to_output_file(output_file, serialized_response)?;
-    open_and_write_to_body_output_file(&args.body_output_file, body_for_file)?;
+// A connect_to target is an address, optionally followed by a port, such as 10.0.0.12, origin.internal:8443
+// or [::1]:8443.
+fn connect_to_target(target : &str) -> (&str, Option<u16>) {
+    match target.rsplit_once(':') {
+        Some((address, port)) if !address.contains(':') || (address.starts_with('[') && address.ends_with(']')) => {
+            match port.parse() {
+                Ok(port) => (address.trim_start_matches('[').trim_end_matches(']'), Some(port)),
+                Err(_) => (target, None),
+            }
+        },
+        _ => (target.trim_start_matches('[').trim_end_matches(']'), None),
+    }
+}
+
+async fn make_request(client : &Client, processed_request : ProcessedRequest) -> Result<Response, CallsyError> {
+
+    let body = reqwest::Body::from(processed_request.body); 
+    let mut headers = reqwest::header::HeaderMap::new();
+    for (k, v) in processed_request.headers.iter() {
+        let name = match reqwest::header::HeaderName::from_bytes(k.as_bytes()) {
+            Ok(name) => name,
+            Err(source) => return Err(CallsyError::InvalidHeader { message : format!("The header name {} is invalid.", k), source : Box::new(source) }),
+        };
+        let value = match reqwest::header::HeaderValue::from_str(v) {
+            Ok(value) => value,
+            Err(source) => return Err(CallsyError::InvalidHeader { message : format!("The value of the {} header is invalid.", k), source : Box::new(source) }),
+        };
+        headers.append(name, value);
+    }
+
+    // When the connect_to target has a port, the request is sent to that port with the host header of the original URL.
+    let mut url = processed_request.url;
+    let target_port = url.host_str().and_then(|host| processed_request.settings.connect_to.as_ref()?.get(host)).and_then(|target| connect_to_target(target).1);
+    if let (Some(host), Some(port)) = (url.host_str(), target_port) {
+        if !headers.contains_key(reqwest::header::HOST) {
+            let authority = match url.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host.to_string(),
+            };
+            match reqwest::header::HeaderValue::from_str(&authority) {
+                Ok(value) => { headers.insert(reqwest::header::HOST, value); },
+                Err(source) => return Err(CallsyError::InvalidHeader { message : format!("The host {} is invalid.", authority), source : Box::new(source) }),
+            }
+        }
+        if url.set_port(Some(port)).is_err() {
+            return Err(CallsyError::InvalidRequest(format!("Cannot connect to port {} in place of {}.", port, url)));
+        }
+    }
+
+    let mut request_builder =
+        client
+        .request(processed_request.method, url)
+        .body(body)
+        .headers(headers);
+
+    if let Some(timeout) = processed_request.settings.timeout {
+        request_builder = request_builder.timeout(seconds_duration("timeout", timeout)?);
+    }
+
+    match request_builder.send().await {
+
+        Ok(res) => Ok(res),
+        Err(error) if crate::tls::is_pin_mismatch(&error) => Err(CallsyError::UntrustedCertificate(String::from("Refused to send the request, as the certificate presented by the server does not match any of the pinned certificates."))),
+        Err(error) => Err(CallsyError::from_reqwest(format!("Error when sending the request, {}", error), error)),
+    }
+}
+
+async fn check_certificate_expiry(url : &str, threshold : i64, config : &crate::config::Config) -> Result<(), CallsyError> {
+    // The certificates aren't verified, so that an expired or otherwise invalid chain can still be reported on.
+    let tls = crate::config::Tls { accept_invalid_certificates : Some(true), pins : None, ..config.defaults.tls.clone().unwrap_or_default() };
+    let client = build_client(config, &crate::config::Settings { tls : Some(tls), ..config.defaults.clone() })?;
+
+    let (response, observation) = crate::connection::observe(client.head(url).send()).await;
+    if let Err(error) = response {
+        return Err(CallsyError::from_reqwest(format!("Error when connecting to {}, {}", url, error), error));
+    }
+
+    let expiries : Vec<_> = observation.handshake.certificates.iter().filter_map(|der| crate::certificate::expiry(der)).collect();
+    if expiries.is_empty() {
+        return Err(CallsyError::InvalidRequest(format!("No certificate was presented by {}, which must be an HTTPS URL.", url)));
+    }
+
+    // Each certificate of the chain is reported, starting with the server's own.
+    for expiry in &expiries {
+        match expiry.days_remaining {
+            days if days < 0 => println!("The certificate for {} expired on {}, {} days ago.", expiry.subject, expiry.not_after, -days),
+            days => println!("The certificate for {} expires on {}, in {} days.", expiry.subject, expiry.not_after, days),
+        }
+    }
+
+    if let Some(expiry) = expiries.iter().find(|expiry| expiry.days_remaining < threshold) {
+        return Err(CallsyError::CertificateExpiring(format!("The certificate for {} expires within the threshold of {} days.", expiry.subject, threshold)));
+    }
 
     Ok(())
 }
 
-#[derive(Deserialize, Debug)]
-struct RawRequest {
-    url : String,
-    method : String,
-    headers : HashMap<String, Option<String>>,
-    body : Option<String>,
-    body_path : Option<std::path::PathBuf>,
-    #[serde(flatten)]
-    other : serde_json::Value,
+// The ETag of a partial download is kept beside it, such as download.zip.etag, so that when the download
+// is continued the server can be asked to send the rest only if the file hasn't changed since.
+fn etag_path(path : &std::path::Path) -> std::path::PathBuf {
+    let mut etag_path = path.as_os_str().to_owned();
+    etag_path.push(".etag");
+    std::path::PathBuf::from(etag_path)
 }
 
-#[allow(dead_code)]
-struct ProcessedRequest {
-    url : String,
-    method : reqwest::Method,
-    headers : HashMap<String, String>,
-    body : String,
+fn read_etag(path : &std::path::Path) -> Option<String> {
+    let etag = std::fs::read_to_string(etag_path(path)).ok()?;
+    let etag = etag.trim();
+    if etag.is_empty() { None } else { Some(String::from(etag)) }
 }
 
-#[derive(Serialize)]
-struct OutputResponse {
-    headers : HashMap<String, String>,
-    status_code : String,
-    body : String,
+// Opens the file being downloaded to, appending to it for partial content which starts where the file
+// ends, and replacing it when the server sends the whole body. Other responses, such as errors, are
+// left in the output rather than written over the partial file.
+fn open_download(download : &crate::config::Download, response : &Response) -> Result<Option<File>, CallsyError> {
+    let append = match response.status() {
+        reqwest::StatusCode::PARTIAL_CONTENT => {
+            let start = response
+                .headers()
+                .get(reqwest::header::CONTENT_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("bytes "))
+                .and_then(|value| value.split_once('-'))
+                .and_then(|(start, _)| start.trim().parse::<u64>().ok());
+
+            if start != Some(download.offset) {
+                return Err(CallsyError::InvalidRequest(format!("The server sent partial content which doesn't continue from byte {} of {:?}.", download.offset, download.path)));
+            }
+            true
+        },
+        reqwest::StatusCode::OK => false,
+        _ => return Ok(None),
+    };
+
+    let file = match std::fs::OpenOptions::new().create(true).write(true).append(append).truncate(!append).open(&download.path) {
+        Ok(file) => file,
+        Err(source) => return Err(CallsyError::Io { message : format!("Failed to open body output file {:?}. {}", download.path, source), source }),
+    };
+
+    let etag_path = etag_path(&download.path);
+    let result = match response.headers().get(reqwest::header::ETAG).and_then(|etag| etag.to_str().ok()) {
+        Some(etag) => std::fs::write(&etag_path, etag),
+        None if etag_path.exists() => std::fs::remove_file(&etag_path),
+        None => Ok(()),
+    };
+    if let Err(source) = result {
+        return Err(CallsyError::Io { message : format!("Failed to update {:?}. {}", etag_path, source), source });
+    }
+
+    Ok(Some(file))
 }
 
-fn check_output_file(path : &std::path::PathBuf) -> Result<bool, String> {
+// The body is read a chunk at a time, so that the download can be stopped once it passes the size limit,
+// or abandoned if it arrives too slowly. A download being continued is written to its file as each chunk
+// arrives, and left out of the output. Every body is read the same way, with or without limits, and is
+// kept as the bytes it was received as, so that text is decoded from it afterwards in the same way.
+async fn read_body(mut response : Response, config : &crate::config::Config) -> Result<(Vec<u8>, Option<bool>), CallsyError> {
+    let max_body_size = config.max_body_size;
 
-    if path.exists() {
-        loop {
-            print!("Output file {:?} already exists, would you like to overwrite [Y/N]: ", path);
+    if max_body_size.is_some_and(|limit| response.content_length().is_some_and(|length| length > limit)) {
+        return Ok((Vec::new(), Some(true)));
+    }
 
-            std::io::stdout().flush().expect("Stdin flush failed.");
-            
-            let stdin = std::io::stdin();
-            let mut buffer = String::with_capacity(2);
+    let mut file = match &config.download {
+        Some(download) => open_download(download, &response)?,
+        None => None,
+    };
 
-            match stdin.read_line(&mut buffer) {
-                Ok(_) => {},
-                Err(_) => {
-                    println!("Failed to read line.");
-                    continue;
-                },
-            }
+    let mut bytes = Vec::new();
+    let mut received = 0;
+    let mut window_started = std::time::Instant::now();
+    let mut window_bytes = 0;
 
-            match buffer.to_lowercase().trim_end().to_owned().as_str() {
-                "y" | "yes" => break Ok(true),
-                "n" | "no" => break Err(String::from("Exited due to inability to overwrite existing file.")),
-                _ => {},
+    loop {
+        // Waiting for a chunk gives up at the end of the window, so that a stalled transfer is caught.
+        let chunk = match config.speed_limit {
+            Some(speed_limit) => {
+                let deadline = tokio::time::Instant::from_std(window_started + speed_limit.time);
+                tokio::time::timeout_at(deadline, response.chunk()).await.ok()
+            },
+            None => Some(response.chunk().await),
+        };
+
+        match chunk {
+            Some(Ok(Some(chunk))) => {
+                match &mut file {
+                    Some(file) => {
+                        if let Err(source) = file.write_all(&chunk) {
+                            return Err(CallsyError::Io { message : format!("Failed to write to body output file. {}", source), source });
+                        }
+                    },
+                    None => bytes.extend_from_slice(&chunk),
+                }
+                received += chunk.len() as u64;
+                window_bytes += chunk.len() as u64;
+                if max_body_size.is_some_and(|limit| received > limit) {
+                    return Ok((Vec::new(), Some(true)));
+                }
+            },
+            Some(Ok(None)) => break,
+            Some(Err(error)) => return Err(CallsyError::from_reqwest(format!("Failed to read the response body, {}", error), error)),
+            None => {},
+        }
+
+        if let Some(speed_limit) = config.speed_limit {
+            let elapsed = window_started.elapsed();
+            if elapsed >= speed_limit.time {
+                let rate = window_bytes as f64 / elapsed.as_secs_f64();
+                if rate < speed_limit.bytes_per_second as f64 {
+                    return Err(CallsyError::TooSlow(format!("The response body arrived at {:.0} bytes per second over the last {:?}, below the speed limit of {} bytes per second.", rate, speed_limit.time, speed_limit.bytes_per_second)));
+                }
+                window_started = std::time::Instant::now();
+                window_bytes = 0;
             }
         }
     }
-    else {
-        Ok(true)
+
+    // A finished download has nothing left to continue, so its ETag is no longer needed.
+    if let (Some(_), Some(download)) = (&file, &config.download) {
+        let _ = std::fs::remove_file(etag_path(&download.path));
     }
+
+    Ok((bytes, None))
 }
 
+fn sha256_hex(bytes : &[u8]) -> String {
+    hex::encode(sha2::Sha256::digest(bytes))
+}
 
-fn check_body_output_file(maybe_path : &Option<std::path::PathBuf>) -> Result<bool, String> {
-    if let Some(path) = maybe_path {
-        check_output_file(&path)
-    }
-    else {
-        Ok(true)
+fn file_sha256(path : &std::path::Path) -> Result<String, CallsyError> {
+    let mut hasher = sha2::Sha256::new();
+    let hashed = File::open(path).and_then(|mut file| std::io::copy(&mut file, &mut hasher));
+
+    match hashed {
+        Ok(_) => Ok(hex::encode(hasher.finalize())),
+        Err(source) => Err(CallsyError::Io { message : format!("Failed to read body output file {:?} to find its checksum. {}", path, source), source }),
     }
 }
 
-fn open_input_file(path : &std::path::PathBuf) -> Result<std::fs::File, String> {
-    match File::open(path) {
-        Ok(file) => Ok(file),
-        Err(error) => Err(format!("Failed to open input file. OS error: {}", error.raw_os_error().unwrap())),
+async fn convert_response(name : Option<String>, response : Response, config : &crate::config::Config) -> Result<OutputResponse, CallsyError> {
+    
+    let http_version = format!("{:?}", response.version());
+    let status_code = response.status().as_u16();
+    let reason = response.status().canonical_reason().map(String::from);
+    let remote_address = response.remote_addr();
+    let certificate =
+        response
+        .extensions()
+        .get::<reqwest::tls::TlsInfo>()
+        .and_then(|tls_info| tls_info.peer_certificate())
+        .and_then(crate::certificate::describe);
+
+    let mut headers = HashMap::new();
+
+    // Headers which are repeated in the response, such as set-cookie, keep each of their values.
+    for (k, v) in response.headers().iter() {
+        headers
+        .entry(String::from(k.as_str()))
+        .or_insert_with(Vec::new)
+        .push(String::from(v.to_str().unwrap_or_default()));
     }
-}
 
-fn read_input_file(mut file : std::fs::File) -> Result<String, String> {
-    let mut content = String::new();
+    let resumed_from = config.download.as_ref().filter(|_| status_code == 206).map(|download| download.offset);
+    let binary = crate::naming::has_binary_content_type(&headers);
+    let (bytes, body_size_exceeded) = read_body(response, config).await?;
 
-    match file.read_to_string(&mut content) {
-        Ok(_) => Ok(content),
-        Err(error) => Err(format!("Failed to read input file. OS error: {}", error.raw_os_error().unwrap()))
+    if body_size_exceeded.is_some() {
+        tracing::warn!(limit = config.max_body_size, "The response body exceeded the maximum size, so its download was stopped");
     }
+
+    let body_sha256 = match &config.download {
+        _ if body_size_exceeded.is_some() => None,
+        Some(download) if status_code == 200 || status_code == 206 => Some(file_sha256(&download.path)?),
+        _ => Some(sha256_hex(&bytes)),
+    };
+
+    // The checksum above is of the bytes as they were received, before text is decoded from them.
+    let (body, binary_body, received_body) = match binary {
+        true => (String::new(), Some(bytes), None),
+        false => (crate::naming::decode_text(&bytes, &headers), None, Some(bytes)),
+    };
+    let body_file = match (&binary_body, &config.sidecar_output) {
+        (Some(bytes), Some(output_path)) if !bytes.is_empty() => {
+            let path = crate::naming::sidecar_path(output_path, bytes, crate::naming::content_type_extension(&headers));
+            write_atomically(&path, bytes, config.fsync)?;
+            tracing::debug!(?path, "Wrote binary body to sidecar file");
+            Some(path)
+        },
+        _ => None,
+    };
+
+    Ok(OutputResponse {
+        name,
+        headers,
+        http_version,
+        status_code,
+        reason,
+        remote_address,
+        local_address : None,
+        connection_reused : None,
+        certificate,
+        certificate_chain : None,
+        tls_version : None,
+        started : None,
+        completed : None,
+        duration : None,
+        pages : None,
+        body,
+        body_sha256,
+        body_file,
+        binary_body,
+        received_body,
+        body_size_exceeded,
+        body_truncated : None,
+        resumed_from,
+        served_by : None,
+        grpc_web : None,
+        jsonrpc : None,
+        idempotency_key : None,
+        range_satisfied : None,
+        url : None,
+        negotiated : None,
+        request : None,
+        location_response : None,
+    })
 }
 
-fn deserialize_request_data(request_data : &str) -> Result<RawRequest, String> {
-    match serde_json::from_str(request_data) {
-        Ok(data) => Ok(data),
-        Err(error) => Err(format!("Unable to deserialise data from input file at line {}, column {}.", error.line(), error.column())),
+impl OutputResponse {
+    fn decode_grpc_web(&mut self, proto : Option<&crate::proto::Compiled>) {
+        if !crate::grpc::is_grpc_web(&self.headers) {
+            return;
+        }
+
+        let body = self.binary_body.as_deref().unwrap_or(self.body.as_bytes());
+        match crate::grpc::decode_response(body, &self.headers, proto) {
+            Ok(grpc_web) => self.grpc_web = Some(grpc_web),
+            Err(error) => tracing::warn!(%error, "Failed to decode the gRPC-Web response"),
+        }
     }
-}
 
-fn check_extra_fields(raw_request : &RawRequest) -> Result<(), String> {
-    match &raw_request.other {
-        serde_json::Value::Object(m) => {
-            if m.is_empty() {
-                Ok(())
-            }
-            else {
-                let mut extra_keys = String::new();
-                for key in m.keys() {
-                    extra_keys.push_str(&key);
-                    extra_keys.push_str(", ");
-                }
-                Err(format!("Request JSON file included fields unknown to callsy: {}", extra_keys))
-            }
+    // A protobuf body is decoded to JSON in place of the empty body of a binary response, with the bytes
+    // themselves still written to a file.
+    fn decode_protobuf(&mut self, proto : &crate::proto::Compiled) {
+        if !crate::naming::has_protobuf_content_type(&self.headers) {
+            return;
+        }
+
+        let bytes = self.binary_body.as_deref().unwrap_or_default();
+        match proto.decode(bytes) {
+            Ok(Some(json)) => self.body = json.to_string(),
+            Ok(None) => {},
+            Err(error) => tracing::warn!(%error, "Failed to decode the protobuf response"),
         }
-        _ => Err(String::from("Request JSON file included fields unknown to callsy."))
     }
-}
 
-fn get_body(raw_request : &RawRequest) -> Result<String, String> {
-    match (&raw_request.body_path, &raw_request.body) {
-        (Some(_), Some(_)) => {
-            Err(String::from("Cannot provide both a body and body_path."))
-        },
-        (Some(path), None) => {
-            let mut file = match File::open(path) {
-                Ok(file) => file,
-                Err(error) => { return Err(format!("Failed to open the body file. OS error: {}", error.raw_os_error().unwrap())); }
-            };
+    // A response without a body or a content type has nothing to check.
+    fn check_negotiation(&mut self, prefer : &crate::negotiation::Prefer) {
+        let content_type = self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case("content-type"))
+            .and_then(|(_, values)| values.first())
+            .cloned();
+        let negotiated = match &content_type {
+            Some(content_type) => prefer.matches(content_type),
+            None if self.body.is_empty() && self.binary_body.as_ref().is_none_or(Vec::is_empty) => return,
+            None => false,
+        };
 
-            let mut body = String::new();
-            match file.read_to_string(&mut body) {
-                Ok(_) => Ok(body),
-                Err(error) => Err(format!("Failed to read body file. OS error: {}", error.raw_os_error().unwrap()))
-            }
-        },
-        (None, Some(body)) => {
-            Ok(body.to_string())
-        },
-        (None, None) => {
-            Ok(String::from(""))
+        if !negotiated {
+            tracing::warn!(content_type = content_type.as_deref().unwrap_or("none"), status_code = self.status_code, "The response does not have any of the preferred content types");
         }
-    }    
-}
+        self.negotiated = Some(negotiated);
+    }
 
-fn process_request_data(raw_request : RawRequest, body : String) -> Result<ProcessedRequest, String> {
-    
-    fn convert_http_method(raw_request : &RawRequest) -> Result<Method, String> {
-        match Method::from_bytes(raw_request.method.to_uppercase().as_bytes()) {
-            Ok(method) => Ok(method),
-            Err(_) => Err(format!("The provided HTTP method of {} is invalid.", raw_request.method)),
+    // MessagePack and CBOR bodies are decoded the same way, whatever the body type of the request.
+    fn decode_msgpack(&mut self) {
+        if !crate::naming::has_msgpack_content_type(&self.headers) {
+            return;
+        }
+
+        let bytes = self.binary_body.as_deref().unwrap_or_default();
+        match crate::msgpack::decode_response(bytes) {
+            Ok(json) => self.body = json.to_string(),
+            Err(error) => tracing::warn!(%error, "Failed to decode the MessagePack response"),
         }
     }
 
-    let method = convert_http_method(&raw_request)?;
+    fn decode_cbor(&mut self) {
+        if !crate::naming::has_cbor_content_type(&self.headers) {
+            return;
+        }
 
-    let mut headers = HashMap::new();
+        let bytes = self.binary_body.as_deref().unwrap_or_default();
+        match crate::cbor::decode_response(bytes) {
+            Ok(json) => self.body = json.to_string(),
+            Err(error) => tracing::warn!(%error, "Failed to decode the CBOR response"),
+        }
+    }
 
-    for (header, value) in raw_request.headers {
-        match value {
-            Some(value) => {
-                headers.insert(header, value);
-            },
-            None => {
-                match header.to_lowercase().as_str() {
-                    // Auto calculation of null headers where possible.
-                    "content-length" => {
-                        headers.insert(header, format!("{}", body.len()));
-                    },
-                    _ => return Err(format!("Cannot autocomplete value of {} header. Try supplying a value directly.", header))
-                }
-            },
+    fn decode_jsonrpc(&mut self) {
+        match crate::jsonrpc::decode_response(&self.body) {
+            Ok(jsonrpc) => self.jsonrpc = jsonrpc,
+            Err(error) => tracing::warn!(%error, "Failed to decode the JSON-RPC response"),
         }
     }
 
-    Ok(ProcessedRequest {
-        url : raw_request.url,
-        method,
-        headers,
-        body,
-    })
-}
+    // The body as it is written to a file of its own, which with pretty set is indented if it is JSON,
+    // XML or HTML. A body which isn't valid JSON is left as it was.
+    pub(crate) fn formatted_body(&self, pretty : bool) -> String {
+        if !pretty {
+            return self.body.clone();
+        }
 
+        match crate::naming::content_type_extension(&self.headers) {
+            Some("json") => {
+                serde_json::from_str::<serde_json::Value>(&self.body)
+                .ok()
+                .and_then(|json| serde_json::to_string_pretty(&json).ok())
+                .unwrap_or_else(|| self.body.clone())
+            },
+            Some("xml" | "html" | "svg") => crate::markup::indent(&self.body),
+            _ => self.body.clone(),
+        }
+    }
 
-async fn make_request(processed_request : ProcessedRequest) -> Result<Response, String> {
+    // The body as it is read in a terminal, with HTML rendered as text and anything else left as it was.
+    pub(crate) fn rendered_text(&self) -> String {
+        match crate::naming::content_type_extension(&self.headers) {
+            Some("html") => crate::markup::to_text(&self.body),
+            _ => self.body.clone(),
+        }
+    }
 
-    fn parse_url(url : &String) -> Result<reqwest::Url, String> {
-        match Url::parse(&url) {
-            Ok(url) => Ok(url),
-            Err(error) => Err(format!("Error while parsing URL. {}", error)),
+    // The body as it is written to the -b file, which is the bytes as they were received. Only a body
+    // which was put together from several, such as the pages of a paginated request, is written as text.
+    pub(crate) fn body_bytes(&self) -> Vec<u8> {
+        match (&self.binary_body, &self.received_body) {
+            (Some(bytes), _) | (None, Some(bytes)) => bytes.clone(),
+            (None, None) => self.body.clone().into_bytes(),
         }
     }
-    
-    let url = parse_url(&processed_request.url)?;
-    let body = reqwest::Body::from(processed_request.body); 
-    let mut headers = reqwest::header::HeaderMap::new();
-    for (k, v) in processed_request.headers.iter() {
-        headers.insert(
-            reqwest::header::HeaderName::from_bytes(k.as_bytes()).unwrap(),
-            reqwest::header::HeaderValue::from_str(v).unwrap()
-        );
+
+    // The parts of the response which are compared between environments, with a JSON body parsed so
+    // that it can be compared value by value.
+    pub(crate) fn comparable(&self) -> serde_json::Value {
+        let body = serde_json::from_str::<serde_json::Value>(&self.body).unwrap_or_else(|_| serde_json::Value::from(self.body.as_str()));
+        serde_json::json!({
+            "status_code" : self.status_code,
+            "headers" : self.headers,
+            "body" : body,
+        })
     }
 
-    match
-        Client::new()
-        .request(processed_request.method, url)
-        .body(body)
-        .headers(headers)
-        .send().await {
+    pub(crate) fn status_code(&self) -> u16 {
+        self.status_code
+    }
 
-        Ok(res) => Ok(res),
-        Err(error) => Err(format!("Error when sending the request, {}", error)),
+    pub(crate) fn connection_reused(&self) -> Option<bool> {
+        self.connection_reused
+    }
+
+    pub(crate) fn url(&self) -> Option<&Url> {
+        self.url.as_ref()
+    }
+
+    // The status line, headers and body, in the form they were received.
+    pub(crate) fn summary(&self) -> String {
+        let mut summary = format!("{} {} {}", self.http_version, self.status_code, self.reason.as_deref().unwrap_or_default());
+        for (header, values) in &self.headers {
+            for value in values {
+                summary.push_str(&format!("\n{}: {}", header, value));
+            }
+        }
+        summary.push_str(&format!("\n\n{}", self.body));
+        summary
     }
 }
 
-async fn convert_response(response : Response) -> Result<OutputResponse, String> {
-    
-    let status_code = String::from(
-        response.status().as_str()
-    );
+fn capture_variables(captures : &HashMap<String, Capture>, output_response : &OutputResponse) -> Result<crate::variables::Variables, String> {
+    let mut captured = crate::variables::Variables::new();
 
-    let mut headers = HashMap::new();
+    if captures.is_empty() {
+        return Ok(captured);
+    }
 
-    for (k, v) in response.headers().iter() {
-        headers.insert(
-            String::from(k.as_str()),
-            String::from(
-                match v.to_str() {
-                    Ok(value) => value,
-                    Err(_) => "",
+    let body = serde_json::from_str::<serde_json::Value>(&output_response.body).ok();
+
+    for (variable, capture) in captures {
+        let value = match capture {
+            Capture::Body(pointer) => {
+                match body.as_ref().and_then(|body| body.pointer(pointer)) {
+                    Some(serde_json::Value::String(value)) => value.clone(),
+                    Some(value) => value.to_string(),
+                    None => return Err(format!("Could not capture {} as the response body has no value at {}.", variable, pointer)),
                 }
-            )
-        );
-    }
+            },
+            Capture::Header(header) => {
+                match output_response.headers.iter().find(|(name, _)| name.eq_ignore_ascii_case(header)).and_then(|(_, values)| values.first()) {
+                    Some(value) => value.clone(),
+                    None => return Err(format!("Could not capture {} as the response has no {} header.", variable, header)),
+                }
+            },
+            Capture::StatusCode => output_response.status_code.to_string(),
+        };
 
-    let body = match &response.text().await {
-        Ok(body) => body,
-        Err(error) => return Err(format!("Failed to get text from response body, {}", error))
-    }.clone();
+        tracing::debug!(variable, "Captured variable from response");
+        captured.insert(variable.clone(), crate::variables::Variable::Value(value));
+    }
 
-    Ok(OutputResponse {
-        headers,
-        status_code,
-        body,
-    })
+    Ok(captured)
 }
 
-fn serialize_response(output_response : OutputResponse) -> String {
-    match serde_json::to_string(&output_response) {
+fn serialize_response<T : serde::Serialize>(output_response : &T) -> String {
+    match serde_json::to_string(output_response) {
         Ok(result) => result,
         Err(_) => panic!("Internal error, could not serialize JSON data for response"),
     }
 }
 
+fn sort_keys(value : &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(object) => {
+            object.sort_keys();
+            object.values_mut().for_each(sort_keys);
+        },
+        serde_json::Value::Array(array) => array.iter_mut().for_each(sort_keys),
+        _ => {},
+    }
+}
 
+// Keys are sorted at every level, as headers otherwise come out in a different order each run, and the
+// JSON is indented with a newline at the end of the file.
+fn serialize_canonical<T : serde::Serialize>(output_response : &T) -> String {
+    let mut value = match serde_json::to_value(output_response) {
+        Ok(value) => value,
+        Err(_) => panic!("Internal error, could not serialize JSON data for response"),
+    };
+    sort_keys(&mut value);
 
-fn open_output_file(path : &std::path::PathBuf) -> Result<std::fs::File, String> {
-    match File::create(path) {
-        Ok(file) => Ok(file),
-        Err(error) => Err(format!("Failed to create output file. OS error {}", error.raw_os_error().unwrap())),
+    match serde_json::to_string_pretty(&value) {
+        Ok(result) => result + "\n",
+        Err(_) => panic!("Internal error, could not serialize JSON data for response"),
+    }
+}
+
+
+
+fn write_output(path : &std::path::PathBuf, serialized_response : String, show_secrets : bool, sync : bool) -> Result<(), CallsyError> {
+    let serialized_response = if show_secrets {
+        serialized_response
+    }
+    else {
+        crate::masking::mask(&serialized_response)
+    };
+
+    write_atomically(path, serialized_response.as_bytes(), sync)?;
+    tracing::debug!(?path, "Wrote output file");
+    Ok(())
+}
+
+// Files are written to a temporary file beside them which is then renamed into place, so that a run
+// which is interrupted or fails part way never leaves a truncated file behind. With sync, the data is
+// flushed to disk before the rename.
+pub(crate) fn write_atomically(path : &std::path::Path, content : &[u8], sync : bool) -> Result<(), CallsyError> {
+    let file_name = path.file_name().map(|file_name| file_name.to_string_lossy().into_owned()).unwrap_or_default();
+    let temporary_path = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+
+    let result = match File::create(&temporary_path) {
+        Ok(file) => write_to_output_file(file, content, sync),
+        Err(source) => Err(CallsyError::Io { message : format!("Failed to create output file {:?}. {}", path, source), source }),
+    };
+
+    let result = result.and_then(|()| {
+        match std::fs::rename(&temporary_path, path) {
+            Ok(()) => Ok(()),
+            Err(source) => Err(CallsyError::Io { message : format!("Failed to move the output into {:?}. {}", path, source), source }),
+        }
+    });
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temporary_path);
     }
+    result
 }
 
-fn write_to_output_file(mut file : std::fs::File, content : String) -> Result<(), String> {
-    match file.write(&content.as_bytes()) {
-        Ok(_) => Ok(()),
-        Err(error) => Err(format!("Failed to write to output file. OS error {}", error))
+fn write_to_output_file(mut file : std::fs::File, content : &[u8], sync : bool) -> Result<(), CallsyError> {
+    let result = match file.write_all(content) {
+        Ok(()) if sync => file.sync_all(),
+        result => result,
+    };
+
+    match result {
+        Ok(()) => Ok(()),
+        Err(source) => Err(CallsyError::Io { message : format!("Failed to write to output file. {}", source), source }),
     }
 }
 
-fn open_and_write_to_body_output_file(path : &Option<std::path::PathBuf>, body : String) -> Result<(), String> {
+fn open_and_write_to_body_output_file(path : &Option<std::path::PathBuf>, body : Vec<u8>, sync : bool) -> Result<(), CallsyError> {
     match path {
-        Some(path) => {
-            let mut file = match File::create(path) {
-                Ok(file) => file,
-                Err(error) => {
-                    return Err(format!("Failed to create output file. OS error {}", error.raw_os_error().unwrap()));
-                }
-            };
+        Some(path) => write_atomically(path, &body, sync),
+        None => Ok(()),
+    }
+}
 
-            match file.write(&body.as_bytes()) {
-                Ok(_) => Ok(()),
-                Err(error) => Err(format!("Failed to write to output file. OS error {}", error.raw_os_error().unwrap())),
-            }
-        },
-        None => {
-            Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connect_to_targets_may_have_ports() {
+        assert_eq!(connect_to_target("10.0.0.12"), ("10.0.0.12", None));
+        assert_eq!(connect_to_target("origin.internal:8443"), ("origin.internal", Some(8443)));
+        assert_eq!(connect_to_target("[::1]:8443"), ("::1", Some(8443)));
+        assert_eq!(connect_to_target("[::1]"), ("::1", None));
+        assert_eq!(connect_to_target("::1"), ("::1", None));
+    }
+
+    fn composed(request : serde_json::Value) -> Result<String, String> {
+        let mut fields = serde_json::json!({ "method" : "GET", "headers" : {} });
+        fields.as_object_mut().unwrap().extend(request.as_object().unwrap().clone());
+        let mut raw_request = serde_json::from_value::<RawRequest>(fields).unwrap();
+        compose_url(&mut raw_request)
+    }
+
+    #[test]
+    fn path_is_joined_to_base_url_with_one_slash() {
+        for (base_url, path) in [("https://somedomain.com/api", "users/1"), ("https://somedomain.com/api//", "//users/1"), ("https://somedomain.com/api/", "/users/1")] {
+            assert_eq!(composed(serde_json::json!({ "base_url" : base_url, "path" : path })).unwrap(), "https://somedomain.com/api/users/1");
         }
-    } 
+        assert_eq!(composed(serde_json::json!({ "base_url" : "https://somedomain.com/api" })).unwrap(), "https://somedomain.com/api");
+        assert_eq!(composed(serde_json::json!({ "path" : "users/1" })).unwrap(), "users/1");
+    }
+
+    #[test]
+    fn path_segments_are_encoded() {
+        let url = composed(serde_json::json!({ "base_url" : "https://somedomain.com", "path" : "files/a b?#%/ü" })).unwrap();
+
+        assert_eq!(url, "https://somedomain.com/files/a%20b%3F%23%25/%C3%BC");
+    }
+
+    #[test]
+    fn query_is_added_before_fragment() {
+        let query = serde_json::json!({ "q" : "a b&c", "tag" : ["x", "y"] });
+
+        assert_eq!(composed(serde_json::json!({ "base_url" : "https://somedomain.com", "path" : "search", "query" : query })).unwrap(), "https://somedomain.com/search?q=a+b%26c&tag=x&tag=y");
+        assert_eq!(composed(serde_json::json!({ "url" : "https://somedomain.com/search?page=2#results", "query" : { "q" : "a" } })).unwrap(), "https://somedomain.com/search?page=2&q=a#results");
+    }
+
+    #[test]
+    fn conflicting_or_missing_fields_are_refused() {
+        assert!(composed(serde_json::json!({ "url" : "https://somedomain.com", "path" : "users" })).is_err());
+        assert!(composed(serde_json::json!({ "base_url" : "https://somedomain.com", "base_urls" : ["https://other.com"], "path" : "users" })).is_err());
+        assert!(composed(serde_json::json!({})).is_err());
+    }
 }
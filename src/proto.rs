@@ -0,0 +1,115 @@
+use std::path::PathBuf;
+
+use prost::Message;
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor};
+
+// The protobuf message types of a request and its response, from a .proto file which is compiled when the
+// request is sent, so that bodies can be written as JSON without generating any code.
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Proto {
+    file : PathBuf,
+    // Directories to find imported files in, which is the directory of the file if none are given.
+    #[serde(default)]
+    include : Vec<PathBuf>,
+    // Full names of message types, such as helloworld.HelloRequest.
+    request : Option<String>,
+    response : Option<String>,
+}
+
+// The message types of a proto once its file has been compiled.
+pub struct Compiled {
+    request : Option<MessageDescriptor>,
+    response : Option<MessageDescriptor>,
+}
+
+impl Proto {
+    fn descriptors(&self) -> Result<DescriptorPool, String> {
+        let includes = match self.include.is_empty() {
+            true => vec![self.file.parent().filter(|parent| !parent.as_os_str().is_empty()).map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."))],
+            false => self.include.clone(),
+        };
+
+        let parsed = protobuf_parse::Parser::new()
+            .pure()
+            .includes(&includes)
+            .input(&self.file)
+            .parse_and_typecheck();
+        let parsed = match parsed {
+            Ok(parsed) => parsed,
+            Err(error) => return Err(format!("Failed to compile the proto file {:?}. {:#}", self.file, error)),
+        };
+
+        // The parser and the reflection library describe files with different types, so the descriptors
+        // are passed between them in their serialized form.
+        let set = protobuf::descriptor::FileDescriptorSet {
+            file : parsed.file_descriptors,
+            ..Default::default()
+        };
+        let serialized = match protobuf::Message::write_to_bytes(&set) {
+            Ok(serialized) => serialized,
+            Err(error) => return Err(format!("Failed to compile the proto file {:?}. {}", self.file, error)),
+        };
+
+        match DescriptorPool::decode(serialized.as_slice()) {
+            Ok(pool) => Ok(pool),
+            Err(error) => Err(format!("Failed to compile the proto file {:?}. {}", self.file, error)),
+        }
+    }
+
+    pub fn compile(&self) -> Result<Compiled, String> {
+        let pool = self.descriptors()?;
+        let find = |name : &Option<String>| -> Result<Option<MessageDescriptor>, String> {
+            match name {
+                Some(name) => match pool.get_message_by_name(name.trim_start_matches('.')) {
+                    Some(descriptor) => Ok(Some(descriptor)),
+                    None => Err(format!("There is no message type {} in the proto file {:?}.", name, self.file)),
+                },
+                None => Ok(None),
+            }
+        };
+
+        Ok(Compiled {
+            request : find(&self.request)?,
+            response : find(&self.response)?,
+        })
+    }
+}
+
+impl Compiled {
+    // Encodes a body written as JSON, using the protobuf JSON mapping, as the request message type.
+    pub fn encode(&self, body : &str) -> Result<Vec<u8>, String> {
+        let descriptor = match &self.request {
+            Some(descriptor) => descriptor,
+            None => return Err(String::from("The proto needs a request message type to encode the body with.")),
+        };
+
+        let mut deserializer = serde_json::Deserializer::from_str(body);
+        let message = DynamicMessage::deserialize(descriptor.clone(), &mut deserializer).and_then(|message| deserializer.end().map(|()| message));
+        match message {
+            Ok(message) => Ok(message.encode_to_vec()),
+            Err(error) => Err(format!("The body is not a valid {} message. {}", descriptor.full_name(), error)),
+        }
+    }
+
+    pub fn has_response(&self) -> bool {
+        self.response.is_some()
+    }
+
+    // Decodes a message of the response type to JSON, or gives None when there is no response type.
+    pub fn decode(&self, bytes : &[u8]) -> Result<Option<serde_json::Value>, String> {
+        let descriptor = match &self.response {
+            Some(descriptor) => descriptor,
+            None => return Ok(None),
+        };
+
+        let message = match DynamicMessage::decode(descriptor.clone(), bytes) {
+            Ok(message) => message,
+            Err(error) => return Err(format!("The response is not a valid {} message. {}", descriptor.full_name(), error)),
+        };
+        match serde_json::to_value(&message) {
+            Ok(json) => Ok(Some(json)),
+            Err(error) => Err(format!("Failed to convert the {} message to JSON. {}", descriptor.full_name(), error)),
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use rustyline::error::ReadlineError;
+
+use crate::error::CallsyError;
+use crate::processing::{OutputResponse, RawRequest, Session};
+use crate::variables::{Variable, Variables};
+
+const HELP : &str = "\
+load <file>             Load a request file as the current request
+new <method> <url>      Start a new request
+method <method>         Set the method of the current request
+url <url>               Set the URL of the current request
+header <name> [value]   Set a header, or autocomplete it if no value is given
+unheader <name>         Remove a header
+body <text>             Set the body of the current request
+env <file>              Load variables from an environment file
+set <name> <value>      Set a variable
+vars                    List the variables
+request                 Show the current request
+send                    Send the current request
+show                    Show the full last response
+help                    Show this message
+exit                    Leave the REPL";
+
+struct Repl {
+    request : Option<RawRequest>,
+    variables : Variables,
+    response : Option<OutputResponse>,
+}
+
+fn print_json<T : serde::Serialize>(value : &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(serialized) => println!("{}", crate::masking::mask(&serialized)),
+        Err(_) => panic!("Internal error, could not serialize JSON data"),
+    }
+}
+
+fn read_file(path : &str) -> Result<String, String> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(contents),
+        Err(error) => Err(format!("Failed to read {}. {}", path, error)),
+    }
+}
+
+impl Repl {
+    fn current_request(&mut self) -> Result<&mut RawRequest, String> {
+        match &mut self.request {
+            Some(request) => Ok(request),
+            None => Err(String::from("There is no current request, load or start one first.")),
+        }
+    }
+
+    async fn execute(&mut self, command : &str, argument : &str, session : &mut Session<'_>) -> Result<(), String> {
+        let (first, rest) = match argument.split_once(' ') {
+            Some((first, rest)) => (first, rest.trim()),
+            None => (argument, ""),
+        };
+
+        match (command, argument) {
+            ("help", _) => println!("{}", HELP),
+            ("load", path) if !path.is_empty() => {
+                let content = crate::include::resolve_request(read_file(path)?, std::path::Path::new(path))?;
+                let raw_request = match serde_json::from_str::<RawRequest>(&content) {
+                    Ok(raw_request) => raw_request,
+                    Err(error) => return Err(format!("Unable to parse request file {}. {}", path, error)),
+                };
+                self.request = Some(raw_request);
+            },
+            ("new", _) if !first.is_empty() && !rest.is_empty() => self.request = Some(RawRequest::new(first, rest)),
+            ("method", method) if !method.is_empty() => self.current_request()?.set_method(method),
+            ("url", url) if !url.is_empty() => self.current_request()?.set_url(url),
+            ("header", _) if !first.is_empty() => {
+                let value = if rest.is_empty() { None } else { Some(String::from(rest)) };
+                self.current_request()?.set_header(first, value);
+            },
+            ("unheader", name) if !name.is_empty() => self.current_request()?.remove_header(name),
+            ("body", body) => self.current_request()?.set_body(body),
+            ("env", path) if !path.is_empty() => {
+                let path = std::path::PathBuf::from(path);
+                self.variables.extend(crate::variables::load_environment(&path)?);
+            },
+            ("set", _) if !first.is_empty() => {
+                self.variables.insert(String::from(first), Variable::Value(String::from(rest)));
+            },
+            ("vars", _) => {
+                let mut names = self.variables.keys().collect::<Vec<_>>();
+                names.sort();
+                for name in names {
+                    match &self.variables[name] {
+                        Variable::Value(value) => println!("{} = {}", name, crate::masking::mask(value)),
+                        Variable::FromCommand(_) => println!("{} (from command)", name),
+                        Variable::Prompt(_) => println!("{} (prompt)", name),
+                    }
+                }
+            },
+            ("request", _) => {
+                let request = self.current_request()?;
+                println!("{}", crate::masking::mask(&request.describe()));
+            },
+            ("send", _) => {
+                let raw_request = self.current_request()?.clone();
+                match crate::processing::send_request(raw_request, session, &mut self.variables).await {
+                    Ok((output_response, captured)) => {
+                        self.variables.extend(captured);
+                        println!("{}", crate::masking::mask(&output_response.summary()));
+                        self.response = Some(output_response);
+                    },
+                    Err(error) => return Err(error.to_string()),
+                }
+            },
+            ("show", _) => {
+                match &self.response {
+                    Some(response) => print_json(response),
+                    None => return Err(String::from("No request has been sent yet.")),
+                }
+            },
+            _ => return Err(format!("Unknown or incomplete command {}, try help.", command)),
+        }
+
+        Ok(())
+    }
+}
+
+// An interactive prompt for building and sending requests. The current request, variables and the
+// last response are kept between commands, along with the history of the commands entered.
+pub async fn run(variables : Variables, session : &mut Session<'_>) -> Result<(), CallsyError> {
+    let mut editor = match rustyline::DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(error) => return Err(CallsyError::Usage(format!("Unable to start the REPL. {}", error))),
+    };
+
+    let mut repl = Repl {
+        request : None,
+        variables,
+        response : None,
+    };
+
+    println!("Type help for a list of commands.");
+
+    loop {
+        let line = match editor.readline("callsy> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => return Ok(()),
+            Err(error) => return Err(CallsyError::Usage(format!("Unable to read the command. {}", error))),
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line);
+
+        let (command, argument) = match line.split_once(' ') {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line, ""),
+        };
+
+        if command == "exit" || command == "quit" {
+            return Ok(());
+        }
+
+        if let Err(error) = repl.execute(command, argument, session).await {
+            println!("Error: {}", crate::masking::mask(&error));
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use crate::error::CallsyError;
+
+pub enum Outcome {
+    Passed,
+    // A response with an error status code.
+    Failed(String),
+    // A request which couldn't be sent, or whose response couldn't be processed.
+    Errored { category : &'static str, message : String },
+}
+
+pub struct Case {
+    pub name : String,
+    pub time : Duration,
+    // The request as it was written, before variables were substituted.
+    pub request : String,
+    pub response : Option<String>,
+    pub outcome : Outcome,
+}
+
+// The results of a run, with one case for each request sent, which can be written as a JUnit or HTML
+// report. Requests pass if they get a response with a status code below 400.
+pub struct Report {
+    pub suite : String,
+    pub cases : Vec<Case>,
+}
+
+impl Report {
+    pub fn new(suite : &str) -> Report {
+        Report {
+            suite : String::from(suite),
+            cases : Vec::new(),
+        }
+    }
+
+    pub fn add_response(&mut self, name : &str, time : Duration, request : String, status_code : u16, response : String) {
+        let outcome = if status_code < 400 {
+            Outcome::Passed
+        }
+        else {
+            Outcome::Failed(format!("Responded with status code {}.", status_code))
+        };
+
+        self.cases.push(Case {
+            name : String::from(name),
+            time,
+            request,
+            response : Some(response),
+            outcome,
+        });
+    }
+
+    pub fn add_error(&mut self, name : &str, time : Duration, request : String, error : &CallsyError) {
+        self.cases.push(Case {
+            name : String::from(name),
+            time,
+            request,
+            response : None,
+            outcome : Outcome::Errored { category : error.category(), message : String::from(error.message()) },
+        });
+    }
+
+    pub fn failures(&self) -> usize {
+        self.cases.iter().filter(|case| matches!(case.outcome, Outcome::Failed(_))).count()
+    }
+
+    pub fn errors(&self) -> usize {
+        self.cases.iter().filter(|case| matches!(case.outcome, Outcome::Errored { .. })).count()
+    }
+
+    pub fn time(&self) -> f64 {
+        self.cases.iter().map(|case| case.time.as_secs_f64()).sum()
+    }
+}
+
+// Escapes text for use in XML or HTML, both in content and attribute values.
+pub fn escape(text : &str) -> String {
+    text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&apos;")
+}
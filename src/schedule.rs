@@ -0,0 +1,94 @@
+use crate::error::CallsyError;
+use crate::processing::{RawRequest, Session};
+
+// Where to send word of a scheduled request which failed.
+pub struct Alerts {
+    pub command : Option<String>,
+    pub webhook : Option<reqwest::Url>,
+}
+
+#[derive(Serialize)]
+struct Failure<'a> {
+    name : &'a str,
+    // When the request was sent, in RFC 3339 format.
+    time : String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status_code : Option<u16>,
+    message : &'a str,
+}
+
+// Cron expressions have five fields (minute, hour, day of month, month and day of week), as in crontab,
+// or six with seconds first for checks more often than once a minute.
+pub fn parse(expression : &str) -> Result<croner::Cron, CallsyError> {
+    match croner::Cron::new(expression).with_seconds_optional().parse() {
+        Ok(cron) => Ok(cron),
+        Err(error) => Err(CallsyError::Usage(format!("Invalid cron expression \"{}\". {}", expression, error))),
+    }
+}
+
+// Sends the request each time the cron expression matches in local time, until callsy is stopped. Times
+// which pass while a slow request is still being sent are skipped.
+pub async fn run(cron : &croner::Cron, name : &str, raw_request : RawRequest, variables : &crate::variables::Variables, session : &mut Session<'_>, alerts : &Alerts) -> Result<(), CallsyError> {
+    let mut after = chrono::Local::now();
+    loop {
+        let next = match cron.find_next_occurrence(&after, false) {
+            Ok(next) => next,
+            Err(error) => return Err(CallsyError::Usage(format!("The cron expression \"{}\" has no time to run next. {}", cron, error))),
+        };
+        tracing::debug!(%next, "Waiting for the next scheduled request");
+        if let Ok(wait) = (next - chrono::Local::now()).to_std() {
+            tokio::time::sleep(wait).await;
+        }
+
+        let time = chrono::Local::now();
+        let description = raw_request.describe();
+        let started = std::time::Instant::now();
+        let sent = crate::processing::send_request(raw_request.clone(), session, &mut variables.clone()).await;
+        session.add_result(name, description, started, &sent);
+
+        // Failures are the same as for --abort-after, no response or one with a status of 400 or more.
+        let (status_code, failure) = match &sent {
+            Ok((output_response, _)) if output_response.status_code() >= 400 => {
+                (Some(output_response.status_code()), Some(format!("Responded with status {}.", output_response.status_code())))
+            },
+            Ok((output_response, _)) => (Some(output_response.status_code()), None),
+            Err(error) => (None, Some(crate::masking::mask(&error.to_string()))),
+        };
+
+        match &failure {
+            Some(message) => println!("{} {} failed in {:.3}s. {}", time.to_rfc3339(), name, started.elapsed().as_secs_f64(), message),
+            None => println!("{} {} succeeded with status {} in {:.3}s", time.to_rfc3339(), name, status_code.unwrap_or_default(), started.elapsed().as_secs_f64()),
+        }
+
+        if let Some(message) = &failure {
+            let failure = Failure { name, time : time.to_rfc3339(), status_code, message };
+            alert(alerts, &failure).await;
+        }
+
+        after = std::cmp::max(next, chrono::Local::now());
+    }
+}
+
+// Failing to alert is reported, but the schedule carries on.
+async fn alert(alerts : &Alerts, failure : &Failure<'_>) {
+    if let Some(command) = &alerts.command {
+        let status_code = failure.status_code.map(|status_code| status_code.to_string()).unwrap_or_default();
+        let env = [
+            ("CALLSY_NAME", failure.name),
+            ("CALLSY_TIME", failure.time.as_str()),
+            ("CALLSY_STATUS_CODE", status_code.as_str()),
+            ("CALLSY_MESSAGE", failure.message),
+        ];
+        if let Err(error) = tokio::task::block_in_place(|| crate::command::run_command_with_env(command, &env)) {
+            tracing::warn!(%error, "Failed to run the failure command");
+        }
+    }
+
+    if let Some(webhook) = &alerts.webhook {
+        match reqwest::Client::new().post(webhook.clone()).json(failure).send().await {
+            Ok(response) if !response.status().is_success() => tracing::warn!(status = %response.status(), "The failure webhook did not accept the alert"),
+            Ok(_) => {},
+            Err(error) => tracing::warn!(%error, "Failed to send the alert to the failure webhook"),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+
+use crate::error::CallsyError;
+
+// Fields which are resolved when a request file is loaded, so that editors using the schema don't flag
+// them. What a $ref or $include brings in is only checked by validating the file once it is loaded.
+pub fn file_fields(schema : &mut schemars::Schema) {
+    if let Some(properties) = schema.get_mut("properties").and_then(serde_json::Value::as_object_mut) {
+        properties.insert(String::from("extends"), serde_json::json!({ "type" : "string" }));
+        properties.insert(String::from("$include"), serde_json::json!({ "type" : ["string", "array"], "items" : { "type" : "string" } }));
+    }
+}
+
+pub fn schema(collection : bool) -> serde_json::Value {
+    match collection {
+        true => schemars::schema_for!(crate::collection::Collection).to_value(),
+        false => schemars::schema_for!(crate::processing::RawRequest).to_value(),
+    }
+}
+
+fn load(path : &PathBuf, collection : bool) -> Result<String, CallsyError> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(source) => return Err(CallsyError::Io { message : format!("Failed to read input file {:?}. {}", path, source), source }),
+    };
+
+    let resolved = match collection {
+        true => crate::include::resolve_collection(content, path),
+        false => crate::include::resolve_request(content, path),
+    };
+    resolved.map_err(CallsyError::File)
+}
+
+// Prints each way in which the files don't match the schema, failing if any of them don't.
+pub fn validate(paths : &[PathBuf], collection : bool) -> Result<(), CallsyError> {
+    let validator = match jsonschema::validator_for(&schema(collection)) {
+        Ok(validator) => validator,
+        Err(error) => panic!("Internal error, could not compile the JSON schema. {}", error),
+    };
+
+    let mut invalid = 0;
+    for path in paths {
+        let instance = match serde_json::from_str::<serde_json::Value>(&load(path, collection)?) {
+            Ok(instance) => instance,
+            Err(error) => {
+                println!("{}: not valid JSON. {}", path.display(), error);
+                invalid += 1;
+                continue;
+            },
+        };
+
+        let errors : Vec<_> = validator.iter_errors(&instance).collect();
+        if errors.is_empty() {
+            println!("{}: valid", path.display());
+            continue;
+        }
+
+        invalid += 1;
+        for error in errors {
+            let location = error.instance_path.to_string();
+            println!("{}: {} {}", path.display(), if location.is_empty() { "/" } else { &location }, error);
+        }
+    }
+
+    match invalid {
+        0 => Ok(()),
+        invalid => Err(CallsyError::InvalidRequest(format!("{} of {} files do not match the schema.", invalid, paths.len()))),
+    }
+}
@@ -0,0 +1,32 @@
+const SERVICE : &str = "callsy";
+
+fn entry(name : &str) -> Result<keyring::Entry, String> {
+    match keyring::Entry::new(SERVICE, name) {
+        Ok(entry) => Ok(entry),
+        Err(error) => Err(format!("Failed to access the secret {} in the keychain. {}", name, error)),
+    }
+}
+
+pub fn get_secret(name : &str) -> Result<String, String> {
+    match entry(name)?.get_password() {
+        Ok(secret) => Ok(secret),
+        Err(keyring::Error::NoEntry) => Err(format!("The secret {} is not in the keychain. Try adding it with `callsy secret set {}`.", name, name)),
+        Err(error) => Err(format!("Failed to read the secret {} from the keychain. {}", name, error)),
+    }
+}
+
+// A secret which may not have been set yet, such as one callsy makes for itself on first use.
+pub fn find_secret(name : &str) -> Result<Option<String>, String> {
+    match entry(name)?.get_password() {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(error) => Err(format!("Failed to read the secret {} from the keychain. {}", name, error)),
+    }
+}
+
+pub fn set_secret(name : &str, secret : &str) -> Result<(), String> {
+    match entry(name)?.set_password(secret) {
+        Ok(_) => Ok(()),
+        Err(error) => Err(format!("Failed to write the secret {} to the keychain. {}", name, error)),
+    }
+}
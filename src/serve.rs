@@ -0,0 +1,289 @@
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::error::CallsyError;
+use crate::processing::{OutputResponse, RawRequest, Session};
+use crate::variables::{Variable, Variables};
+
+// Only the most recent results are kept, so that a long running server doesn't keep growing.
+const KEPT_RESULTS : usize = 100;
+
+// A request of the collection to send, with variables set for it alone, and where to reply with the result.
+struct Job {
+    index : usize,
+    variables : Variables,
+    done : oneshot::Sender<String>,
+}
+
+// The result of sending a request through the API, kept so that it can be fetched again later.
+#[derive(Serialize)]
+struct Sent<'a> {
+    id : usize,
+    name : &'a str,
+    // Seconds since the Unix epoch, when the request was started.
+    timestamp : f64,
+    time : f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response : Option<&'a OutputResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error : Option<&'a CallsyError>,
+}
+
+// What is shared between connections. The requests themselves are sent one at a time by the worker,
+// which owns the session, so that variables captured by one request are there for the next.
+struct Api {
+    // The bearer token every request must give, which is made anew each time the API is served.
+    token : String,
+    names : Vec<String>,
+    descriptions : Vec<String>,
+    // Each result as masked JSON, with its id.
+    results : Mutex<Vec<(usize, String)>>,
+    jobs : mpsc::Sender<Job>,
+}
+
+impl Api {
+    fn results(&self) -> std::sync::MutexGuard<'_, Vec<(usize, String)>> {
+        match self.results.lock() {
+            Ok(results) => results,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+fn json_response(status : StatusCode, body : String) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response.headers_mut().insert(hyper::header::CONTENT_TYPE, hyper::header::HeaderValue::from_static("application/json"));
+    response
+}
+
+fn message_response(status : StatusCode, message : &str) -> Response<Body> {
+    json_response(status, serde_json::json!({ "message" : message }).to_string())
+}
+
+// The body of a request to send is an optional JSON object of variables to set for that request alone.
+async fn read_variables(body : Body) -> Result<Variables, String> {
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(error) => return Err(format!("Failed to read the body of the request. {}", error)),
+    };
+    if body.iter().all(|byte| byte.is_ascii_whitespace()) {
+        return Ok(Variables::new());
+    }
+
+    match serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(&body) {
+        Ok(object) => {
+            Ok(
+                object
+                .into_iter()
+                .map(|(name, value)| {
+                    let value = match value {
+                        serde_json::Value::String(value) => value,
+                        value => value.to_string(),
+                    };
+                    (name, Variable::Value(value))
+                })
+                .collect()
+            )
+        },
+        Err(error) => Err(format!("The body must be a JSON object of variables. {}", error)),
+    }
+}
+
+// Only hosts which name this machine are served, so that a web page can't reach the API through DNS
+// rebinding.
+fn is_local_host(host : &str) -> bool {
+    let host = match host.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.bytes().all(|byte| byte.is_ascii_digit()) => host,
+        _ => host,
+    };
+    matches!(host.to_ascii_lowercase().as_str(), "localhost" | "127.0.0.1" | "[::1]")
+}
+
+// Compares every byte, so that how long the comparison takes doesn't tell how much of the token was right.
+fn is_token(given : &str, token : &str) -> bool {
+    given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0, |difference, (given, token)| difference | (given ^ token)) == 0
+}
+
+// The response refusing the request, unless it's from a local client which knows the token. Browsers always send an
+// Origin header with cross-origin requests, so any request which has one is refused.
+fn check_request(api : &Api, parts : &hyper::http::request::Parts) -> Option<Response<Body>> {
+    let header = |name| parts.headers.get(name).and_then(|value : &hyper::header::HeaderValue| value.to_str().ok());
+
+    match header(hyper::header::HOST) {
+        Some(host) if is_local_host(host) => (),
+        _ => return Some(message_response(StatusCode::FORBIDDEN, "The Host header must be localhost or 127.0.0.1.")),
+    }
+    if parts.headers.contains_key(hyper::header::ORIGIN) {
+        return Some(message_response(StatusCode::FORBIDDEN, "Requests with an Origin header are not allowed."));
+    }
+
+    match header(hyper::header::AUTHORIZATION).and_then(|value| value.strip_prefix("Bearer ")) {
+        Some(given) if is_token(given.trim(), &api.token) => (),
+        _ => {
+            let mut response = message_response(StatusCode::UNAUTHORIZED, "The Authorization header must give the bearer token printed when the API was started.");
+            response.headers_mut().insert(hyper::header::WWW_AUTHENTICATE, hyper::header::HeaderValue::from_static("Bearer"));
+            return Some(response);
+        },
+    }
+
+    if parts.method == Method::POST {
+        let is_json = header(hyper::header::CONTENT_TYPE)
+            .map(|content_type| content_type.split(';').next().unwrap_or_default().trim().eq_ignore_ascii_case("application/json"))
+            .unwrap_or(false);
+        if !is_json {
+            return Some(message_response(StatusCode::UNSUPPORTED_MEDIA_TYPE, "The Content-Type header must be application/json."));
+        }
+    }
+
+    None
+}
+
+async fn respond(api : Arc<Api>, request : Request<Body>) -> Result<Response<Body>, Infallible> {
+    let (parts, body) = request.into_parts();
+    if let Some(response) = check_request(&api, &parts) {
+        return Ok(response);
+    }
+    let segments : Vec<String> = parts.uri.path().trim_matches('/').split('/').map(|segment| percent_encoding::percent_decode_str(segment).decode_utf8_lossy().into_owned()).collect();
+    let segments : Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    let response = match (&parts.method, segments.as_slice()) {
+        (&Method::GET, ["requests"]) => {
+            let requests : Vec<serde_json::Value> = api.names
+                .iter()
+                .zip(&api.descriptions)
+                .map(|(name, description)| serde_json::json!({ "name" : name, "request" : description }))
+                .collect();
+            json_response(StatusCode::OK, crate::masking::mask(&serde_json::Value::from(requests).to_string()))
+        },
+        (&Method::POST, ["requests", name]) => {
+            let index = match api.names.iter().position(|request_name| request_name == name) {
+                Some(index) => index,
+                None => return Ok(message_response(StatusCode::NOT_FOUND, &format!("There is no request named {} in the collection.", name))),
+            };
+            let variables = match read_variables(body).await {
+                Ok(variables) => variables,
+                Err(error) => return Ok(message_response(StatusCode::BAD_REQUEST, &error)),
+            };
+
+            let (done, result) = oneshot::channel();
+            if api.jobs.send(Job { index, variables, done }).await.is_err() {
+                return Ok(message_response(StatusCode::SERVICE_UNAVAILABLE, "The server is shutting down."));
+            }
+            match result.await {
+                Ok(result) => json_response(StatusCode::OK, result),
+                Err(_) => message_response(StatusCode::INTERNAL_SERVER_ERROR, "The request was dropped before it was sent."),
+            }
+        },
+        (&Method::GET, ["results"]) => {
+            let results = api.results();
+            let joined = results.iter().map(|(_, result)| result.as_str()).collect::<Vec<_>>().join(",");
+            json_response(StatusCode::OK, format!("[{}]", joined))
+        },
+        (&Method::GET, ["results", id]) => {
+            let results = api.results();
+            match results.iter().find(|(result_id, _)| id.parse() == Ok(*result_id)) {
+                Some((_, result)) => json_response(StatusCode::OK, result.clone()),
+                None => message_response(StatusCode::NOT_FOUND, &format!("There is no result with the id {}.", id)),
+            }
+        },
+        (_, ["requests"] | ["requests", _] | ["results"] | ["results", _]) => message_response(StatusCode::METHOD_NOT_ALLOWED, "The method is not allowed for this path."),
+        _ => message_response(StatusCode::NOT_FOUND, "The API serves /requests, /requests/{name}, /results and /results/{id}."),
+    };
+
+    Ok(response)
+}
+
+// Sends each request asked for through the API in turn, keeping the variables it captures.
+async fn work(api : &Api, raw_requests : &[RawRequest], mut jobs : mpsc::Receiver<Job>, mut variables : Variables, session : &mut Session<'_>) {
+    let mut id = 0;
+    while let Some(job) = jobs.recv().await {
+        id += 1;
+        let name = &api.names[job.index];
+        let raw_request = raw_requests[job.index].clone();
+        let mut request_variables = variables.clone();
+        request_variables.extend(job.variables);
+
+        let timestamp = match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+            Ok(now) => now.as_secs_f64(),
+            Err(_) => 0.0,
+        };
+        let description = raw_request.describe();
+        let started = std::time::Instant::now();
+        let sent = crate::processing::send_request(raw_request, session, &mut request_variables).await;
+        session.add_result(name, description, started, &sent);
+        tracing::info!(name, id, "Sent request through the API");
+
+        let result = Sent {
+            id,
+            name,
+            timestamp,
+            time : started.elapsed().as_secs_f64(),
+            response : sent.as_ref().ok().map(|(output_response, _)| output_response),
+            error : sent.as_ref().err(),
+        };
+        let serialized = match serde_json::to_string(&result) {
+            Ok(serialized) => crate::masking::mask(&serialized),
+            Err(_) => panic!("Internal error, could not serialize JSON data for result"),
+        };
+
+        if let Ok((_, captured)) = sent {
+            variables.extend(captured);
+        }
+
+        {
+            let mut results = api.results();
+            results.push((id, serialized.clone()));
+            if results.len() > KEPT_RESULTS {
+                results.remove(0);
+            }
+        }
+
+        // The client may have gone away while the request was being sent, but the result is still kept.
+        let _ = job.done.send(serialized);
+    }
+}
+
+// Serves the API on localhost until callsy is stopped. Requests without a name are named after their
+// position in the collection, such as "request 2". The token to send requests with is printed alongside the
+// address.
+pub async fn serve(port : u16, raw_requests : Vec<RawRequest>, variables : Variables, session : &mut Session<'_>) -> Result<(), CallsyError> {
+    let address = std::net::SocketAddr::from(([127, 0, 0, 1], port));
+    let (jobs, received) = mpsc::channel(16);
+
+    let api = Arc::new(Api {
+        token : hex::encode(rand::random::<[u8; 32]>()),
+        names : raw_requests.iter().enumerate().map(|(index, raw_request)| raw_request.name.clone().unwrap_or_else(|| format!("request {}", index + 1))).collect(),
+        descriptions : raw_requests.iter().map(|raw_request| raw_request.describe().lines().next().unwrap_or_default().to_string()).collect(),
+        results : Mutex::new(Vec::new()),
+        jobs,
+    });
+
+    let service_api = api.clone();
+    let make_service = make_service_fn(move |_| {
+        let api = service_api.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| respond(api.clone(), request)))
+        }
+    });
+
+    let server = match Server::try_bind(&address) {
+        Ok(builder) => builder.serve(make_service),
+        Err(error) => return Err(CallsyError::Usage(format!("Unable to serve the API on {}. {}", address, error))),
+    };
+
+    println!("Serving {} requests on http://{}", raw_requests.len(), address);
+    println!("Authorization: Bearer {}", api.token);
+
+    tokio::select! {
+        served = server => match served {
+            Ok(()) => Ok(()),
+            Err(error) => Err(CallsyError::Usage(format!("The API stopped unexpectedly. {}", error))),
+        },
+        () = work(&api, &raw_requests, received, variables, session) => Ok(()),
+    }
+}
@@ -0,0 +1,27 @@
+use crate::variables::Variables;
+
+pub fn render_body_template(path : &std::path::PathBuf, variables : &mut Variables, config : &crate::config::Config) -> Result<String, String> {
+    let template = match std::fs::read_to_string(path) {
+        Ok(template) => template,
+        Err(error) => return Err(format!("Failed to read body template {:?}. {}", path, error)),
+    };
+
+    // Templates can use any variable, so commands and prompts are all resolved before rendering.
+    let mut context = tera::Context::new();
+    for (name, value) in crate::variables::resolve_all(variables, config)? {
+        context.insert(name, &value);
+    }
+
+    match tera::Tera::one_off(&template, &context, false) {
+        Ok(body) => Ok(body),
+        Err(error) => {
+            let mut message = format!("Failed to render body template {:?}. {}", path, error);
+            let mut source = std::error::Error::source(&error);
+            while let Some(error) = source {
+                message.push_str(&format!(" {}", error));
+                source = error.source();
+            }
+            Err(message)
+        },
+    }
+}
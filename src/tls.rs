@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use rustls::client::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, CertificateError, ClientConfig, DigitallySignedStruct, RootCertStore, ServerName};
+
+use crate::error::CallsyError;
+
+// The error a handshake fails with when the server's certificate doesn't match any of the pins, which is
+// found again in the error from sending the request to report it as an untrusted certificate.
+#[derive(Debug)]
+struct PinMismatch;
+
+impl std::fmt::Display for PinMismatch {
+    fn fmt(&self, formatter : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(formatter, "The certificate presented by the server does not match any of the pinned certificates.")
+    }
+}
+
+impl std::error::Error for PinMismatch {}
+
+// Checks the certificate while connecting, so that nothing is sent to a server which isn't trusted, or which
+// doesn't have one of the pinned certificates. Invalid certificates are accepted by leaving out the
+// verification against the roots, but pins and the handshake signatures are still checked. The chain and
+// TLS version are reported for the output as they are seen.
+struct Verifier {
+    webpki : WebPkiVerifier,
+    accept_invalid : bool,
+    pins : Option<Vec<String>>,
+}
+
+impl ServerCertVerifier for Verifier {
+    fn verify_server_cert(&self, end_entity : &Certificate, intermediates : &[Certificate], server_name : &ServerName, scts : &mut dyn Iterator<Item = &[u8]>, ocsp_response : &[u8], now : std::time::SystemTime) -> Result<ServerCertVerified, rustls::Error> {
+        crate::connection::record(|observation| {
+            observation.handshake.certificates = std::iter::once(end_entity).chain(intermediates).map(|certificate| certificate.0.clone()).collect();
+        });
+
+        if !self.accept_invalid {
+            self.webpki.verify_server_cert(end_entity, intermediates, server_name, scts, ocsp_response, now)?;
+        }
+
+        if let Some(pins) = &self.pins {
+            // The pins were checked when the client was built, so can't be malformed here.
+            if !crate::certificate::matches_pin(&end_entity.0, pins).unwrap_or(false) {
+                return Err(rustls::Error::InvalidCertificate(CertificateError::Other(Arc::new(PinMismatch))));
+            }
+        }
+
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(&self, message : &[u8], certificate : &Certificate, signature : &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crate::connection::record(|observation| observation.handshake.tls_version = Some("TLSv1.2"));
+        self.webpki.verify_tls12_signature(message, certificate, signature)
+    }
+
+    fn verify_tls13_signature(&self, message : &[u8], certificate : &Certificate, signature : &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crate::connection::record(|observation| observation.handshake.tls_version = Some("TLSv1.3"));
+        self.webpki.verify_tls13_signature(message, certificate, signature)
+    }
+}
+
+fn root_certificates(tls : Option<&crate::config::Tls>) -> Result<RootCertStore, CallsyError> {
+    let mut roots = RootCertStore::empty();
+
+    match rustls_native_certs::load_native_certs() {
+        Ok(certificates) => {
+            let certificates : Vec<_> = certificates.into_iter().map(|certificate| certificate.0).collect();
+            roots.add_parsable_certificates(&certificates);
+        },
+        Err(error) => tracing::warn!(%error, "Failed to load the system's root certificates"),
+    }
+
+    if let Some(path) = tls.and_then(|tls| tls.ca_certificate.as_ref()) {
+        let pem = match std::fs::read(path) {
+            Ok(pem) => pem,
+            Err(source) => return Err(CallsyError::Io { message : format!("Failed to read CA certificate file {:?}. {}", path, source), source }),
+        };
+
+        match rustls_pemfile::certs(&mut pem.as_slice()) {
+            Ok(certificates) if !certificates.is_empty() => {
+                roots.add_parsable_certificates(&certificates);
+            },
+            _ => return Err(CallsyError::Config(format!("Failed to parse CA certificate file {:?}, which must hold PEM encoded certificates.", path))),
+        }
+    }
+
+    Ok(roots)
+}
+
+pub fn client_config(tls : Option<&crate::config::Tls>) -> Result<ClientConfig, CallsyError> {
+    let pins = tls.and_then(|tls| tls.pins.clone());
+    for pin in pins.iter().flatten() {
+        if !pin.starts_with("sha256//") {
+            return Err(CallsyError::Config(format!("The certificate pin {} should start with sha256//.", pin)));
+        }
+    }
+
+    // Without verification against the roots, the verifier is only used to check handshake signatures.
+    let accept_invalid = tls.and_then(|tls| tls.accept_invalid_certificates) == Some(true);
+    let roots = match accept_invalid {
+        true => RootCertStore::empty(),
+        false => root_certificates(tls)?,
+    };
+    let webpki = WebPkiVerifier::new(roots, None);
+
+    Ok(
+        ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(Verifier { webpki, accept_invalid, pins }))
+        .with_no_client_auth()
+    )
+}
+
+// Whether the request failed because the server's certificate didn't match the pins. The rustls error is
+// wrapped in IO errors, whose source skips over what they wrap, so those are unwrapped by hand.
+pub fn is_pin_mismatch(error : &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(error);
+    while let Some(error) = source {
+        if let Some(rustls::Error::InvalidCertificate(CertificateError::Other(other))) = error.downcast_ref::<rustls::Error>() {
+            return other.is::<PinMismatch>();
+        }
+        source = match error.downcast_ref::<std::io::Error>().and_then(std::io::Error::get_ref) {
+            Some(inner) => Some(inner),
+            None => error.source(),
+        };
+    }
+    false
+}
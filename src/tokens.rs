@@ -0,0 +1,166 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::Engine;
+use sha2::Digest;
+
+// An access token from an identity provider, kept between runs so that a new one isn't fetched for
+// every request while it is still valid.
+#[derive(Serialize, Deserialize)]
+pub struct CachedToken {
+    pub access_token : String,
+    // Seconds since the Unix epoch.
+    pub expires_at : u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token : Option<String>,
+}
+
+pub fn unix_time() -> u64 {
+    match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        Ok(now) => now.as_secs(),
+        Err(_) => 0,
+    }
+}
+
+impl CachedToken {
+    pub fn new(access_token : String, expires_in : u64, refresh_token : Option<String>) -> CachedToken {
+        CachedToken {
+            access_token,
+            expires_at : unix_time() + expires_in,
+            refresh_token,
+        }
+    }
+
+    // A token which expires within the next minute is treated as expired, so that it doesn't run out
+    // while the request is being sent.
+    pub fn is_fresh(&self) -> bool {
+        self.expires_at > unix_time() + 60
+    }
+}
+
+// The cache is encrypted with a key kept in the keychain, which is made the first time a token is cached,
+// so that the files alone don't give away live tokens.
+const KEY_NAME : &str = "token-cache-key";
+const NONCE_LENGTH : usize = 12;
+
+fn encryption_key(create : bool) -> Result<Option<Key<Aes256Gcm>>, String> {
+    if let Some(encoded) = crate::secrets::find_secret(KEY_NAME)? {
+        return match base64::engine::general_purpose::STANDARD.decode(encoded) {
+            Ok(key) if key.len() == 32 => Ok(Some(*Key::<Aes256Gcm>::from_slice(&key))),
+            _ => Err(format!("The secret {} in the keychain is not a valid key for the token cache.", KEY_NAME)),
+        };
+    }
+    if !create {
+        return Ok(None);
+    }
+
+    let key = Aes256Gcm::generate_key(OsRng);
+    crate::secrets::set_secret(KEY_NAME, &base64::engine::general_purpose::STANDARD.encode(key))?;
+    Ok(Some(key))
+}
+
+// Each file is a random nonce followed by the encrypted token. What the token was fetched for is
+// authenticated along with it, so that a file can't be swapped for another.
+fn seal(key : &Key<Aes256Gcm>, label : &str, plaintext : &[u8]) -> Vec<u8> {
+    let nonce = Aes256Gcm::generate_nonce(OsRng);
+    let payload = Payload { msg : plaintext, aad : label.as_bytes() };
+    match Aes256Gcm::new(key).encrypt(&nonce, payload) {
+        Ok(ciphertext) => [nonce.as_slice(), &ciphertext].concat(),
+        Err(_) => panic!("Internal error, could not encrypt cached token"),
+    }
+}
+
+fn open(key : &Key<Aes256Gcm>, label : &str, sealed : &[u8]) -> Option<Vec<u8>> {
+    if sealed.len() < NONCE_LENGTH {
+        return None;
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+    let payload = Payload { msg : ciphertext, aad : label.as_bytes() };
+    Aes256Gcm::new(key).decrypt(Nonce::from_slice(nonce), payload).ok()
+}
+
+// Tokens are cached in files named after a hash of what they were fetched for, such as the tenant,
+// client and scope, so that a change to any of them fetches a new token.
+fn cache_path(key : &str) -> Option<PathBuf> {
+    let hash = hex::encode(sha2::Sha256::digest(key.as_bytes()));
+    dirs::cache_dir().map(|cache| cache.join("callsy").join("tokens").join(format!("{}.bin", &hash[..16])))
+}
+
+pub fn load(key : &str) -> Option<CachedToken> {
+    let path = cache_path(key)?;
+    let sealed = std::fs::read(path).ok()?;
+    let encryption_key = match encryption_key(false) {
+        Ok(encryption_key) => encryption_key?,
+        Err(error) => {
+            tracing::warn!(%error, "Failed to read the key for the token cache");
+            return None;
+        },
+    };
+    let content = open(&encryption_key, key, &sealed)?;
+    let token = serde_json::from_slice::<CachedToken>(&content).ok()?;
+
+    crate::masking::register(&token.access_token);
+    if let Some(refresh_token) = &token.refresh_token {
+        crate::masking::register(refresh_token);
+    }
+
+    Some(token)
+}
+
+fn write(path : &PathBuf, content : &[u8]) -> std::io::Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.create(true).write(true).truncate(true);
+    // Only the user can read the cache, even though the tokens in it are encrypted.
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options.open(path)?.write_all(content)
+}
+
+// Failing to cache a token is reported, but the token can still be used.
+pub fn store(key : &str, token : &CachedToken) {
+    let path = match cache_path(key) {
+        Some(path) => path,
+        None => return,
+    };
+
+    let encryption_key = match encryption_key(true) {
+        Ok(Some(encryption_key)) => encryption_key,
+        Ok(None) => return,
+        Err(error) => {
+            tracing::warn!(%error, "Failed to cache access token, as the keychain could not be used");
+            return;
+        },
+    };
+
+    let serialized = match serde_json::to_vec(token) {
+        Ok(serialized) => serialized,
+        Err(_) => panic!("Internal error, could not serialize JSON data for cached token"),
+    };
+
+    if let Err(error) = write(&path, &seal(&encryption_key, key, &serialized)) {
+        tracing::warn!(?path, %error, "Failed to cache access token");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_token_opens_with_same_key_and_label() {
+        let key = Aes256Gcm::generate_key(OsRng);
+        let sealed = seal(&key, "azure tenant", b"token");
+
+        assert_eq!(open(&key, "azure tenant", &sealed).as_deref(), Some(b"token".as_slice()));
+        assert_eq!(open(&key, "google tenant", &sealed), None);
+        assert_eq!(open(&Aes256Gcm::generate_key(OsRng), "azure tenant", &sealed), None);
+        assert_eq!(open(&key, "azure tenant", &sealed[..NONCE_LENGTH - 1]), None);
+    }
+}
@@ -0,0 +1,88 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// W3C trace context for one request, which is sent in a traceparent header so that the request can be
+// correlated with the spans of the services it reaches.
+#[derive(Clone, Debug)]
+pub struct TraceContext {
+    trace_id : String,
+    span_id : String,
+}
+
+fn unix_nanos(time : SystemTime) -> u128 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_nanos(),
+        Err(_) => 0,
+    }
+}
+
+fn attribute(key : &str, value : serde_json::Value) -> serde_json::Value {
+    serde_json::json!({ "key" : key, "value" : value })
+}
+
+impl TraceContext {
+    pub fn new() -> TraceContext {
+        TraceContext {
+            trace_id : hex::encode(rand::random::<[u8; 16]>()),
+            span_id : hex::encode(rand::random::<[u8; 8]>()),
+        }
+    }
+
+    // The trace is always sampled, as callsy was asked to send it.
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-01", self.trace_id, self.span_id)
+    }
+
+    // Sends a client span for the request to an OTLP/HTTP collector, using the JSON encoding. Failures are
+    // only reported, as the request itself has already been made.
+    pub async fn export(&self, client : &reqwest::Client, endpoint : &str, span : Span<'_>) {
+        let mut attributes = vec![
+            attribute("http.request.method", serde_json::json!({ "stringValue" : span.method })),
+            attribute("url.full", serde_json::json!({ "stringValue" : crate::masking::mask(span.url) })),
+        ];
+        if let Some(status_code) = span.status_code {
+            attributes.push(attribute("http.response.status_code", serde_json::json!({ "intValue" : status_code.to_string() })));
+        }
+        if let Some(error) = span.error {
+            attributes.push(attribute("error.type", serde_json::json!({ "stringValue" : error })));
+        }
+
+        // Status codes 1 and 2 are OK and ERROR.
+        let failed = span.error.is_some() || span.status_code.is_some_and(|status_code| status_code >= 400);
+        let payload = serde_json::json!({
+            "resourceSpans" : [{
+                "resource" : {
+                    "attributes" : [attribute("service.name", serde_json::json!({ "stringValue" : "callsy" }))],
+                },
+                "scopeSpans" : [{
+                    "scope" : { "name" : "callsy" },
+                    "spans" : [{
+                        "traceId" : self.trace_id,
+                        "spanId" : self.span_id,
+                        "name" : span.method,
+                        "kind" : 3,
+                        "startTimeUnixNano" : unix_nanos(span.start).to_string(),
+                        "endTimeUnixNano" : unix_nanos(span.end).to_string(),
+                        "attributes" : attributes,
+                        "status" : { "code" : if failed { 2 } else { 1 } },
+                    }],
+                }],
+            }],
+        });
+
+        let url = format!("{}/v1/traces", endpoint.trim_end_matches('/'));
+        match client.post(&url).json(&payload).send().await {
+            Ok(response) if !response.status().is_success() => tracing::warn!(%url, status = %response.status(), "The OTLP collector rejected the span"),
+            Ok(_) => tracing::debug!(%url, trace_id = %self.trace_id, "Exported span"),
+            Err(error) => tracing::warn!(%url, %error, "Failed to export the span"),
+        }
+    }
+}
+
+pub struct Span<'a> {
+    pub method : &'a str,
+    pub url : &'a str,
+    pub start : SystemTime,
+    pub end : SystemTime,
+    pub status_code : Option<u16>,
+    pub error : Option<&'static str>,
+}
@@ -0,0 +1,330 @@
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::crossterm::{execute, terminal};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::error::CallsyError;
+use crate::processing::{RawRequest, Session};
+use crate::variables::{Variable, Variables};
+
+enum Mode {
+    Browsing,
+    // Setting a variable, with what has been typed so far in the form name=value.
+    Editing(String),
+}
+
+struct App {
+    requests : Vec<RawRequest>,
+    selected : ListState,
+    variables : Variables,
+    // The latest response to each request, or the error it failed with.
+    responses : Vec<Option<Result<serde_json::Value, String>>>,
+    scroll : u16,
+    mode : Mode,
+}
+
+impl App {
+    fn selected(&self) -> usize {
+        self.selected.selected().unwrap_or_default()
+    }
+
+    fn select(&mut self, offset : isize) {
+        if self.requests.is_empty() {
+            return;
+        }
+
+        let last = self.requests.len() as isize - 1;
+        let index = (self.selected() as isize + offset).clamp(0, last);
+        self.selected.select(Some(index as usize));
+        self.scroll = 0;
+    }
+
+    fn set_variable(&mut self, assignment : &str) {
+        if let Some((name, value)) = assignment.split_once('=') {
+            let name = name.trim();
+            if !name.is_empty() {
+                self.variables.insert(String::from(name), Variable::Value(String::from(value)));
+            }
+        }
+    }
+}
+
+fn io_error(source : std::io::Error) -> CallsyError {
+    CallsyError::Io { message : format!("Failed to update the terminal. {}", source), source }
+}
+
+// The terminal is handed back while a request is sent, so that prompts for variables can be answered.
+fn suspend() -> Result<(), CallsyError> {
+    terminal::disable_raw_mode().map_err(io_error)?;
+    execute!(std::io::stdout(), terminal::LeaveAlternateScreen).map_err(io_error)
+}
+
+fn resume(terminal : &mut DefaultTerminal) -> Result<(), CallsyError> {
+    terminal::enable_raw_mode().map_err(io_error)?;
+    execute!(std::io::stdout(), terminal::EnterAlternateScreen).map_err(io_error)?;
+    terminal.clear().map_err(io_error)
+}
+
+fn request_name(raw_request : &RawRequest, index : usize) -> String {
+    match &raw_request.name {
+        Some(name) => name.clone(),
+        None => format!("request {}", index + 1),
+    }
+}
+
+fn describe_variable(variable : &Variable) -> String {
+    match variable {
+        Variable::Value(value) => crate::masking::mask(value),
+        Variable::FromCommand(_) => String::from("(from command)"),
+        Variable::Prompt(_) => String::from("(prompt)"),
+    }
+}
+
+fn status_style(status_code : u64) -> Style {
+    match status_code {
+        200..=299 => Style::default().fg(Color::Green),
+        300..=399 => Style::default().fg(Color::Yellow),
+        _ => Style::default().fg(Color::Red),
+    }
+}
+
+// Colours one line of pretty printed JSON, telling keys apart from string values by the colon which follows them.
+fn highlight_json(line : &str) -> Line<'static> {
+    let mut spans = Vec::new();
+    let mut characters = line.char_indices().peekable();
+
+    while let Some((start, character)) = characters.next() {
+        match character {
+            '"' => {
+                let mut end = line.len();
+                let mut escaped = false;
+                for (index, character) in characters.by_ref() {
+                    if character == '"' && !escaped {
+                        end = index + 1;
+                        break;
+                    }
+                    escaped = character == '\\' && !escaped;
+                }
+                let is_key = line[end..].trim_start().starts_with(':');
+                let colour = if is_key { Color::Cyan } else { Color::Green };
+                spans.push(Span::styled(String::from(&line[start..end]), Style::default().fg(colour)));
+            },
+            '-' | '0'..='9' | 't' | 'f' | 'n' => {
+                let mut end = start + character.len_utf8();
+                while let Some((index, character)) = characters.peek() {
+                    if character.is_alphanumeric() || matches!(character, '.' | '-' | '+') {
+                        end = index + character.len_utf8();
+                        characters.next();
+                    }
+                    else {
+                        break;
+                    }
+                }
+                spans.push(Span::styled(String::from(&line[start..end]), Style::default().fg(Color::Yellow)));
+            },
+            character => spans.push(Span::raw(String::from(character))),
+        }
+    }
+
+    Line::from(spans)
+}
+
+// Colours the tags of one line of indented XML or HTML.
+fn highlight_markup(line : &str) -> Line<'static> {
+    let mut spans = Vec::new();
+    let mut rest = line;
+
+    while let Some(start) = rest.find('<') {
+        if start > 0 {
+            spans.push(Span::raw(String::from(&rest[..start])));
+        }
+        let end = rest[start..].find('>').map(|end| start + end + 1).unwrap_or(rest.len());
+        spans.push(Span::styled(String::from(&rest[start..end]), Style::default().fg(Color::Cyan)));
+        rest = &rest[end..];
+    }
+    if !rest.is_empty() {
+        spans.push(Span::raw(String::from(rest)));
+    }
+
+    Line::from(spans)
+}
+
+fn response_lines(response : &Option<Result<serde_json::Value, String>>) -> Vec<Line<'static>> {
+    let response = match response {
+        Some(Ok(response)) => response,
+        Some(Err(error)) => return vec![Line::styled(crate::masking::mask(error), Style::default().fg(Color::Red))],
+        None => return vec![Line::raw("Press enter to send this request.")],
+    };
+
+    let status_code = response["status_code"].as_u64().unwrap_or_default();
+    let mut lines = vec![
+        Line::styled(
+            format!("{} {} {}", response["http_version"].as_str().unwrap_or_default(), status_code, response["reason"].as_str().unwrap_or_default()),
+            status_style(status_code).add_modifier(Modifier::BOLD),
+        ),
+    ];
+
+    if let Some(headers) = response["headers"].as_object() {
+        for (name, values) in headers {
+            for value in values.as_array().into_iter().flatten() {
+                lines.push(Line::from(vec![
+                    Span::styled(format!("{}: ", name), Style::default().fg(Color::Cyan)),
+                    Span::raw(crate::masking::mask(value.as_str().unwrap_or_default())),
+                ]));
+            }
+        }
+    }
+
+    lines.push(Line::raw(""));
+
+    let content_type = response["headers"]
+        .as_object()
+        .and_then(|headers| headers.iter().find(|(name, _)| name.eq_ignore_ascii_case("content-type")))
+        .and_then(|(_, values)| values[0].as_str())
+        .unwrap_or_default();
+    let is_markup = matches!(crate::naming::extension_for_content_type(content_type), Some("xml" | "html" | "svg"));
+
+    let body = crate::masking::mask(response["body"].as_str().unwrap_or_default());
+    match serde_json::from_str::<serde_json::Value>(&body).ok().and_then(|json| serde_json::to_string_pretty(&json).ok()) {
+        Some(pretty) => lines.extend(pretty.lines().map(highlight_json)),
+        None if is_markup => lines.extend(crate::markup::indent(&body).lines().map(highlight_markup)),
+        None => lines.extend(body.lines().map(|line| Line::raw(String::from(line)))),
+    }
+
+    lines
+}
+
+fn draw(frame : &mut Frame, app : &mut App) {
+    let [main, footer] = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .areas(frame.area());
+
+    let [sidebar, response] = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
+        .areas(main);
+
+    let [requests, variables] = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+        .areas(sidebar);
+
+    let items = app.requests
+        .iter()
+        .enumerate()
+        .map(|(index, raw_request)| ListItem::new(request_name(raw_request, index)))
+        .collect::<Vec<_>>();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Requests"))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, requests, &mut app.selected);
+
+    let mut names = app.variables.keys().collect::<Vec<_>>();
+    names.sort();
+    let items = names
+        .into_iter()
+        .map(|name| ListItem::new(format!("{} = {}", name, describe_variable(&app.variables[name]))))
+        .collect::<Vec<_>>();
+    frame.render_widget(List::new(items).block(Block::default().borders(Borders::ALL).title("Variables")), variables);
+
+    let lines = response_lines(&app.responses[app.selected()]);
+    let paragraph = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title("Response"))
+        .scroll((app.scroll, 0));
+    frame.render_widget(paragraph, response);
+
+    let footer_text = match &app.mode {
+        Mode::Browsing => String::from("enter: send  up/down: select  pgup/pgdn: scroll  v: set variable  q: quit"),
+        Mode::Editing(assignment) => format!("Set variable (name=value): {}", assignment),
+    };
+    frame.render_widget(Paragraph::new(footer_text), footer);
+}
+
+async fn send(app : &mut App, session : &mut Session<'_>, terminal : &mut DefaultTerminal) -> Result<(), CallsyError> {
+    let index = app.selected();
+    let raw_request = app.requests[index].clone();
+
+    suspend()?;
+    let result = crate::processing::send_request(raw_request, session, &mut app.variables).await;
+    resume(terminal)?;
+
+    app.responses[index] = match result {
+        Ok((output_response, captured)) => {
+            app.variables.extend(captured);
+            match serde_json::to_value(&output_response) {
+                Ok(value) => Some(Ok(value)),
+                Err(_) => panic!("Internal error, could not serialize JSON data for response"),
+            }
+        },
+        Err(error) => Some(Err(error.to_string())),
+    };
+    app.scroll = 0;
+
+    Ok(())
+}
+
+async fn run_app(terminal : &mut DefaultTerminal, app : &mut App, session : &mut Session<'_>) -> Result<(), CallsyError> {
+    loop {
+        terminal.draw(|frame| draw(frame, app)).map_err(io_error)?;
+
+        let key = match event::read().map_err(io_error)? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+
+        match &mut app.mode {
+            Mode::Browsing => {
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Up | KeyCode::Char('k') => app.select(-1),
+                    KeyCode::Down | KeyCode::Char('j') => app.select(1),
+                    KeyCode::PageUp => app.scroll = app.scroll.saturating_sub(10),
+                    KeyCode::PageDown => app.scroll = app.scroll.saturating_add(10),
+                    KeyCode::Char('v') => app.mode = Mode::Editing(String::new()),
+                    KeyCode::Enter => send(app, session, terminal).await?,
+                    _ => {},
+                }
+            },
+            Mode::Editing(assignment) => {
+                match key.code {
+                    KeyCode::Esc => app.mode = Mode::Browsing,
+                    KeyCode::Backspace => { assignment.pop(); },
+                    KeyCode::Char(character) => assignment.push(character),
+                    KeyCode::Enter => {
+                        let assignment = std::mem::take(assignment);
+                        app.set_variable(&assignment);
+                        app.mode = Mode::Browsing;
+                    },
+                    _ => {},
+                }
+            },
+        }
+    }
+}
+
+// Browses a collection in the terminal, sending requests one at a time. Variables captured from a
+// response are available to the requests sent after it, as when running the collection.
+pub async fn run(requests : Vec<RawRequest>, variables : Variables, session : &mut Session<'_>) -> Result<(), CallsyError> {
+    if requests.is_empty() {
+        return Err(CallsyError::Usage(String::from("The collection has no requests to show.")));
+    }
+
+    let mut app = App {
+        responses : vec![None; requests.len()],
+        requests,
+        selected : ListState::default().with_selected(Some(0)),
+        variables,
+        scroll : 0,
+        mode : Mode::Browsing,
+    };
+
+    let mut terminal = ratatui::init();
+    let result = run_app(&mut terminal, &mut app, session).await;
+    ratatui::restore();
+
+    result
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+
+pub type Variables = HashMap<String, Variable>;
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum Variable {
+    Value(String),
+    FromCommand(FromCommand),
+    Prompt(Prompt),
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FromCommand {
+    from_command : String,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Prompt {
+    prompt : String,
+    #[serde(default)]
+    secret : bool,
+}
+
+impl Prompt {
+    fn ask(&self) -> Result<String, String> {
+        let message = format!("{}: ", self.prompt);
+
+        if self.secret {
+            return match rpassword::prompt_password(message) {
+                Ok(value) => Ok(value),
+                Err(error) => Err(format!("Failed to read value for prompt. {}", error)),
+            };
+        }
+
+        print!("{}", message);
+        std::io::stdout().flush().expect("Stdin flush failed.");
+
+        let mut buffer = String::new();
+        match std::io::stdin().read_line(&mut buffer) {
+            Ok(0) => Err(format!("No value was entered for the prompt {}.", self.prompt)),
+            Ok(_) => Ok(String::from(buffer.trim_end_matches(['\r', '\n']))),
+            Err(error) => Err(format!("Failed to read value for prompt. {}", error)),
+        }
+    }
+}
+
+pub fn load_environment(path : &std::path::PathBuf) -> Result<Variables, String> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) => return Err(format!("Failed to open environment file {:?}. {}", path, error)),
+    };
+
+    let mut content = String::new();
+    if let Err(error) = file.read_to_string(&mut content) {
+        return Err(format!("Failed to read environment file {:?}. {}", path, error));
+    }
+
+    match serde_json::from_str(&content) {
+        Ok(variables) => Ok(variables),
+        Err(error) => Err(format!("Unable to deserialise environment file {:?} at line {}, column {}. Values must be strings, or objects with a from_command or prompt field.", path, error.line(), error.column())),
+    }
+}
+
+// Parses an expression of the form function("argument"), returning the argument.
+fn parse_call<'a>(expression : &'a str, function : &str) -> Option<&'a str> {
+    expression
+    .strip_prefix(function)?
+    .trim_start()
+    .strip_prefix('(')?
+    .strip_suffix(')')?
+    .trim()
+    .strip_prefix('"')?
+    .strip_suffix('"')
+}
+
+fn resolve(expression : &str, variables : &mut Variables, config : &crate::config::Config) -> Result<String, String> {
+    if let Some(name) = parse_call(expression, "secret") {
+        let secret = crate::secrets::get_secret(name)?;
+        crate::masking::register(&secret);
+        return Ok(secret);
+    }
+
+    if let Some(reference) = expression.strip_prefix("vault:") {
+        if config.offline {
+            return Err(format!("Cannot read {} from Vault while offline.", reference.trim()));
+        }
+        let secret = crate::vault::get_secret(&config.vault, reference.trim())?;
+        crate::masking::register(&secret);
+        return Ok(secret);
+    }
+
+    match variables.get(expression) {
+        Some(Variable::Value(value)) => Ok(value.clone()),
+        Some(Variable::FromCommand(FromCommand { from_command })) => {
+            let output = crate::command::run_command(from_command)?;
+            let value = String::from(output.trim_end_matches(['\r', '\n']));
+            crate::masking::register(&value);
+            Ok(value)
+        },
+        Some(Variable::Prompt(prompt)) => {
+            // The answer replaces the prompt, so that each variable is only asked for once per run.
+            let value = prompt.ask()?;
+            if prompt.secret {
+                crate::masking::register(&value);
+            }
+            variables.insert(String::from(expression), Variable::Value(value.clone()));
+            Ok(value)
+        },
+        None => Err(format!("The variable {} is not defined.", expression)),
+    }
+}
+
+// Replaces each {{name}} in the text with the value of that variable.
+pub fn substitute(text : &str, variables : &mut Variables, config : &crate::config::Config) -> Result<String, String> {
+    let mut result = String::with_capacity(text.len());
+    let mut remaining = text;
+
+    while let Some(start) = remaining.find("{{") {
+        let end = match remaining[start..].find("}}") {
+            Some(end) => start + end,
+            None => return Err(format!("Unterminated variable in {}", text)),
+        };
+
+        let expression = remaining[start + 2..end].trim();
+        let value = resolve(expression, variables, config)?;
+
+        result.push_str(&remaining[..start]);
+        result.push_str(&value);
+
+        remaining = &remaining[end + 2..];
+    }
+
+    result.push_str(remaining);
+
+    Ok(result)
+}
+
+// Resolves every variable, for uses such as templates which can't ask for them one at a time.
+pub fn resolve_all(variables : &mut Variables, config : &crate::config::Config) -> Result<HashMap<String, String>, String> {
+    let names : Vec<String> = variables.keys().cloned().collect();
+    let mut values = HashMap::new();
+    for name in names {
+        let value = resolve(&name, variables, config)?;
+        values.insert(name, value);
+    }
+    Ok(values)
+}
+
+// Writes the variables into the environment file, keeping the variables which are already there.
+pub fn save_to_environment(path : &std::path::PathBuf, variables : &Variables, sync : bool) -> Result<(), String> {
+    let mut content = String::new();
+
+    match File::open(path) {
+        Ok(mut file) => {
+            if let Err(error) = file.read_to_string(&mut content) {
+                return Err(format!("Failed to read environment file {:?}. {}", path, error));
+            }
+        },
+        Err(error) => return Err(format!("Failed to open environment file {:?}. {}", path, error)),
+    }
+
+    let mut environment : serde_json::Map<String, serde_json::Value> = match serde_json::from_str(&content) {
+        Ok(environment) => environment,
+        Err(error) => return Err(format!("Unable to deserialise environment file {:?} at line {}, column {}.", path, error.line(), error.column())),
+    };
+
+    for (name, variable) in variables {
+        let value = match serde_json::to_value(variable) {
+            Ok(value) => value,
+            Err(_) => panic!("Internal error, could not serialize JSON data for environment"),
+        };
+        environment.insert(name.clone(), value);
+    }
+
+    let serialized = match serde_json::to_string_pretty(&environment) {
+        Ok(serialized) => serialized,
+        Err(_) => panic!("Internal error, could not serialize JSON data for environment"),
+    };
+
+    // The file is replaced atomically, so that a failed write leaves the environment as it was.
+    crate::processing::write_atomically(path, serialized.as_bytes(), sync).map_err(|error| error.to_string())
+}
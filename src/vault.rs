@@ -0,0 +1,119 @@
+use std::path::PathBuf;
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Vault {
+    pub address : Option<String>,
+    pub namespace : Option<String>,
+    pub auth : Option<Auth>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Auth {
+    Token {
+        token : Option<String>,
+        token_file : Option<PathBuf>,
+    },
+    Approle {
+        role_id : String,
+        secret_id : String,
+    },
+}
+
+fn address(vault : &Vault) -> Result<String, String> {
+    match (&vault.address, std::env::var("VAULT_ADDR")) {
+        (Some(address), _) => Ok(address.clone()),
+        (None, Ok(address)) => Ok(address),
+        (None, Err(_)) => Err(String::from("No Vault address is configured. Set address in the vault section of the config file or the VAULT_ADDR environment variable.")),
+    }
+}
+
+fn with_namespace(builder : reqwest::RequestBuilder, vault : &Vault) -> reqwest::RequestBuilder {
+    match vault.namespace.as_ref().cloned().or_else(|| std::env::var("VAULT_NAMESPACE").ok()) {
+        Some(namespace) => builder.header("x-vault-namespace", namespace),
+        None => builder,
+    }
+}
+
+fn read_token_file(path : &PathBuf) -> Result<String, String> {
+    match std::fs::read_to_string(path) {
+        Ok(token) => Ok(String::from(token.trim())),
+        Err(error) => Err(format!("Failed to read Vault token file {:?}. {}", path, error)),
+    }
+}
+
+async fn login(client : &reqwest::Client, address : &str, vault : &Vault) -> Result<String, String> {
+    match &vault.auth {
+        Some(Auth::Token { token : Some(token), .. }) => Ok(token.clone()),
+        Some(Auth::Token { token : None, token_file : Some(path) }) => read_token_file(path),
+        Some(Auth::Approle { role_id, secret_id }) => {
+            let body = serde_json::json!({ "role_id" : role_id, "secret_id" : secret_id });
+            let request = with_namespace(client.post(format!("{}/v1/auth/approle/login", address)), vault).json(&body);
+            let response = send(request).await?;
+
+            match response.pointer("/auth/client_token") {
+                Some(serde_json::Value::String(token)) => Ok(token.clone()),
+                _ => Err(String::from("Vault AppRole login response did not include a client token.")),
+            }
+        },
+        Some(Auth::Token { token : None, token_file : None }) | None => {
+            if let Ok(token) = std::env::var("VAULT_TOKEN") {
+                return Ok(token);
+            }
+
+            match dirs::home_dir() {
+                Some(home) if home.join(".vault-token").exists() => read_token_file(&home.join(".vault-token")),
+                _ => Err(String::from("No Vault token is available. Configure vault authentication in the config file or set the VAULT_TOKEN environment variable.")),
+            }
+        },
+    }
+}
+
+async fn send(request : reqwest::RequestBuilder) -> Result<serde_json::Value, String> {
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(error) => return Err(format!("Error when sending the request to Vault, {}", error)),
+    };
+
+    let status = response.status();
+
+    match response.json::<serde_json::Value>().await {
+        Ok(body) if status.is_success() => Ok(body),
+        Ok(body) => Err(format!("Vault responded with status {}. {}", status, body.get("errors").unwrap_or(&body))),
+        Err(error) => Err(format!("Failed to read the response from Vault, {}", error)),
+    }
+}
+
+async fn fetch_secret(vault : &Vault, path : &str, key : &str) -> Result<String, String> {
+    let address = address(vault)?;
+    let address = address.trim_end_matches('/');
+    let client = reqwest::Client::new();
+    let token = login(&client, address, vault).await?;
+
+    let request = with_namespace(client.get(format!("{}/v1/{}", address, path.trim_start_matches('/'))), vault).header("x-vault-token", token);
+    let response = send(request).await?;
+
+    // Version 2 of the key value engine nests the secret inside a second data object.
+    let value = response
+        .pointer(&format!("/data/data/{}", key))
+        .or_else(|| response.pointer(&format!("/data/{}", key)));
+
+    match value {
+        Some(serde_json::Value::String(value)) => Ok(value.clone()),
+        Some(value) => Ok(value.to_string()),
+        None => Err(format!("The Vault secret at {} does not have a value for {}.", path, key)),
+    }
+}
+
+// Resolves a reference of the form path#key, such as secret/data/api#token.
+pub fn get_secret(vault : &Vault, reference : &str) -> Result<String, String> {
+    let (path, key) = match reference.rsplit_once('#') {
+        Some((path, key)) => (path, key),
+        None => return Err(format!("The Vault reference {} must be of the form path#key.", reference)),
+    };
+
+    tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(fetch_secret(vault, path, key))
+    })
+}